@@ -0,0 +1,47 @@
+//! hashing throughput depending on the size of the reads
+//!
+//! `Hash::hash` feeds the digest straight from the buffer of the reader:
+//! the size of the reads is the capacity of the `BufReader`. It matters
+//! when every read is a system call (reading a file).
+//!
+//! Run with `cargo bench --bench hash`.
+
+extern crate git;
+
+use std::fs::{self, File};
+use std::io::{Write, BufReader};
+use std::time::Instant;
+
+use git::protocol::{Hash, SHA1};
+
+/// size of the hashed input: 1 MiB
+const INPUT_SIZE : usize = 1024 * 1024;
+const ITERATIONS : u32 = 50;
+
+fn bench<F: Fn() -> SHA1>(name: &str, hash: F) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        hash();
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    let throughput = INPUT_SIZE as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{:<28} {:>10.3?} per MiB ({:.0} MiB/s)", name, elapsed, throughput);
+}
+
+fn main() {
+    let data : Vec<u8> = (0..INPUT_SIZE).map(|n| (n % 251) as u8).collect();
+    let path = std::env::temp_dir().join("git-rs-bench-hash");
+    File::create(&path).and_then(|mut f| f.write_all(&data)).expect("writing the input file");
+
+    let from_file = |capacity: usize| {
+        let file = File::open(&path).expect("opening the input file");
+        SHA1::hash(&mut BufReader::with_capacity(capacity, file)).unwrap()
+    };
+    bench("file, 128 bytes reads", || from_file(128));
+    bench("file, 64 KiB reads", || from_file(64 * 1024));
+    bench("memory, 128 bytes reads", || SHA1::hash(&mut BufReader::with_capacity(128, data.as_slice())).unwrap());
+    bench("memory, 64 KiB reads", || SHA1::hash(&mut BufReader::with_capacity(64 * 1024, data.as_slice())).unwrap());
+    bench("memory, slice", || SHA1::hash(&mut data.as_slice()).unwrap());
+
+    let _ = fs::remove_file(&path);
+}
@@ -2,8 +2,8 @@ extern crate git;
 extern crate clap;
 use clap::{Arg, App, SubCommand};
 use std::path::Path;
-use std::str::FromStr;
-use git::refs::SpecRef;
+use std::io::{self, Write, BufRead};
+use std::process;
 use git::object::*;
 use git::protocol::{SHA1, Hash, Repo};
 
@@ -16,10 +16,22 @@ fn main() {
                 .arg(
                     Arg::with_name("pretty").short("p").help("pretty print the content")
                 )
+                .arg(
+                    Arg::with_name("type").short("t").conflicts_with_all(&["pretty", "size"])
+                        .help("show the object type")
+                )
+                .arg(
+                    Arg::with_name("size").short("s").conflicts_with("pretty")
+                        .help("show the object size")
+                )
+                .arg(
+                    Arg::with_name("batch").long("batch").conflicts_with_all(&["pretty", "type", "size", "REF"])
+                        .help("print the type, size and content of the objects named on the standard input")
+                )
                 .arg(
                     Arg::with_name("REF")
                         .help("the hash to print")
-                        .required(true)
+                        .required_unless("batch")
                         .index(1)
                 )
             ).subcommand(SubCommand::with_name("branch")
@@ -44,19 +56,84 @@ fn main() {
     }
 }
 
+/// resolve the given hash, reference or abbreviated hash, exiting (as git
+/// does, with the status 128) if it cannot be resolved
+fn resolve(git: &git::fs::GitFS, r: &str) -> SHA1 {
+    match try_resolve(git, r) {
+        Ok(hash) => hash,
+        Err(err) => {
+            let _ = writeln!(io::stderr(), "fatal: {}", err);
+            process::exit(128)
+        }
+    }
+}
+fn try_resolve(git: &git::fs::GitFS, r: &str) -> git::Result<SHA1> {
+    // `master@{u}`... needs the configuration of the branches
+    if r.contains("@{") {
+        return git.resolve_specref(r).and_then(|specref| git.get_ref_follow_links(specref))
+    }
+    git.resolve(r)
+}
+
+/// what `cat-file` prints about the object
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CatFileMode { Pretty, Type, Size }
+
 fn cat_file(matches: &clap::ArgMatches) {
+    if matches.is_present("batch") {
+        let git = git::fs::GitFS::new(Path::new(".git")).expect("valid git repository");
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        cat_file_batch(&git, stdin.lock(), &mut stdout.lock()).unwrap();
+        return
+    }
     let r = matches.value_of("REF").expect("reference to git Object");
+    let mode = if matches.is_present("type") { CatFileMode::Type }
+               else if matches.is_present("size") { CatFileMode::Size }
+               else { CatFileMode::Pretty };
 
     let git = git::fs::GitFS::new(Path::new(".git")).expect("valid git repository");
-    let hash = match SHA1::from_hex(r.clone()) {
-        Some(e) => e,
-        None    => git.get_ref_follow_links(
-            SpecRef::from_str(r.clone()).unwrap()
-        ).unwrap()
-    };
+    let stdout = io::stdout();
+    cat_file_to(&git, r, mode, &mut stdout.lock()).unwrap();
+}
 
+fn cat_file_to<W: Write>(git: &git::fs::GitFS, r: &str, mode: CatFileMode, out: &mut W)
+    -> io::Result<()>
+{
+    let hash = resolve(git, r);
+    match mode {
+        CatFileMode::Pretty => match git.get_object_(hash).unwrap() {
+            // the content of the blobs may be binary
+            Obj::Blob(blob) => blob.write_raw(out).map(|_| ()),
+            obj => write!(out, "{}", obj)
+        },
+        CatFileMode::Type => writeln!(out, "{}", git.object_header(&hash).unwrap().0),
+        CatFileMode::Size => writeln!(out, "{}", git.object_header(&hash).unwrap().1)
+    }
+}
 
-    print!("{}", git.get_object_(hash).unwrap());
+/// `cat-file --batch`: for every object name read from `input` (one per
+/// line) print `<hash> <type> <size>`, the content of the object and a new
+/// line; or `<name> missing` if the object cannot be found.
+fn cat_file_batch<R: BufRead, W: Write>(git: &git::fs::GitFS, input: R, out: &mut W)
+    -> io::Result<()>
+{
+    for line in input.lines() {
+        let line = try!(line);
+        let name = line.trim();
+        let object = try_resolve(git, name).and_then(|hash| {
+            git.read_raw_object(&hash).map(|(kind, content)| (hash, kind, content))
+        });
+        match object {
+            Ok((hash, kind, content)) => {
+                try!(writeln!(out, "{} {} {}", hash.to_hexadecimal(), kind, content.len()));
+                try!(out.write_all(&content));
+                try!(writeln!(out));
+            },
+            Err(_) => try!(writeln!(out, "{} missing", name))
+        }
+    }
+    out.flush()
 }
 
 fn branch(matches: &clap::ArgMatches) {
@@ -79,16 +156,42 @@ fn log(matches: &clap::ArgMatches) {
     let r = matches.value_of("REF").unwrap_or("HEAD");
 
     let git = git::fs::GitFS::new(Path::new(".git")).expect("valid git repository");
-    let hash = match SHA1::from_hex(r.clone()) {
-        Some(e) => e,
-        None    => git.get_ref_follow_links(
-            SpecRef::from_str(r.clone()).unwrap()
-        ).unwrap()
-    };
-    let mut cmhash = CommitRef::new(hash);
+    let hash = resolve(&git, r);
+    for commit in git.walk_commits(CommitRef::new(hash)) {
+        print!("{}", commit.expect("valid commit"));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cat_file_test(r: &str, mode: CatFileMode) -> String {
+        let git = git::fs::GitFS::new(Path::new("test_ref/.git")).unwrap();
+        let mut out = Vec::new();
+        cat_file_to(&git, r, mode, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn cat_file_type_and_size() {
+        // see test_ref/init.sh
+        assert_eq!(cat_file_test("HEAD", CatFileMode::Type), "commit\n");
+        assert_eq!(cat_file_test("refs/tags/v0.1", CatFileMode::Type), "tag\n");
+        let pretty = cat_file_test("HEAD", CatFileMode::Pretty);
+        assert_eq!(cat_file_test("HEAD", CatFileMode::Size), format!("{}\n", pretty.len()));
+    }
 
-    while let Ok(commit) = git.get_commit(cmhash) {
-        print!("{}", commit);
-        cmhash = commit.parents.first().unwrap().clone();
+    #[test]
+    fn cat_file_batch_mixed() {
+        let git = git::fs::GitFS::new(Path::new("test_ref/.git")).unwrap();
+        let input = "e845566c06f9bf557d35e8292c37cf05d97a9769\ne845566\nnothing-here\n";
+        let mut out = Vec::new();
+        cat_file_batch(&git, input.as_bytes(), &mut out).unwrap();
+        // see test_ref/init.sh
+        let readme = "e845566c06f9bf557d35e8292c37cf05d97a9769 blob 7\nREADME\n\n";
+        assert_eq!( String::from_utf8(out).unwrap()
+                  , format!("{}{}nothing-here missing\n", readme, readme)
+                  );
     }
 }
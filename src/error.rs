@@ -1,20 +1,10 @@
 use std::path::PathBuf;
 use std::fmt::Display;
-use std::{io, result, fmt};
+use std::{io, result, fmt, str, string};
 use std::error::Error;
+use nom;
 
-use refs::RefName;
-
-/// *try* the IO operation, wrap the IOError in a GitError if failed
-macro_rules! io_try {
-    ($expression:expr) => ({
-        use ::error::{GitError};
-        match $expression {
-            Ok(v) => v,
-            Err(err) => return Err(GitError::ioerror(err))
-        }
-    })
-}
+use refs::{RefName, SpecRef};
 
 /// *try* to run a nom parser, wrap the Nom's Error in a GitError if failed
 macro_rules! nom_try {
@@ -30,38 +20,114 @@ macro_rules! nom_try {
                 return Err(GitError::ParsingErrorNotEnough(Some(s)))
             },
             IResult::Error(err) => {
-                return Err(GitError::ParsingError(format!("{:?}", err).to_string()))
+                return Err(GitError::from_nom_error(err))
             }
         }
     })
 }
 
+/// code of the `nom::ErrorKind::Custom` error of a commit without `author`
+pub const NOM_MISSING_AUTHOR : u32 = 0x1001;
+/// code of the `nom::ErrorKind::Custom` error of a commit without `committer`
+pub const NOM_MISSING_COMMITTER : u32 = 0x1002;
+
 #[derive(PartialEq, Debug)]
 pub enum GitError {
     OutOfBound(usize, usize),
+    /// the expected and the given sizes of a hash, in bytes
     InvalidHashSize(usize, usize),
+    /// the expected and the given sizes of a hash in hexadecimal, in digits
+    InvalidHexSize(usize, usize),
     MissingDirectory(PathBuf),
     MissingFile(PathBuf),
     InvalidRef(RefName),
     InvalidBranch(RefName),
     InvalidTag(RefName),
     InvalidRemote(RefName),
+    RefUpdateRejected(SpecRef, Option<String>),
+    RefCycle(SpecRef),
+    HashMismatch(String, String),
+    MissingHeader(String),
+    NotHexadecimal(String),
+    AmbiguousRef(Vec<String>),
     ParsingErrorNotEnough(Option<usize>),
     ParsingError(String),
-    IoError(String),
+    TrailingBytes(usize),
+    IoError(io::ErrorKind, String),
     Other(String),
-    Unknown(String)
+    Unknown(String),
+    Context { while_doing: String, source: Box<GitError> }
 }
 impl GitError {
     #[inline(always)]
     pub fn ioerror(err: io::Error) -> Self {
-        GitError::IoError(format!("{:?}", err))
+        GitError::IoError(err.kind(), format!("{}", err))
+    }
+
+    /// turn the error of a nom parser into a `GitError`: the custom errors
+    /// of the parsers (`NOM_MISSING_AUTHOR`...) get their own variant,
+    /// the others are a `ParsingError`
+    pub fn from_nom_error(err: nom::ErrorKind) -> Self {
+        match err {
+            nom::ErrorKind::Custom(NOM_MISSING_AUTHOR) => GitError::MissingHeader("author".to_string()),
+            nom::ErrorKind::Custom(NOM_MISSING_COMMITTER) => GitError::MissingHeader("committer".to_string()),
+            err => GitError::ParsingError(format!("{:?}", err))
+        }
+    }
+
+    /// describe what was being done when the error happened, as the error
+    /// goes up:
+    ///
+    /// ```
+    /// use git::GitError;
+    ///
+    /// let err = GitError::ParsingError("Tag".to_string())
+    ///     .context("reading tree 4b825dc6 of commit 11f766d2");
+    /// assert_eq!( format!("{}", err)
+    ///           , "while reading tree 4b825dc6 of commit 11f766d2: ParsingError(\"Tag\")"
+    ///           );
+    /// ```
+    pub fn context<S: Into<String>>(self, while_doing: S) -> Self {
+        GitError::Context { while_doing: while_doing.into(), source: Box::new(self) }
+    }
+
+    /// the error without its contexts
+    pub fn root_cause(&self) -> &GitError {
+        match self {
+            &GitError::Context { ref source, .. } => source.root_cause(),
+            err => err
+        }
+    }
+
+    /// the kind of the underlying IO error (if this is an `IoError`)
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self.root_cause() {
+            &GitError::IoError(kind, _) => Some(kind),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for GitError {
+    fn from(err: io::Error) -> Self { GitError::ioerror(err) }
+}
+impl From<str::Utf8Error> for GitError {
+    fn from(err: str::Utf8Error) -> Self {
+        GitError::ParsingError(format!("invalid UTF-8: {}", err))
+    }
+}
+impl From<string::FromUtf8Error> for GitError {
+    fn from(err: string::FromUtf8Error) -> Self {
+        GitError::ParsingError(format!("invalid UTF-8: {}", err))
     }
 }
 
 impl Display for GitError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            &GitError::Context { ref while_doing, ref source } => write!(f, "while {}: {}", while_doing, source),
+            err => write!(f, "{:?}", err)
+        }
     }
 }
 
@@ -70,3 +136,62 @@ impl Error for GitError {
 }
 
 pub type Result<T> = result::Result<T, GitError>;
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{fs, str};
+
+    fn open_missing() -> Result<fs::File> {
+        Ok(try!(fs::File::open("./this/file/does/not/exist")))
+    }
+    fn decode(b: &[u8]) -> Result<&str> {
+        Ok(try!(str::from_utf8(b)))
+    }
+
+    #[test]
+    fn from_io_error() {
+        match open_missing() {
+            Err(GitError::IoError(io::ErrorKind::NotFound, _)) => {},
+            r => panic!("expected an IoError, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn io_kind() {
+        let err = GitError::ioerror(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        assert_eq!(err.io_kind(), Some(io::ErrorKind::PermissionDenied));
+        assert_eq!(open_missing().unwrap_err().io_kind(), Some(io::ErrorKind::NotFound));
+        assert_eq!(GitError::Other("not io".to_string()).io_kind(), None);
+        assert_eq!( err
+                  , GitError::IoError(io::ErrorKind::PermissionDenied, "denied".to_string())
+                  );
+    }
+
+    #[test]
+    fn context_chain() {
+        let err = open_missing().unwrap_err()
+            .context("reading tree 4b825dc6")
+            .context("reading commit 11f766d2");
+        assert_eq!(err.io_kind(), Some(io::ErrorKind::NotFound));
+        assert_eq!(err.root_cause(), &open_missing().unwrap_err());
+        assert!(format!("{}", err).starts_with("while reading commit 11f766d2: while reading tree 4b825dc6: IoError(NotFound"));
+    }
+
+    #[test]
+    fn from_utf8_error() {
+        assert_eq!(decode(b"hello"), Ok("hello"));
+        match decode(b"\xff\xfe") {
+            Err(GitError::ParsingError(_)) => {},
+            r => panic!("expected a ParsingError, got {:?}", r)
+        }
+        match String::from_utf8(vec![0xff]).map_err(GitError::from) {
+            Err(GitError::ParsingError(_)) => {},
+            r => panic!("expected a ParsingError, got {:?}", r)
+        }
+    }
+}
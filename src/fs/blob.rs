@@ -0,0 +1,83 @@
+//! streaming access to the blobs stored in the repository
+
+use std::io::{self, Read};
+
+use error::{Result, GitError};
+use object::ObjectKind;
+use super::util::read_loose_header;
+
+/// reader over the content of a `Blob`
+///
+/// Unlike `object::Blob`, the content is not loaded in memory: it is
+/// inflated as it is read. The `blob <size>\0` header is consumed when the
+/// reader is created so only the payload is returned by `read`.
+///
+/// See `GitFS::open_blob`.
+pub struct BlobReader {
+    inner: Box<Read>,
+    size: usize,
+    remaining: usize
+}
+impl BlobReader {
+    /// create a reader from the raw (inflated) object data: the
+    /// `blob <size>\0` header is parsed and skipped.
+    pub fn new(mut inner: Box<Read>) -> Result<Self> {
+        match try!(read_loose_header(&mut inner)) {
+            (ObjectKind::Blob, size) => Ok(Self::without_header(inner, size)),
+            _ => Err(GitError::ParsingError("expected a blob object".to_string()))
+        }
+    }
+
+    /// create a reader from the content of the blob (without the header)
+    pub fn without_header(inner: Box<Read>, size: usize) -> Self {
+        BlobReader { inner: inner, size: size, remaining: size }
+    }
+
+    /// the size of the blob's content (as given in the object's header)
+    pub fn size(&self) -> usize { self.size }
+}
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0)
+        }
+        let max = ::std::cmp::min(buf.len(), self.remaining);
+        let read = try!(self.inner.read(&mut buf[..max]));
+        if read == 0 && max > 0 {
+            return Err(io::Error::new( io::ErrorKind::UnexpectedEof
+                                     , format!("blob truncated: {} bytes missing", self.remaining)
+                                     ))
+        }
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn blob_reader_skip_header() {
+        let data = b"blob 5\0hello".to_vec();
+        let mut reader = BlobReader::new(Box::new(Cursor::new(data))).unwrap();
+        assert_eq!(reader.size(), 5);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"hello".to_vec());
+    }
+
+    #[test]
+    fn blob_reader_invalid() {
+        let tree = b"tree 0\0".to_vec();
+        assert!(BlobReader::new(Box::new(Cursor::new(tree))).is_err());
+        let truncated = b"blob 10\0hello".to_vec();
+        let mut reader = BlobReader::new(Box::new(Cursor::new(truncated))).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+}
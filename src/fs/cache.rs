@@ -0,0 +1,202 @@
+//! in memory cache of the objects read from the repository
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+use object::ObjectKind;
+
+/// a cached object, linked to the entries used just before and just after
+/// it
+struct Entry {
+    key: String,
+    kind: ObjectKind,
+    content: Vec<u8>,
+    /// the entry used less recently
+    older: Option<usize>,
+    /// the entry used more recently
+    newer: Option<usize>
+}
+
+/// the entries, in a slab: a hit is a lookup in `slots` and the moving of
+/// the entry at the end of the list, without going through the other
+/// entries
+struct Entries {
+    slots: HashMap<String, usize>,
+    entries: Vec<Entry>,
+    /// the indexes of the evicted entries, to be reused
+    free: Vec<usize>,
+    oldest: Option<usize>,
+    newest: Option<usize>,
+    /// the sum of the sizes of the cached contents
+    size: usize
+}
+impl Entries {
+    fn new() -> Self {
+        Entries {
+            slots: HashMap::new(),
+            entries: Vec::new(),
+            free: Vec::new(),
+            oldest: None,
+            newest: None,
+            size: 0
+        }
+    }
+
+    /// take the entry out of the list of the entries
+    fn unlink(&mut self, idx: usize) {
+        let (older, newer) = (self.entries[idx].older, self.entries[idx].newer);
+        match older {
+            Some(o) => self.entries[o].newer = newer,
+            None => self.oldest = newer
+        }
+        match newer {
+            Some(n) => self.entries[n].older = older,
+            None => self.newest = older
+        }
+        self.entries[idx].older = None;
+        self.entries[idx].newer = None;
+    }
+
+    /// put the (unlinked) entry at the end of the list: the most recently
+    /// used
+    fn push_newest(&mut self, idx: usize) {
+        self.entries[idx].older = self.newest;
+        match self.newest {
+            Some(n) => self.entries[n].newer = Some(idx),
+            None => self.oldest = Some(idx)
+        }
+        self.newest = Some(idx);
+    }
+
+    /// remove the least recently used entry, `false` if the cache is empty
+    fn evict_oldest(&mut self) -> bool {
+        let idx = match self.oldest { Some(idx) => idx, None => return false };
+        self.unlink(idx);
+        let key = mem::replace(&mut self.entries[idx].key, String::new());
+        let content = mem::replace(&mut self.entries[idx].content, Vec::new());
+        self.slots.remove(&key);
+        self.size -= content.len();
+        self.free.push(idx);
+        true
+    }
+}
+
+/// cache of the raw objects (kind and content), keyed by their hash in
+/// hexadecimal
+///
+/// The objects are immutable (their key is the hash of their content) so
+/// the entries are never invalidated: when adding an object would make the
+/// cached contents bigger than the capacity (in bytes), the least recently
+/// used entries are evicted. An object bigger than the whole capacity is
+/// not kept. The clones of a cache share its entries.
+///
+/// A cache with a capacity of 0 (the default) does not keep anything.
+///
+/// See `GitFS::with_cache`.
+#[derive(Clone)]
+pub struct ObjectCache {
+    capacity: usize,
+    entries: Arc<Mutex<Entries>>
+}
+impl ObjectCache {
+    pub fn new(capacity: usize) -> Self {
+        ObjectCache {
+            capacity: capacity,
+            entries: Arc::new(Mutex::new(Entries::new()))
+        }
+    }
+
+    /// the maximum size (in bytes) of the contents kept in the cache
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    /// get a copy of the cached object (and mark it as recently used)
+    pub fn get(&self, key: &str) -> Option<(ObjectKind, Vec<u8>)> {
+        if self.capacity == 0 { return None }
+        let mut entries = match self.entries.lock() { Ok(e) => e, Err(_) => return None };
+        let idx = match entries.slots.get(key) { Some(&idx) => idx, None => return None };
+        entries.unlink(idx);
+        entries.push_newest(idx);
+        let entry = &entries.entries[idx];
+        Some((entry.kind, entry.content.clone()))
+    }
+
+    /// add an object to the cache, evicting the least recently used ones
+    /// until it fits
+    pub fn insert(&self, key: String, kind: ObjectKind, content: Vec<u8>) {
+        if content.len() > self.capacity { return }
+        let mut entries = match self.entries.lock() { Ok(e) => e, Err(_) => return };
+        if entries.slots.contains_key(&key) { return }
+        while entries.size + content.len() > self.capacity {
+            if ! entries.evict_oldest() { break }
+        }
+        entries.size += content.len();
+        let entry = Entry { key: key.clone(), kind: kind, content: content, older: None, newer: None };
+        let idx = match entries.free.pop() {
+            Some(idx) => { entries.entries[idx] = entry; idx },
+            None => { entries.entries.push(entry); entries.entries.len() - 1 }
+        };
+        entries.slots.insert(key, idx);
+        entries.push_newest(idx);
+    }
+
+    /// the number of objects in the cache
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.slots.len()).unwrap_or(0)
+    }
+
+    /// the sum of the sizes (in bytes) of the objects in the cache
+    pub fn size(&self) -> usize {
+        self.entries.lock().map(|e| e.size).unwrap_or(0)
+    }
+}
+/// the cache is not part of the identity of a repository: all the caches
+/// are equal
+impl PartialEq for ObjectCache {
+    fn eq(&self, _: &Self) -> bool { true }
+}
+impl Eq for ObjectCache {}
+impl fmt::Debug for ObjectCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectCache {{ capacity: {}, len: {}, size: {} }}", self.capacity, self.len(), self.size())
+    }
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn blob(cache: &ObjectCache, key: &str) -> Option<Vec<u8>> {
+        cache.get(key).map(|(_, content)| content)
+    }
+
+    #[test]
+    fn bounded_by_size() {
+        let cache = ObjectCache::new(10);
+        cache.insert("a".to_string(), ObjectKind::Blob, vec![b'a'; 4]);
+        cache.insert("b".to_string(), ObjectKind::Blob, vec![b'b'; 4]);
+        assert_eq!((cache.len(), cache.size()), (2, 8));
+        // `a` is used again: `b` is the least recently used
+        assert_eq!(blob(&cache, "a"), Some(vec![b'a'; 4]));
+        cache.insert("c".to_string(), ObjectKind::Blob, vec![b'c'; 4]);
+        assert_eq!(blob(&cache, "b"), None);
+        assert_eq!(blob(&cache, "a"), Some(vec![b'a'; 4]));
+        assert_eq!((cache.len(), cache.size()), (2, 8));
+        // a big object evicts all the others, a too big one is not kept
+        cache.insert("d".to_string(), ObjectKind::Blob, vec![b'd'; 10]);
+        assert_eq!((cache.len(), cache.size()), (1, 10));
+        cache.insert("e".to_string(), ObjectKind::Blob, vec![b'e'; 11]);
+        assert_eq!(blob(&cache, "e"), None);
+        assert_eq!(blob(&cache, "d"), Some(vec![b'd'; 10]));
+        // the evicted slots are reused
+        cache.insert("f".to_string(), ObjectKind::Blob, vec![b'f'; 2]);
+        cache.insert("g".to_string(), ObjectKind::Blob, vec![b'g'; 2]);
+        assert_eq!((cache.len(), cache.size()), (2, 4));
+        assert_eq!(blob(&cache, "f"), Some(vec![b'f'; 2]));
+        assert_eq!(blob(&cache, "g"), Some(vec![b'g'; 2]));
+    }
+}
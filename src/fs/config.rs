@@ -0,0 +1,291 @@
+//! Git's configuration file (`config`)
+
+use std::str::FromStr;
+
+use error::{Result, GitError};
+
+/// Git configuration
+///
+/// The configuration is stored as the flat list of variables, in the order
+/// they appear in the file. The keys are the same as the one displayed
+/// by `git config --list`:
+///
+/// * `section.name`;
+/// * `section.subsection.name`.
+///
+/// The section and the variable name are case insensitive but the
+/// subsection is case sensitive.
+///
+/// # Example
+///
+/// ```
+/// use git::fs::Config;
+/// use std::str::FromStr;
+///
+/// let config = Config::from_str(
+///     "[branch \"master\"]\n\tremote = origin\n\tmerge = refs/heads/master\n"
+/// ).unwrap();
+/// assert_eq!(config.get("branch.master.remote"), Some("origin"));
+/// assert_eq!(config.get("Branch.master.Merge"), Some("refs/heads/master"));
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Config(Vec<(String, String)>);
+impl Config {
+    /// create an empty configuration
+    pub fn new() -> Self { Config(Vec::new()) }
+
+    /// get the value of the given variable
+    ///
+    /// If the variable is set multiple times, the last one wins (as git does).
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.0.iter().rev()
+            .find(|&&(ref k, _)| k == &key)
+            .map(|&(_, ref v)| v.as_str())
+    }
+
+    /// get all the values of a multi-valued variable (`remote.origin.fetch`...)
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.0.iter()
+            .filter(|&&(ref k, _)| k == &key)
+            .map(|&(_, ref v)| v.as_str())
+            .collect()
+    }
+
+    /// get the boolean value of the given variable
+    ///
+    /// Returns `None` if the variable is not set or is not a valid boolean.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key).map(|v| v.to_lowercase()) {
+            None => None,
+            Some(v) => match v.as_str() {
+                "true"  | "yes" | "on"  | "1" => Some(true),
+                "false" | "no"  | "off" | "0" | "" => Some(false),
+                _ => None
+            }
+        }
+    }
+
+    /// iterate over all the `(key, value)` of the configuration
+    pub fn iter(&self) -> ::std::slice::Iter<(String, String)> { self.0.iter() }
+}
+
+impl FromStr for Config {
+    type Err = GitError;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut config = Config::new();
+        let mut section : Option<String> = None;
+        let mut lines = s.lines().enumerate();
+        while let Some((n, line)) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = Some(try!(parse_section(line).ok_or(invalid_line(n, line))));
+                continue;
+            }
+            let section = match &section {
+                &Some(ref section) => section,
+                &None => return Err(invalid_line(n, line))
+            };
+            let (name, value) = match line.find('=') {
+                Some(idx) => (line[..idx].trim(), Some(&line[idx+1..])),
+                None => (line, None)
+            };
+            if ! is_valid_name(name) {
+                return Err(invalid_line(n, line))
+            }
+            let value = match value {
+                // a variable without value is a boolean set to true
+                None => "true".to_string(),
+                Some(value) => try!(parse_value(value, &mut lines.by_ref().map(|(_, l)| l))
+                                      .ok_or(invalid_line(n, line)))
+            };
+            config.0.push((format!("{}.{}", section, name.to_lowercase()), value));
+        }
+        Ok(config)
+    }
+}
+
+fn invalid_line(n: usize, line: &str) -> GitError {
+    GitError::ParsingError(format!("invalid config line {}: {}", n + 1, line))
+}
+
+/// lowercase the section and the name but not the subsection
+fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) if first != last => {
+            format!( "{}{}{}"
+                   , key[..first].to_lowercase()
+                   , &key[first..last]
+                   , key[last..].to_lowercase()
+                   )
+        },
+        _ => key.to_lowercase()
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.chars().next().map_or(false, |c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// parse `[section]`, `[section "subsection"]` or the deprecated
+/// `[section.subsection]` and return the key prefix.
+fn parse_section(line: &str) -> Option<String> {
+    let end = match line.rfind(']') { Some(end) => end, None => return None };
+    if ! line[end+1..].trim().is_empty()
+        && ! line[end+1..].trim().starts_with('#')
+        && ! line[end+1..].trim().starts_with(';') {
+        return None
+    }
+    let content = &line[1..end];
+    match content.find('"') {
+        None => {
+            // the deprecated `[section.subsection]` is case insensitive
+            let content = content.trim();
+            if content.is_empty()
+               || ! content.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+                return None
+            }
+            Some(content.to_lowercase())
+        },
+        Some(idx) => {
+            let name = content[..idx].trim();
+            if name.is_empty() || ! content.ends_with('"') || content.len() == idx + 1 {
+                return None
+            }
+            let mut sub = String::new();
+            let mut chars = content[idx+1..content.len()-1].chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some(c) => sub.push(c),
+                        None => return None
+                    },
+                    '"' => return None,
+                    c => sub.push(c)
+                }
+            }
+            Some(format!("{}.{}", name.to_lowercase(), sub))
+        }
+    }
+}
+
+/// parse the value of a variable: handles the quoted sections, the
+/// escape sequences, the comments and the line continuations (a `\` at the
+/// end of the line).
+fn parse_value<'a, I>(first: &str, next_lines: &mut I) -> Option<String>
+    where I: Iterator<Item=&'a str>
+{
+    let mut value = String::new();
+    // white spaces are only kept if followed by something else
+    let mut spaces = String::new();
+    let mut quoted = false;
+    let mut line = first.to_string();
+    loop {
+        let mut continued = false;
+        {
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        quoted = ! quoted;
+                        value.push_str(&spaces);
+                        spaces.clear();
+                    },
+                    '\\' => {
+                        let escaped = match chars.next() {
+                            None => { continued = true; break },
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('b') => '\u{8}',
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            Some(_) => return None
+                        };
+                        value.push_str(&spaces);
+                        spaces.clear();
+                        value.push(escaped);
+                    },
+                    '#' | ';' if ! quoted => break,
+                    c if c.is_whitespace() && ! quoted => {
+                        if ! value.is_empty() { spaces.push(c) }
+                    },
+                    c => {
+                        value.push_str(&spaces);
+                        spaces.clear();
+                        value.push(c);
+                    }
+                }
+            }
+        }
+        if ! continued { break }
+        match next_lines.next() {
+            Some(l) => line = l.to_string(),
+            None => break
+        }
+    }
+    if quoted { None } else { Some(value) }
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    const CONFIG : &'static str = r#"
+# this is a comment
+[core]
+	repositoryformatversion = 0
+	filemode = true
+	bare = false
+	logallrefupdates
+[remote "origin"]
+	url = https://github.com/NicolasDP/git ; the url
+	fetch = +refs/heads/*:refs/remotes/origin/*
+	fetch = +refs/tags/*:refs/tags/*
+[branch "Dev/Stage"]
+	remote = origin
+	merge = refs/heads/dev/stage
+[user]
+	name = "Nicolas  Di Prima" # quoted
+	motto = hello \
+world
+	escaped = "a\tb\\c\"d"
+"#;
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_str(CONFIG).unwrap();
+        assert_eq!(config.get("core.repositoryformatversion"), Some("0"));
+        assert_eq!(config.get_bool("core.filemode"), Some(true));
+        assert_eq!(config.get_bool("core.bare"), Some(false));
+        assert_eq!(config.get_bool("core.logallrefupdates"), Some(true));
+        assert_eq!(config.get("remote.origin.url"), Some("https://github.com/NicolasDP/git"));
+        assert_eq!(config.get("remote.origin.fetch"), Some("+refs/tags/*:refs/tags/*"));
+        assert_eq!( config.get_all("remote.origin.fetch")
+                  , vec!["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"]
+                  );
+        assert_eq!(config.get("BRANCH.Dev/Stage.REMOTE"), Some("origin"));
+        assert_eq!(config.get("branch.dev/stage.remote"), None);
+        assert_eq!(config.get("user.name"), Some("Nicolas  Di Prima"));
+        assert_eq!(config.get("user.motto"), Some("hello world"));
+        assert_eq!(config.get("user.escaped"), Some("a\tb\\c\"d"));
+        assert_eq!(config.get("user.email"), None);
+    }
+
+    #[test]
+    fn parse_config_invalid() {
+        assert!(Config::from_str("key = value\n").is_err());
+        assert!(Config::from_str("[core\n").is_err());
+        assert!(Config::from_str("[core]\n\tkey = \"value\n").is_err());
+        assert!(Config::from_str("[core]\n\t1key = value\n").is_err());
+    }
+}
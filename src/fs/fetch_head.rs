@@ -0,0 +1,71 @@
+//! Git's `FETCH_HEAD`: the references fetched by the last `git fetch`
+//!
+//! Every fetched reference is written on a line:
+//! `<hash>\t<not-for-merge>\t<description>`, the second field is empty for
+//! the references `git pull` merges.
+
+use nom;
+use std::str;
+
+use protocol::Hash;
+
+/// a reference fetched by the last `git fetch`
+///
+/// The `description` is the one written by git, for example:
+/// `branch 'master' of https://github.com/NicolasDP/git`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FetchHeadEntry<H: Hash> {
+    pub hash: H,
+    /// `false` if the line is marked `not-for-merge`
+    pub merge: bool,
+    pub description: String
+}
+
+fn nom_parse_fetch_head_entry<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], FetchHeadEntry<H>> {
+    let (b, hash) = try_parse!(b, H::decode_hex);
+    let (b, _) = try_parse!(b, tag!("\t"));
+    let (b, not_for_merge) = try_parse!(b, opt!(tag!("not-for-merge")));
+    let (b, _) = try_parse!(b, tag!("\t"));
+    let (b, description) = try_parse!(b, map_res!(take_until_and_consume!("\n"), str::from_utf8));
+    nom::IResult::Done(
+        b,
+        FetchHeadEntry {
+            hash: hash,
+            merge: not_for_merge.is_none(),
+            description: description.to_string()
+        }
+    )
+}
+
+/// parse the content of the `FETCH_HEAD` file
+pub fn parse_fetch_head<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Vec<FetchHeadEntry<H>>> {
+    many0!(b, nom_parse_fetch_head_entry)
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::SHA1;
+
+    const FETCH_HEAD : &'static str =
+        "251fd8e7f2482bda4b0c0186dd7bdc98b52fc955\t\tbranch 'master' of https://github.com/NicolasDP/git\n\
+         a5f0d4ba4a55af51346a0fb401bc291833d62711\tnot-for-merge\tbranch 'dev' of https://github.com/NicolasDP/git\n\
+         2aae6c35c94fcfb415dbe95f408b9ce91ee846ed\tnot-for-merge\ttag 'v0.1' of https://github.com/NicolasDP/git\n";
+
+    #[test]
+    fn parse_fetch_head_entries() {
+        let (rest, entries) = parse_fetch_head::<SHA1>(FETCH_HEAD.as_bytes()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].hash.to_hexadecimal(), "251fd8e7f2482bda4b0c0186dd7bdc98b52fc955");
+        assert!(entries[0].merge);
+        assert_eq!(entries[0].description, "branch 'master' of https://github.com/NicolasDP/git");
+        assert!(! entries[1].merge);
+        assert!(! entries[2].merge);
+        assert_eq!(entries[2].description, "tag 'v0.1' of https://github.com/NicolasDP/git");
+    }
+}
@@ -1,26 +1,55 @@
 use std::path::*;
-use std::io::Read;
+use std::io::{self, Read, Seek, SeekFrom, BufReader};
 use std::str::FromStr;
 use std::fs;
+use std::ffi::OsStr;
+use std::collections::{BTreeSet, BTreeMap};
+use nom;
 
-use protocol::{Repo, Hash, ZlibDecoder, Decoder, Partial};
+use protocol::{Repo, ObjectSource, MAX_SYMREF_DEPTH, MAX_TREE_DEPTH, Hash, ZlibDecoder, Decoder, Encoder, Partial, same_hash};
 use error::{Result, GitError};
-use refs::{SpecRef, Ref};
-use object::{Object, Obj};
-use nom;
+use refs::{SpecRef, Ref, RefName, HeadState};
+use object::{Object, Obj, ObjectKind, BlobRef, TreeRef, TreeEnt, Permission, object_id};
 
 mod pack;
 mod util;
+mod config;
+mod blob;
+mod staging;
+mod reflog;
+mod writer;
+mod cache;
+mod fetch_head;
+mod stats;
 
 pub use self::pack::*;
+pub use self::config::Config;
+pub use self::blob::BlobReader;
+pub use self::staging::{StagingIndex, IndexEntry};
+pub use self::reflog::ReflogEntry;
+pub use self::writer::ObjectSinkWriter;
+pub use self::cache::ObjectCache;
+pub use self::fetch_head::FetchHeadEntry;
+pub use self::stats::RepoStats;
 use self::util::*;
-use self::pack::index::{list_indexes, IndexRef, parse_index_file};
+use self::pack::index::{list_indexes, IndexRef, parse_index_file, parse_index};
 
 /// default structure used to contain some information regarding the git repository
 /// some information such as the file path.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct GitFS {
     path: PathBuf,
+    verify: bool,
+    /// the objects directory, when not the `objects` of the git directory
+    /// (see `with_object_dir` and the `alternates`)
+    objects: Option<PathBuf>,
+    /// the object directories listed in `objects/info/alternates`
+    alternates: Vec<PathBuf>,
+    /// the objects already read (see `with_cache`)
+    cache: ObjectCache,
+    /// the directory of the `.git` file the repository was opened from (see
+    /// `new`): the working directory, the git directory being elsewhere
+    work_tree: Option<PathBuf>
 }
 
 impl GitFS {
@@ -45,9 +74,174 @@ impl GitFS {
     /// and then returned the loaded GitFS.
     ///
     /// TODO: rename to `open`
+    ///
+    /// If the given path is a `.git` file (`gitdir: <path>`, as git writes
+    /// for the submodules) the directory it points to is opened instead, and
+    /// the directory of the `.git` file is the working directory.
     pub fn new(p: &Path) -> Result<Self> {
-        let git = GitFS { path: p.to_path_buf() };
-        git.check_repo().map(move |_| git)
+        let (path, work_tree) = if p.is_file() {
+            (try!(read_gitdir_file(p)), p.parent().map(|d| d.to_path_buf()))
+        } else {
+            (p.to_path_buf(), None)
+        };
+        let mut git = GitFS {
+            path: path,
+            verify: false,
+            objects: None,
+            alternates: Vec::new(),
+            cache: ObjectCache::new(0),
+            work_tree: work_tree
+        };
+        try!(git.check_repo());
+        git.alternates = try!(git.read_alternates());
+        Ok(git)
+    }
+
+    /// find the repository containing the given directory, as
+    /// `git rev-parse --git-dir` does: the directory and then its parents
+    /// are searched for a `.git` directory, or are tried as a git directory
+    /// (a bare repository or a path inside the `.git` directory).
+    ///
+    /// Fails if none of the parents is in a repository.
+    pub fn discover(start: &Path) -> Result<Self> {
+        let start = try!(fs::canonicalize(start));
+        let mut dir = Some(start.as_path());
+        while let Some(d) = dir {
+            let dot_git = d.join(".git");
+            if dot_git.exists() {
+                if let Ok(git) = GitFS::new(&dot_git) { return Ok(git) }
+            }
+            if let Ok(git) = GitFS::new(d) { return Ok(git) }
+            dir = d.parent();
+        }
+        Err(GitError::Other(format!( "not a git repository (or any of the parent directories): {}"
+                                   , start.display()
+                                   )))
+    }
+
+    /// tell if the repository is bare (without a working directory): as
+    /// given by `core.bare` in the config or, if not set, if the repository
+    /// was not opened from a `.git` file and the git directory is not a
+    /// `.git` directory.
+    pub fn is_bare(&self) -> Result<bool> {
+        match try!(self.get_config()).get_bool("core.bare") {
+            Some(bare) => Ok(bare),
+            None => Ok(self.work_tree.is_none() && self.path.file_name() != Some(OsStr::new(".git")))
+        }
+    }
+
+    /// the working directory of the repository (the directory of the `.git`
+    /// file it was opened from, or else the parent of the git directory),
+    /// `None` if the repository is bare
+    pub fn work_dir(&self) -> Result<Option<PathBuf>> {
+        if try!(self.is_bare()) {
+            return Ok(None)
+        }
+        match &self.work_tree {
+            &Some(ref dir) => Ok(Some(dir.clone())),
+            &None => Ok(self.path.parent().map(|p| p.to_path_buf()))
+        }
+    }
+
+    /// return the path of the alternates file: the list of the other
+    /// object directories this repository borrows objects from
+    pub fn alternates_file(&self) -> PathBuf { self.objs_dir().join("info").join("alternates") }
+
+    /// read the object directories listed in the alternates file (one per
+    /// line, relative to the `objects` directory if not absolute)
+    fn read_alternates(&self) -> Result<Vec<PathBuf>> {
+        let path = self.alternates_file();
+        if ! path.is_file() {
+            return Ok(Vec::new())
+        }
+        let mut content = String::new();
+        try!(try!(open_file(&path)).read_to_string(&mut content));
+        Ok(content.lines()
+                  .map(|l| l.trim())
+                  .filter(|l| ! l.is_empty() && ! l.starts_with('#'))
+                  .map(|l| self.objs_dir().join(l))
+                  .collect())
+    }
+
+    /// the object stores of the alternate object directories, in the order
+    /// they are listed (the alternates of the alternates are not followed)
+    fn alternate_stores(&self) -> Vec<GitFS> {
+        self.alternates.iter().map(|dir| GitFS {
+            path: self.path.clone(),
+            verify: self.verify,
+            objects: Some(dir.clone()),
+            alternates: Vec::new(),
+            cache: ObjectCache::new(0),
+            work_tree: self.work_tree.clone()
+        }).collect()
+    }
+
+    /// check the object is in this object store (ignoring the alternates)
+    fn exists_local<H: Hash>(&self, h: &H) -> Result<bool> {
+        if self.object_file(h).is_file() {
+            return Ok(true)
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            if index.find(h).is_some() {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    /// find the alternate object store containing the given object
+    fn find_alternate<H: Hash>(&self, h: &H) -> Result<Option<GitFS>> {
+        for alt in self.alternate_stores() {
+            if try!(alt.exists_local(h)) {
+                return Ok(Some(alt))
+            }
+        }
+        Ok(None)
+    }
+
+    /// enable (or disable) the verification of the objects read from the
+    /// repository: the hash of the object is computed again and compared
+    /// to the requested one (`GitError::HashMismatch` on mismatch).
+    ///
+    /// This is disabled by default as it has a cost on every read. The blobs
+    /// streamed with `open_blob` are not verified.
+    pub fn set_verify(&mut self, verify: bool) { self.verify = verify }
+
+    /// keep in memory up to `capacity` bytes of the objects read from the
+    /// repository (see `ObjectCache`), so reading the same objects again
+    /// (the trees of a history...) does not open and inflate them again.
+    ///
+    /// The clones of the returned `GitFS` share the same cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::path::*;
+    /// use git::fs::GitFS;
+    /// let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap().with_cache(16 * 1024 * 1024);
+    /// ```
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = ObjectCache::new(capacity);
+        self
+    }
+
+    /// read and write the objects in the given directory instead of the
+    /// `objects` directory of the repository (as git does with
+    /// `GIT_OBJECT_DIRECTORY`); the references are still the ones of the
+    /// repository.
+    ///
+    /// The alternates are then the ones listed in the given directory: it
+    /// may have no alternates file, but an alternates file which cannot be
+    /// read is an error (as in `new`).
+    pub fn with_object_dir(mut self, dir: PathBuf) -> Result<Self> {
+        self.objects = Some(dir);
+        self.alternates = match self.read_alternates() {
+            Ok(alternates) => alternates,
+            Err(ref err) if err.io_kind() == Some(io::ErrorKind::NotFound) => Vec::new(),
+            Err(err) => return Err(err)
+        };
+        Ok(self)
     }
 
     /// return the refs directory (where all the link to the branches and tags are)
@@ -64,7 +258,12 @@ impl GitFS {
     /// ```
     pub fn refs_dir(&self)  -> PathBuf { self.path.to_path_buf().join("refs") }
     /// return the objects path (contain all the blob and packed git objects)
-    pub fn objs_dir(&self)  -> PathBuf { self.path.to_path_buf().join("objects") }
+    pub fn objs_dir(&self)  -> PathBuf {
+        match &self.objects {
+            &Some(ref objects) => objects.clone(),
+            &None => self.path.to_path_buf().join("objects")
+        }
+    }
     /// return the info directory path
     pub fn info_dir(&self)  -> PathBuf { self.path.to_path_buf().join("info") }
     /// return the hooks directory path
@@ -75,6 +274,8 @@ impl GitFS {
     pub fn description_file(&self) -> PathBuf { self.path.to_path_buf().join("description") }
     /// return the git current HEAD file path
     pub fn head_file(&self)        -> PathBuf { self.path.to_path_buf().join("HEAD") }
+    /// return the path of the references fetched by the last `git fetch`
+    pub fn fetch_head_file(&self)  -> PathBuf { self.path.to_path_buf().join("FETCH_HEAD") }
 
 
     fn check_repo(&self) -> Result<()> {
@@ -83,17 +284,29 @@ impl GitFS {
                    , self.info_dir()
                    , self.hooks_dir()
                    ];
+        // the `description` file is optional (git does not need it)
         let files = [ self.config_file()
-                    , self.description_file()
                     , self.head_file()
                     ];
+        // only a missing entry means this is not a git repository, the
+        // other errors (permission denied...) are reported as they are
         for dir in dirs.iter() {
-            if ! dir.exists() || ! dir.is_dir() {
+            let found = match dir.metadata() {
+                Ok(m) => m.is_dir(),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => false,
+                Err(err) => return Err(GitError::ioerror(err))
+            };
+            if ! found {
                 return Err(GitError::MissingDirectory(dir.clone()))
             }
         };
         for file in files.iter() {
-            if ! file.exists() || ! file.is_file() {
+            let found = match file.metadata() {
+                Ok(m) => m.is_file(),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => false,
+                Err(err) => return Err(GitError::ioerror(err))
+            };
+            if ! found {
                 return Err(GitError::MissingFile(file.clone()))
             }
         };
@@ -101,16 +314,28 @@ impl GitFS {
     }
     fn lookup_hash_loose<H: Hash>(&self, prefix: &Partial<H>) -> Result<Vec<H>> {
         let hex = prefix.to_hexadecimal();
-        let hex_start = &hex.as_str()[0..2];
-        let hex_end = &hex.as_str()[2..];
-        let mut looses = Vec::new();// try!(self.lookup_hash_loose(prefix.to_hexadecimal()));
+        if hex.len() < 2 {
+            // the prefix does not select one directory: look in all of them
+            let mut looses = Vec::new();
+            for n in 0..256 {
+                let dir = format!("{:02x}", n);
+                if dir.starts_with(&hex) {
+                    looses.extend(try!(self.lookup_hash_loose_in(&dir, "")));
+                }
+            }
+            return Ok(looses)
+        }
+        self.lookup_hash_loose_in(&hex[0..2], &hex[2..])
+    }
+    fn lookup_hash_loose_in<H: Hash>(&self, hex_start: &str, hex_end: &str) -> Result<Vec<H>> {
+        let mut looses = Vec::new();
         let loose_path = self.objs_dir().join(hex_start);
         if ! loose_path.exists() || ! loose_path.is_dir() {
             return Ok(looses)
         }
-        let paths = io_try!(fs::read_dir(&loose_path));
+        let paths = try!(fs::read_dir(&loose_path));
         for path in paths {
-            let path = io_try!(path).path();
+            let path = try!(path).path();
             let filename = path.file_name().unwrap()
                                .to_str().unwrap();
             if filename.starts_with(hex_end) {
@@ -122,228 +347,2703 @@ impl GitFS {
         }
         Ok(looses)
     }
-}
-impl Repo for GitFS {
-    fn is_valid(&self) -> Result<()> { self.check_repo() }
 
-    fn get_description(&self) -> Result<String> {
-        let filepath = self.description_file();
-        let mut file = try!(open_file(&filepath));
-        let mut s = String::new();
-        file.read_to_string(&mut s)
-            .map_err(|err| GitError::ioerror(err))
-            .map(move |_| s)
+    /// list the hashes of all the objects of the repository, loose or
+    /// packed, sorted and without duplicates.
+    ///
+    /// The loose objects are found in the `objects/xx/` directories (the
+    /// `pack` and `info` directories are not objects' directories) and the
+    /// packed objects in the indexes of the pack files.
+    pub fn list_all_objects<H: Hash>(&self) -> Result<Vec<H>> {
+        let mut objects = BTreeSet::new();
+        for n in 0..256 {
+            let looses : Vec<H> = try!(self.lookup_hash_loose_in(&format!("{:02x}", n), ""));
+            objects.extend(looses.iter().map(|h| h.as_bytes().to_vec()));
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            objects.extend(index.hashes.iter().map(|h| h.as_bytes().to_vec()));
+        }
+        Ok(objects.into_iter().filter_map(H::from_bytes).collect())
     }
 
-    fn get_ref<H: Hash>(&self, r: SpecRef) -> Result<Ref<H>> {
-        let filepath = self.path.to_path_buf().join(PathBuf::from(r));
-        let mut file = try!(open_file(&filepath));
-        let mut s = String::new();
-        file.read_to_string(&mut s)
-            .map_err(|err| GitError::ioerror(err))
-            .and_then(|_| Ref::from_str(&s))
+    /// count the objects of the repository: the loose ones, the packed ones
+    /// and the objects of each kind (see `RepoStats`)
+    ///
+    /// The kind of every object is read from its header, so this has to
+    /// inflate the beginning of every loose object. The packs are read in
+    /// the order of their objects, each index being parsed once: the kind
+    /// of a deltified object is the one of its base, already met in the
+    /// pack.
+    pub fn stats<H: Hash>(&self) -> Result<RepoStats> {
+        let mut stats = RepoStats::default();
+        // the objects counted by kind, to count only once the objects both
+        // loose and packed
+        let mut counted = BTreeSet::new();
+        for n in 0..256 {
+            let looses : Vec<H> = try!(self.lookup_hash_loose_in(&format!("{:02x}", n), ""));
+            stats.loose_objects += looses.len();
+            for h in looses.iter() {
+                let file = try!(open_file(&self.object_file(h)));
+                let (kind, _) = try!(read_loose_header(&mut ZlibDecoder::new(file)));
+                stats.add_kind(kind);
+                counted.insert(h.as_bytes().to_vec());
+            }
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            let path = self.pack_file(index.pack());
+            stats.packs += 1;
+            stats.packed_objects += index.hashes.len();
+            stats.pack_size += try!(fs::metadata(&path)).len();
+            let mut file = BufReader::new(try!(open_file(&path)));
+            let mut kinds = BTreeMap::new();
+            for (offset, h) in index.by_offset() {
+                try!(file.seek(SeekFrom::Start(offset as u64)));
+                let (ty, _) = try!(read_object_header(&mut file));
+                let base = match ty {
+                    PackObjectType::OfsDelta => offset.checked_sub(try!(read_ofs_delta_offset(&mut file))),
+                    PackObjectType::RefDelta => {
+                        let mut base = vec![0u8; H::digest_size()];
+                        try!(file.read_exact(&mut base));
+                        H::from_bytes(base).and_then(|base| index.offset(&base))
+                    },
+                    _ => None
+                };
+                let kind = match (ty.kind(), base.and_then(|base| kinds.get(&base))) {
+                    (Some(kind), _) => kind,
+                    (None, Some(&kind)) => kind,
+                    // a base stored after the delta, or in another pack
+                    (None, None) => {
+                        let (ty, _) = try!(self.read_pack_object_content(offset, index.pack()));
+                        ty.kind().expect("deltified objects are resolved")
+                    }
+                };
+                kinds.insert(offset, kind);
+                if counted.insert(h.as_bytes().to_vec()) {
+                    stats.add_kind(kind);
+                }
+            }
+        }
+        Ok(stats)
     }
 
-    fn get_object_<H>(&self, hhr: H) -> Result<Obj<H>> where H:Hash {
-        let r = hhr.to_hexadecimal();
-        let (rh, lh) = r.as_str().split_at(2);
-        let path = self.objs_dir().join(rh).join(lh);
-        if ! path.is_file() {
-            return Err(GitError::InvalidRef(path))
-        }
-        let file = try!(open_file(&path));
-        let mut zlibr = ZlibDecoder::new(file);
-        let mut s = Vec::new();
-        zlibr.read_to_end(&mut s)
-             .map_err(|err| GitError::ioerror(err))
-             .and_then(|_| {
-                 match Obj::<H>::decode(s.as_ref()) {
-                     nom::IResult::Done(_, v) => Ok(v),
-                     nom::IResult::Error(err) => {
-                         Err(GitError::ParsingError(format!("{:?}", err)))
-                     },
-                     nom::IResult::Incomplete(err) => Err(GitError::ParsingErrorNotEnough(None))
-                 }
-             })
-    }
-    fn get_object<H, O>(&self, hhr: O::Id) -> Result<O>
-        where H: Hash
-            , O: Object<H>
-            , O::Id: Hash
-    {
-        let r = hhr.to_hexadecimal();
-        let (rh, lh) = r.as_str().split_at(2);
-        let path = self.objs_dir().join(rh).join(lh);
-        if ! path.is_file() {
-            return Err(GitError::InvalidRef(path))
-        }
-        let file = try!(open_file(&path));
-        let mut zlibr = ZlibDecoder::new(file);
-        let mut s = Vec::new();
-        zlibr.read_to_end(&mut s)
-             .map_err(|err| GitError::ioerror(err))
-             .and_then(|_| {
-                 match O::decode(s.as_ref()) {
-                     nom::IResult::Done(_, v) => Ok(v),
-                     nom::IResult::Error(err) => {
-                         Err(GitError::ParsingError(format!("{:?}", err)))
-                     },
-                     nom::IResult::Incomplete(err) => Err(GitError::ParsingErrorNotEnough(None))
-                 }
-             })
+    /// return the path of the staging index file
+    pub fn staging_index_file(&self) -> PathBuf { self.path.to_path_buf().join("index") }
+
+    /// read and parse the staging index (`.git/index`)
+    pub fn read_index<H: Hash>(&self) -> Result<StagingIndex<H>> {
+        let mut file = try!(open_file(&self.staging_index_file()));
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        Ok(nom_try!(self::staging::parse_staging_index(&data)))
     }
 
-    fn lookup_hash<H: Hash>(&self, prefix: &Partial<H>) -> Result<Vec<H>> {
-        let mut looses = try!(self.lookup_hash_loose(prefix));
-        for idx in list_indexes::<H>(self)?.iter() {
-            let idx_file = format!("pack-{}.idx", idx.to_hexadecimal());
-            let path_idx = self.objs_dir().join("pack").join(idx_file);
-            let index = try!(parse_index_file::<H>(&path_idx));
-            looses.extend(
-                index.hashes.into_iter().filter(
-                    |h| prefix.is_prefix_of(h)
-                )
-            )
-        }
-        Ok(looses)
+    /// return the path of the log of the given reference
+    pub fn reflog_file(&self, r: &SpecRef) -> PathBuf {
+        self.path.to_path_buf().join("logs").join(PathBuf::from(r))
     }
-    fn list_branches(&self) -> Result<Vec<SpecRef>> {
-        get_all_files_in( self.refs_dir().join("heads")
-                        , &|x| Ok(Some(SpecRef::branch(x)))
-                        )
+
+    /// read the log of the given reference (`.git/logs/<ref>`)
+    ///
+    /// The entries are returned the most recent first (the first entry is
+    /// `<ref>@{0}`). A reference without log returns an empty list.
+    pub fn read_reflog<H: Hash>(&self, r: SpecRef) -> Result<Vec<ReflogEntry<H>>> {
+        let path = self.reflog_file(&r);
+        if ! path.is_file() {
+            return Ok(Vec::new())
+        }
+        let mut file = try!(open_file(&path));
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        let (rest, mut entries) = match self::reflog::parse_reflog(&data) {
+            nom::IResult::Done(rest, entries) => (rest, entries),
+            _ => return Err(GitError::ParsingError(format!("invalid reflog of {}", r)))
+        };
+        // `many0` stops on the first line it cannot parse
+        if ! rest.is_empty() {
+            return Err(GitError::ParsingError(format!("invalid line in the reflog of {}", r)))
+        }
+        entries.reverse();
+        Ok(entries)
     }
-    fn list_remotes(&self) -> Result<Vec<SpecRef>> {
-        get_all_files_in( self.refs_dir().join("remotes")
-                        , &|remote_path| {
-            let mut components = remote_path.components();
-            components
-                .next()
-                .map_or(Err(GitError::InvalidRemote(remote_path.to_path_buf())), |p| {
-                    match p {
-                        Component::Normal(remote) => Ok(
-                            Some(SpecRef::remote(remote, components.as_path()))
-                        ),
-                        _ => Err(GitError::Unknown("invalid remote name".to_string()))
-                    }
-                })
-            }
-        )
+
+    /// read the references fetched by the last `git fetch` (`FETCH_HEAD`)
+    ///
+    /// Unlike the other references, `FETCH_HEAD` may hold multiple hashes
+    /// (one per fetched reference). An empty list is returned if nothing
+    /// has been fetched yet.
+    pub fn read_fetch_head<H: Hash>(&self) -> Result<Vec<FetchHeadEntry<H>>> {
+        let path = self.fetch_head_file();
+        if ! path.is_file() {
+            return Ok(Vec::new())
+        }
+        let mut file = try!(open_file(&path));
+        let mut data = Vec::new();
+        try!(file.read_to_end(&mut data));
+        let (rest, entries) = match self::fetch_head::parse_fetch_head(&data) {
+            nom::IResult::Done(rest, entries) => (rest, entries),
+            _ => return Err(GitError::ParsingError("invalid FETCH_HEAD".to_string()))
+        };
+        if ! rest.is_empty() {
+            return Err(GitError::ParsingError("invalid FETCH_HEAD line".to_string()))
+        }
+        Ok(entries)
     }
-    fn list_tags(&self) -> Result<Vec<SpecRef>> {
-        get_all_files_in( self.refs_dir().join("tags")
-                        , &|x| Ok(Some(SpecRef::tag(x)))
-                        )
+
+    /// read and parse the git config file
+    pub fn get_config(&self) -> Result<Config> {
+        let mut file = try!(open_file(&self.config_file()));
+        let mut s = String::new();
+        try!(file.read_to_string(&mut s));
+        Config::from_str(&s)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use ::protocol::*;
-    use ::error::*;
-    use ::refs::*;
-    use ::object::*;
-    use std::path::*;
+    /// the branch `HEAD` points to (`None` if `HEAD` is detached)
+    fn current_branch(&self) -> Result<Option<RefName>> {
+        let mut file = try!(open_file(&self.head_file()));
+        let mut s = String::new();
+        try!(file.read_to_string(&mut s));
+        let s = s.trim();
+        if ! s.starts_with("ref:") {
+            return Ok(None)
+        }
+        match try!(SpecRef::from_str(s[4..].trim_left())) {
+            SpecRef::Branch(b) => Ok(Some(b)),
+            _ => Ok(None)
+        }
+    }
 
-    fn get_test_commit() -> Ref<CommitRef<SHA1>> {
-        Ref::Link(SpecRef::branch("master"))
+    /// get the remote tracking branch of the given local branch
+    ///
+    /// The upstream is read from the branch's configuration:
+    ///
+    /// ```text
+    /// [remote "origin"]
+    ///     fetch = +refs/heads/*:refs/remotes/origin/*
+    /// [branch "master"]
+    ///     remote = origin
+    ///     merge = refs/heads/master
+    /// ```
+    ///
+    /// The `merge` reference (as named on the remote) is mapped to the local
+    /// remote tracking branch with the remote's `fetch` refspecs, which gives
+    /// `refs/remotes/origin/master` here. Without `fetch` refspecs, the
+    /// default `refs/remotes/<remote>/<name>` is used. A `remote` set to `.`
+    /// means the upstream is a local branch.
+    ///
+    /// Returns `None` if the branch has no upstream configured or if the
+    /// upstream is not fetched in a remote tracking branch.
+    pub fn upstream_of(&self, branch: &RefName) -> Result<Option<SpecRef>> {
+        let config = try!(self.get_config());
+        let name = format!("branch.{}", branch.display());
+        let (remote, merge) = match ( config.get(&format!("{}.remote", name))
+                                    , config.get(&format!("{}.merge", name))
+                                    ) {
+            (Some(remote), Some(merge)) => (remote, merge),
+            _ => return Ok(None)
+        };
+        if remote == "." {
+            return SpecRef::from_str(merge).map(Some)
+        }
+        let merged_branch = match try!(SpecRef::from_str(merge)) {
+            SpecRef::Branch(b) => b,
+            _ => return Err(GitError::InvalidRef(PathBuf::from(merge)))
+        };
+        let refspecs = config.get_all(&format!("remote.{}.fetch", remote));
+        if refspecs.is_empty() {
+            return Ok(Some(SpecRef::remote(remote, merged_branch)))
+        }
+        for refspec in refspecs {
+            if let Some(tracking) = map_refspec(refspec, merge) {
+                return SpecRef::from_str(&tracking).map(Some)
+            }
+        }
+        Ok(None)
     }
 
-    fn get_root_test() -> PathBuf {
-        PathBuf::new().join(".").join("test_ref").join(".git")
+    /// parse a reference, understanding git's revision shortcuts
+    ///
+    /// On top of what `SpecRef::from_str` parses (including `@` for `HEAD`),
+    /// this resolves the `@{upstream}` (or `@{u}`) suffix: `master@{u}` is
+    /// the remote tracking branch of `master` (see `upstream_of`) and `@{u}`
+    /// the one of the current branch.
+    pub fn resolve_specref(&self, s: &str) -> Result<SpecRef> {
+        let s = s.trim();
+        for suffix in ["@{upstream}", "@{u}"].iter() {
+            if s.len() < suffix.len() || ! s.is_char_boundary(s.len() - suffix.len()) {
+                continue;
+            }
+            let (branch, tail) = s.split_at(s.len() - suffix.len());
+            if ! tail.eq_ignore_ascii_case(suffix) {
+                continue;
+            }
+            let branch = match branch {
+                "" | "@" | "HEAD" => match try!(self.current_branch()) {
+                    Some(b) => b,
+                    None => return Err(GitError::InvalidRef(PathBuf::from(s)))
+                },
+                branch => match SpecRef::from_str(branch) {
+                    Ok(SpecRef::Branch(b)) => b,
+                    _ => PathBuf::from(branch)
+                }
+            };
+            return match try!(self.upstream_of(&branch)) {
+                Some(upstream) => Ok(upstream),
+                None => Err(GitError::InvalidBranch(branch))
+            }
+        }
+        SpecRef::from_str(s)
     }
 
-    #[test]
-    fn lookup() {
-        let path = PathBuf::new().join(".").join(".git");
-        let git = GitFS::new(&path).unwrap();
-        let r = git.lookup_hash(
-            &Partial::<SHA1>::from_hex("f34").unwrap()
-        ).unwrap();
-        for h in r.iter() {
-            let h_str = format!("{}", h);
-            assert!(h_str.starts_with("f34"));
+    /// tell whether `HEAD` points to a branch or directly to a commit
+    /// (detached `HEAD`).
+    pub fn head_state<H: Hash>(&self) -> Result<HeadState<H>> {
+        match try!(self.get_head()) {
+            Ref::Link(r) => Ok(HeadState::OnBranch(r)),
+            Ref::Hash(h) => Ok(HeadState::Detached(h))
         }
     }
-    #[test]
-    fn new() {
-        let path = get_root_test();
-        assert_eq!(GitFS::new(&path), Ok(GitFS { path: path.clone()}))
+
+    /// return the path of the given pack file
+    pub fn pack_file<H: Hash>(&self, pack: &PackRef<H>) -> PathBuf {
+        self.objs_dir().join("pack").join(format!("pack-{}.pack", pack.to_hexadecimal()))
     }
-    #[test]
-    fn new_fail() {
-        let path = PathBuf::new().join(".").join("src");
-        let missing = PathBuf::new().join(".").join("src").join("refs");
-        assert_eq!(GitFS::new(&path), Err(GitError::MissingDirectory(missing)))
+    /// return the path of the given pack index file
+    pub fn index_file<H: Hash>(&self, idx: &IndexRef<H>) -> PathBuf {
+        self.objs_dir().join("pack").join(format!("pack-{}.idx", idx.to_hexadecimal()))
     }
 
-    #[test]
-    fn git_fs_get_description() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let desc = git.get_description();
-        assert!(desc.is_ok())
+    /// check the trailing checksums of the pack index and of its pack file
+    ///
+    /// * the `.idx` ends with the hash of the pack and with its own hash
+    ///   (of all the bytes before it);
+    /// * the `.pack` ends with the hash of all the bytes before it.
+    ///
+    /// The pack file is streamed through `H::hash`, it is not loaded in
+    /// memory. Returns `GitError::HashMismatch` (expected, actual) if one of
+    /// the checksums does not match.
+    pub fn verify_pack<H: Hash>(&self, idx: &IndexRef<H>) -> Result<()> {
+        let mut data = Vec::new();
+        try!(try!(open_file(&self.index_file(idx))).read_to_end(&mut data));
+        let index = nom_try!(parse_index::<H>(&data));
+        if data.len() < H::digest_size() {
+            return Err(GitError::ParsingErrorNotEnough(Some(H::digest_size() - data.len())))
+        }
+        let actual = try!(H::hash(&mut &data[..data.len() - H::digest_size()]));
+        try!(check_checksum(index.index(), &actual));
+
+        let path = self.pack_file(index.pack());
+        let size = try!(fs::metadata(&path)).len();
+        if size < H::digest_size() as u64 {
+            return Err(GitError::ParsingErrorNotEnough(Some(H::digest_size() - size as usize)))
+        }
+        let mut file = try!(open_file(&path));
+        let actual = try!(H::hash(&mut BufReader::new(file.by_ref().take(size - H::digest_size() as u64))));
+        let mut trailer = Vec::with_capacity(H::digest_size());
+        try!(file.read_to_end(&mut trailer));
+        let len = trailer.len();
+        let expected = try!(H::from_bytes(trailer).ok_or(GitError::InvalidHashSize(H::digest_size(), len)));
+        try!(check_checksum(&expected, &actual));
+        check_checksum(index.pack(), &actual)
     }
 
-    #[test]
-    fn git_fs_get_ref() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let r = SpecRef::branch("master");
-        let result : Result<Ref<SHA1>> = git.get_ref(r);
-        assert!(result.is_ok())
+    /// list the objects of the given pack, sorted by offset, with their
+    /// type and sizes (see `PackObjectInfo`)
+    ///
+    /// Only the headers of the objects are read: the deltas are not
+    /// resolved and the objects are not inflated.
+    pub fn iter_pack_objects<H: Hash>(&self, idx: &IndexRef<H>) -> Result<Vec<PackObjectInfo<H>>> {
+        let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+        let path = self.pack_file(index.pack());
+        let pack_size = try!(fs::metadata(&path)).len() as usize;
+        // the objects end where the trailing checksum starts
+        let pack_end = match pack_size.checked_sub(H::digest_size()) {
+            Some(end) => end,
+            None => return Err(GitError::ParsingErrorNotEnough(Some(H::digest_size() - pack_size)))
+        };
+        let entries = index.by_offset();
+        if entries.len() != index.header().size() {
+            return Err(GitError::ParsingError(format!( "the index {} lists {} objects out of {}"
+                                                     , idx.to_hexadecimal(), entries.len(), index.header().size()
+                                                     )))
+        }
+
+        let mut file = BufReader::new(try!(open_file(&path)));
+        let mut objects = Vec::with_capacity(entries.len());
+        for (i, &(offset, h)) in entries.iter().enumerate() {
+            let end = entries.get(i + 1).map(|&(next, _)| next).unwrap_or(pack_end);
+            if end <= offset {
+                return Err(GitError::ParsingError(format!("invalid pack object offset: {}", offset)))
+            }
+            try!(file.seek(SeekFrom::Start(offset as u64)));
+            let (kind, size) = try!(read_object_header(&mut file));
+            objects.push(PackObjectInfo {
+                hash: try!(H::from_bytes(h.as_bytes().to_vec()).ok_or(GitError::InvalidHashSize(H::digest_size(), h.as_bytes().len()))),
+                offset: offset,
+                kind: kind,
+                size: size,
+                packed_size: end - offset
+            });
+        }
+        Ok(objects)
     }
-    #[test]
-    fn git_fs_get_ref_follow_link() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let result : Result<SHA1> = git.get_ref_follow_links(SpecRef::Head);
-        assert!(result.is_ok())
+
+    /// return the path of the loose object file: `objects/xx/yyyy...`
+    fn object_file<H: Hash>(&self, h: &H) -> PathBuf {
+        let hex = h.to_hexadecimal();
+        let (rh, lh) = hex.as_str().split_at(2);
+        self.objs_dir().join(rh).join(lh)
     }
-    #[test]
-    fn git_fs_get_head() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let head : Result<Ref<SHA1>> = git.get_head();
-        assert_eq!( head
-                  , Ok(Ref::Link(SpecRef::branch("master")))
-                  )
+
+    /// read the raw data of the object: `<type> <size>\0<content>`
+    ///
+    /// The object is looked up in the loose objects first and then in the
+    /// pack files.
+    fn read_object_data<H: Hash>(&self, h: &H) -> Result<Vec<u8>> {
+        let data = try!(self.read_object_data_(h));
+        if self.verify {
+            let actual = try!(H::hash(&mut data.as_slice()));
+            if ! same_hash(&actual, h) {
+                return Err(GitError::HashMismatch(h.to_hexadecimal(), actual.to_hexadecimal()))
+            }
+        }
+        Ok(data)
     }
-    #[test]
-    fn git_fs_get_branches() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let branches = git.list_branches().expect("expect to list the branches");
-        assert!(branches.contains(&SpecRef::branch("master")));
+    fn read_object_data_<H: Hash>(&self, h: &H) -> Result<Vec<u8>> {
+        let path = self.object_file(h);
+        if path.is_file() {
+            let file = try!(open_file(&path));
+            let mut zlibr = ZlibDecoder::new(file);
+            let mut s = Vec::new();
+            try!(zlibr.read_to_end(&mut s));
+            return Ok(s)
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            if let Some(offset) = index.offset(h) {
+                return self.read_pack_object_data(offset, index.pack())
+            }
+        }
+        if let Some(alt) = try!(self.find_alternate(h)) {
+            return alt.read_object_data_(h)
+        }
+        Err(GitError::InvalidRef(path))
     }
-    #[test]
-    fn git_fs_get_remotes() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let branches = git.list_remotes().expect("expect to list the remotes");
-        println!("{:?}", branches);
-        assert!( branches.contains(&SpecRef::remote("origin", "HEAD"))
-                 || branches.contains(&SpecRef::remote("origin", "master")));
+
+    /// read the kind and the size of the given object without reading its
+    /// content (as `git cat-file -t` and `git cat-file -s` do).
+    ///
+    /// Only the header of the loose objects and of the packed objects is
+    /// read, but the deltified objects still have to be resolved.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use git::fs::GitFS;
+    /// use git::protocol::{Repo, SHA1};
+    /// use git::refs::SpecRef;
+    /// use git::object::ObjectKind;
+    /// use std::path::Path;
+    ///
+    /// let git = GitFS::new(Path::new("test_ref/.git")).unwrap();
+    /// let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+    /// let (kind, _size) = git.object_header(&head).unwrap();
+    /// assert_eq!(kind, ObjectKind::Commit);
+    /// ```
+    pub fn object_header<H: Hash>(&self, h: &H) -> Result<(ObjectKind, usize)> {
+        let path = self.object_file(h);
+        if path.is_file() {
+            let file = try!(open_file(&path));
+            return read_loose_header(&mut ZlibDecoder::new(file))
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            let offset = match index.offset(h) { Some(offset) => offset, None => continue };
+            let mut file = BufReader::new(try!(open_file(&self.pack_file(index.pack()))));
+            try!(file.seek(SeekFrom::Start(offset as u64)));
+            let (ty, size) = try!(read_object_header(&mut file));
+            if let Some(kind) = ty.kind() {
+                return Ok((kind, size))
+            }
+            let (ty, content) = try!(self.read_pack_object_content(offset, index.pack()));
+            let kind = ty.kind().expect("deltified objects are resolved");
+            return Ok((kind, content.len()))
+        }
+        if let Some(alt) = try!(self.find_alternate(h)) {
+            return alt.object_header(h)
+        }
+        Err(GitError::InvalidRef(path))
     }
-    #[test]
-    fn git_fs_get_commit() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        let commit = git.get_object_ref(get_test_commit()).unwrap();
-        println!("{}", commit)
+
+    /// read the object at the given offset of the pack file and return its
+    /// data as it would be in a loose object: `<type> <size>\0<content>`
+    fn read_pack_object_data<H: Hash>(&self, offset: usize, pack: &PackRef<H>) -> Result<Vec<u8>> {
+        let (ty, content) = try!(self.read_pack_object_content(offset, pack));
+        let name = ty.name().expect("deltified objects are resolved");
+        let mut data = format!("{} {}\0", name, content.len()).into_bytes();
+        data.extend(content);
+        Ok(data)
     }
-    #[test]
-    fn git_fs_get_tree() {
-        let path = get_root_test();
-        let git = GitFS::new(&path).unwrap();
-        // get the head
-        let commit =
-            git.get_object_ref(get_test_commit())
-                    .expect("expected to read a commit object");
+
+    /// read the type and the content of the object at the given offset of
+    /// the pack file, resolving the deltified objects.
+    ///
+    /// The chain of deltas is followed down to the base object (up to
+    /// `MAX_DELTA_CHAIN` deltas) and then the deltas are applied back up.
+    fn read_pack_object_content<H: Hash>(&self, offset: usize, pack: &PackRef<H>)
+        -> Result<(PackObjectType, Vec<u8>)>
+    {
+        let mut file = BufReader::new(try!(open_file(&self.pack_file(pack))));
+        let mut index = None;
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+        let (ty, mut content) = loop {
+            if deltas.len() > MAX_DELTA_CHAIN {
+                return Err(GitError::Other(format!("delta chain too long at offset {}", offset)))
+            }
+            try!(file.seek(SeekFrom::Start(offset as u64)));
+            let (ty, size) = try!(read_object_header(&mut file));
+            match ty {
+                PackObjectType::OfsDelta => {
+                    let distance = try!(read_ofs_delta_offset(&mut file));
+                    if distance == 0 || distance > offset {
+                        return Err(GitError::OutOfBound(distance, offset))
+                    }
+                    deltas.push(try!(inflate(&mut file, size)));
+                    offset -= distance;
+                },
+                PackObjectType::RefDelta => {
+                    let mut base = vec![0u8; H::digest_size()];
+                    try!(file.read_exact(&mut base));
+                    let base = match H::from_bytes(base) {
+                        Some(base) => base,
+                        None => return Err(GitError::InvalidHashSize(H::digest_size(), 0))
+                    };
+                    deltas.push(try!(inflate(&mut file, size)));
+                    if index.is_none() {
+                        let idx = match IndexRef::<H>::from_bytes(pack.as_bytes().to_vec()) {
+                            Some(idx) => idx,
+                            None => return Err(GitError::InvalidHashSize(H::digest_size(), pack.as_bytes().len()))
+                        };
+                        index = Some(try!(parse_index_file::<H>(&self.index_file(&idx))));
+                    }
+                    match index.as_ref().and_then(|index| index.offset(&base)) {
+                        Some(base_offset) => offset = base_offset,
+                        None => {
+                            // the base is not in this pack
+                            let data = try!(self.read_object_data(&base));
+                            break try!(split_object_data(&data))
+                        }
+                    }
+                },
+                ty => break (ty, try!(inflate(&mut file, size)))
+            }
+        };
+        while let Some(delta) = deltas.pop() {
+            content = try!(apply_delta(&content, &delta));
+        }
+        Ok((ty, content))
+    }
+
+    /// read the object (loose or packed): returns its kind and its body,
+    /// without the `<type> <size>\0` header
+    ///
+    /// The body is not parsed (see `Repo::get_object_` to decode it). This
+    /// is the `ObjectSource` implementation of the `GitFS`, it goes through
+    /// the cache (see `with_cache`).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use git::fs::GitFS;
+    /// use git::object::ObjectKind;
+    /// use git::protocol::{SHA1, Hash};
+    /// use std::path::PathBuf;
+    ///
+    /// let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap();
+    /// let readme = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap();
+    /// let (kind, body) = git.read_raw_object(&readme).unwrap();
+    /// assert_eq!(kind, ObjectKind::Blob);
+    /// assert_eq!(body, b"README\n".to_vec());
+    /// ```
+    pub fn read_raw_object<H: Hash>(&self, h: &H) -> Result<(ObjectKind, Vec<u8>)> {
+        let key = h.to_hexadecimal();
+        if let Some(object) = self.cache.get(&key) {
+            return Ok(object)
+        }
+        let mut data = try!(self.read_object_data(h));
+        let (kind, size, header) = {
+            let mut content = data.as_slice();
+            let (kind, size) = try!(read_loose_header(&mut content));
+            (kind, size, data.len() - content.len())
+        };
+        if data.len() - header < size {
+            return Err(GitError::ParsingErrorNotEnough(Some(size - (data.len() - header))))
+        }
+        if data.len() - header > size {
+            return Err(GitError::TrailingBytes(data.len() - header - size))
+        }
+        data.drain(..header);
+        self.cache.insert(key, kind, data.clone());
+        Ok((kind, data))
+    }
+
+    /// write the given object in the repository as a loose object
+    ///
+    /// An object already in the repository (loose, packed or in an
+    /// alternate) is not written again. Otherwise the object is encoded
+    /// straight into an `ObjectSinkWriter`: it is hashed and compressed into
+    /// a temporary file which is then renamed into `objects/xx/yyyy...`.
+    ///
+    /// Returns the hash of the object.
+    pub fn write_object<H, O>(&self, obj: &O) -> Result<H>
+        where H: Hash
+            , O: Object<H> + Encoder
+    {
+        let id = try!(object_id(obj));
+        if try!(self.exists(&id)) {
+            return Ok(id)
+        }
+        let mut writer = try!(self.object_writer());
+        try!(obj.encode(&mut writer));
+        writer.finish()
+    }
+
+    /// create a writer of a new loose object
+    ///
+    /// The raw object (`<type> <size>\0<content>`) is hashed and compressed
+    /// as it is written, so large blobs can be stored without being loaded in memory.
+    /// See `ObjectSinkWriter::finish`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::io::Write;
+    /// use std::path::PathBuf;
+    /// use git::fs::GitFS;
+    /// use git::protocol::{SHA1, Hash};
+    ///
+    /// let git = GitFS::new(&PathBuf::from(".git")).unwrap();
+    /// let mut writer = git.object_writer::<SHA1>().unwrap();
+    /// writer.write_all(b"blob 6\0hello\n").unwrap();
+    /// let hash = writer.finish().unwrap();
+    /// assert_eq!(hash.to_hexadecimal(), "ce013625030ba8dba906f756967f9e9ca394464a");
+    /// ```
+    pub fn object_writer<H: Hash>(&self) -> Result<ObjectSinkWriter<H>> {
+        ObjectSinkWriter::new(self.objs_dir())
+    }
+
+    /// open the given blob for reading, without loading its content in
+    /// memory.
+    ///
+    /// The loose blobs and the packed blobs stored as a whole are inflated
+    /// on the fly; the deltified blobs have to be resolved in memory first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use git::fs::GitFS;
+    /// use git::object::BlobRef;
+    /// use git::protocol::{SHA1, Hash};
+    /// use std::path::PathBuf;
+    /// use std::io::Read;
+    ///
+    /// let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap();
+    /// let readme = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap();
+    /// let mut reader = git.open_blob(BlobRef::new(readme)).unwrap();
+    /// let mut content = String::new();
+    /// reader.read_to_string(&mut content).unwrap();
+    /// assert_eq!(content, "README\n");
+    /// ```
+    pub fn open_blob<H: Hash>(&self, r: BlobRef<H>) -> Result<BlobReader> {
+        let h : &H = r.as_ref();
+        let path = self.object_file(h);
+        if path.is_file() {
+            let file = try!(open_file(&path));
+            return BlobReader::new(Box::new(ZlibDecoder::new(file)))
+        }
+        for idx in try!(list_indexes::<H>(self)).iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            let offset = match index.offset(h) { Some(offset) => offset, None => continue };
+            let mut file = BufReader::new(try!(open_file(&self.pack_file(index.pack()))));
+            try!(file.seek(SeekFrom::Start(offset as u64)));
+            let (ty, size) = try!(read_object_header(&mut file));
+            return match ty {
+                PackObjectType::Blob =>
+                    Ok(BlobReader::without_header(Box::new(ZlibDecoder::new(file)), size)),
+                PackObjectType::OfsDelta | PackObjectType::RefDelta => {
+                    match try!(self.read_pack_object_content(offset, index.pack())) {
+                        (PackObjectType::Blob, content) => {
+                            let size = content.len();
+                            Ok(BlobReader::without_header(Box::new(io::Cursor::new(content)), size))
+                        },
+                        _ => Err(GitError::ParsingError("expected a blob object".to_string()))
+                    }
+                },
+                _ => Err(GitError::ParsingError("expected a blob object".to_string()))
+            }
+        }
+        if let Some(alt) = try!(self.find_alternate(h)) {
+            return alt.open_blob(r)
+        }
+        Err(GitError::InvalidRef(path))
+    }
+
+    /// write the files of the given tree in the `dest` directory (created
+    /// if needed), as `git checkout` does in the working directory
+    ///
+    /// * the sub-trees are written in sub-directories;
+    /// * the files are executable if their permissions are (the other
+    ///   permission bits are not used, as git does);
+    /// * the symbolic links are created as such (on unix only, elsewhere
+    ///   a file holding the target of the link is written);
+    /// * the submodules are created as empty directories.
+    ///
+    /// The existing files are overwritten. The entries with a name that
+    /// could escape `dest` (`..`, a path with separators...) or write into
+    /// a `.git` directory are rejected with `GitError::Other`.
+    pub fn checkout_tree<H: Hash>(&self, root: TreeRef<H>, dest: &Path) -> Result<()> {
+        try!(fs::create_dir_all(dest));
+        let mut trees = vec![(dest.to_path_buf(), root, 0)];
+        while let Some((dir, tr, depth)) = trees.pop() {
+            if depth > MAX_TREE_DEPTH {
+                return Err(GitError::Other(format!("tree too deep: {}", dir.display())))
+            }
+            for te in try!(self.get_tree(tr)) {
+                match te {
+                    TreeEnt::Tree(_, name, tr) => {
+                        let path = try!(checkout_path(dest, &dir, &name));
+                        try!(fs::create_dir_all(&path));
+                        trees.push((path, tr, depth + 1))
+                    },
+                    TreeEnt::Blob(perm, name, br) => {
+                        let path = try!(checkout_path(dest, &dir, &name));
+                        let mut reader = try!(self.open_blob(br));
+                        let mut file = try!(fs::File::create(&path));
+                        try!(io::copy(&mut reader, &mut file));
+                        try!(set_executable(&path, perm.user.contains(&Permission::Executable)));
+                    },
+                    TreeEnt::SymbolicLink(_, name, br) => {
+                        let path = try!(checkout_path(dest, &dir, &name));
+                        let mut target = Vec::new();
+                        try!(try!(self.open_blob(br)).read_to_end(&mut target));
+                        try!(write_symlink(&path, target));
+                    },
+                    TreeEnt::GitLink(_, name, _) => {
+                        try!(fs::create_dir_all(try!(checkout_path(dest, &dir, &name))));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// read the object stored at the given offset of the given pack file
+    ///
+    /// The offsets of the packed objects are listed in the pack's index
+    /// (see `fs::pack::index::Index`).
+    pub fn read_pack_object<H: Hash>(&self, offset: usize, pack: &PackRef<H>) -> Result<Obj<H>> {
+        let data = try!(self.read_pack_object_data(offset, pack));
+        Obj::<H>::decode_all(data.as_ref())
+    }
+
+    /// return the path of the packed references file
+    pub fn packed_refs_file(&self) -> PathBuf { self.path.to_path_buf().join("packed-refs") }
+
+    /// read the references packed in the `packed-refs` file
+    ///
+    /// Returns an empty list if there is no `packed-refs` file.
+    pub fn get_packed_refs<H: Hash>(&self) -> Result<Vec<PackedRef<H>>> {
+        let path = self.packed_refs_file();
+        if ! path.is_file() {
+            return Ok(Vec::new())
+        }
+        let mut file = try!(open_file(&path));
+        let mut s = String::new();
+        try!(file.read_to_string(&mut s));
+        parse_packed_refs(&s)
+    }
+
+    /// get the hash of the given reference from the `packed-refs` file
+    fn get_packed_ref<H: Hash>(&self, r: &SpecRef) -> Result<Option<H>> {
+        Ok(try!(self.get_packed_refs::<H>())
+               .into_iter()
+               .find(|pr| &pr.name == r)
+               .map(|pr| pr.hash))
+    }
+
+    /// add the packed references (selected by `filter`) that are not
+    /// already in the given list of loose references.
+    fn merge_packed_refs<F>(&self, mut refs: Vec<SpecRef>, filter: F) -> Result<Vec<SpecRef>>
+        where F: Fn(&SpecRef) -> bool
+    {
+        let path = self.packed_refs_file();
+        if ! path.is_file() {
+            return Ok(refs)
+        }
+        let mut s = String::new();
+        try!(try!(open_file(&path)).read_to_string(&mut s));
+        for name in try!(parse_packed_ref_names(&s)) {
+            if filter(&name) && ! refs.contains(&name) {
+                refs.push(name)
+            }
+        }
+        Ok(refs)
+    }
+
+    /// return the path of the file holding the given reference
+    fn ref_file(&self, r: &SpecRef) -> PathBuf {
+        self.path.to_path_buf().join(PathBuf::from(r))
+    }
+
+    /// follow the symbolic references (`ref: ...`) until the reference
+    /// actually holding a hash (or not existing yet).
+    fn resolve_symbolic_ref<H: Hash>(&self, r: &SpecRef) -> Result<SpecRef> {
+        let mut current = r.clone();
+        let mut depth = 0;
+        while self.ref_file(&current).is_file() {
+            match try!(self.get_ref::<H>(current.clone())) {
+                Ref::Link(link) => current = link,
+                Ref::Hash(_)    => break
+            }
+            depth += 1;
+            if depth > MAX_SYMREF_DEPTH {
+                return Err(GitError::RefCycle(r.clone()))
+            }
+        }
+        Ok(current)
+    }
+
+    /// set the reference `r` to `new` if and only if its current value is
+    /// `expected`.
+    ///
+    /// `expected` set to `None` means the reference must not exist yet
+    /// (a new branch or an unborn branch). Symbolic references are followed,
+    /// so updating `HEAD` updates the branch it points to.
+    ///
+    /// The check and the update are done while holding the reference's lock
+    /// file (see `LockFile`), so a concurrent writer cannot slip in between.
+    /// Returns `GitError::RefUpdateRejected` with the value found if the
+    /// current value is not the expected one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::*;
+    /// use git::fs::GitFS;
+    /// use git::refs::SpecRef;
+    /// use git::protocol::{SHA1, Hash};
+    ///
+    /// let git = GitFS::new(&PathBuf::from(".git")).unwrap();
+    /// let old = SHA1::from_hex("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed").unwrap();
+    /// let new = SHA1::from_hex("da39a3ee5e6b4b0d3255bfef95601890afd80709").unwrap();
+    /// git.compare_and_swap_ref(&SpecRef::branch("master"), Some(&old), &new).unwrap();
+    /// ```
+    pub fn compare_and_swap_ref<H: Hash>(&self, r: &SpecRef, expected: Option<&H>, new: &H) -> Result<()> {
+        self.write_ref_checked(r, new, |r, current| {
+            let unchanged = match (&current, expected) {
+                (&None, None) => true,
+                (&Some(ref c), Some(e)) => same_hash(c, e),
+                _ => false
+            };
+            if unchanged {
+                Ok(())
+            } else {
+                Err(GitError::RefUpdateRejected(r.clone(), current.map(|c| c.to_hexadecimal())))
+            }
+        })
+    }
+
+    /// set the reference `r` to `new`, creating it if needed.
+    ///
+    /// If `old` is given, this is `compare_and_swap_ref`: the reference is
+    /// only updated if its current value is `old`, otherwise
+    /// `GitError::RefUpdateRejected` is returned with the value found.
+    /// Without `old` the reference is overwritten whatever its value.
+    ///
+    /// The new value is written in the reference's lock file which is then
+    /// renamed over the loose reference file, so a reader never sees a
+    /// partially written reference.
+    pub fn update_ref<H: Hash>(&self, r: SpecRef, new: &H, old: Option<&H>) -> Result<()> {
+        match old {
+            Some(_) => self.compare_and_swap_ref(&r, old, new),
+            None => self.write_ref_checked(&r, new, |_, _: Option<H>| Ok(()))
+        }
+    }
+
+    /// write `new` in the loose reference file of `r` (after following the
+    /// symbolic references) if `check` accepts the current value of the
+    /// reference. The lock is held from the read of the current value to
+    /// the write of the new one.
+    fn write_ref_checked<H, F>(&self, r: &SpecRef, new: &H, check: F) -> Result<()>
+        where H: Hash
+            , F: FnOnce(&SpecRef, Option<H>) -> Result<()>
+    {
+        let r = try!(self.resolve_symbolic_ref::<H>(r));
+        let path = self.ref_file(&r);
+        let mut lock = try!(LockFile::acquire(&path));
+        let current = if path.is_file() {
+            match try!(self.get_ref::<H>(r.clone())) {
+                Ref::Hash(h) => Some(h),
+                Ref::Link(_) => return Err(GitError::RefUpdateRejected(r, None))
+            }
+        } else {
+            try!(self.get_packed_ref::<H>(&r))
+        };
+        try!(check(&r, current));
+        try!(lock.write_all(format!("{}\n", new.to_hexadecimal()).as_bytes()));
+        lock.commit()
+    }
+}
+/// the path of the entry `name` of a tree checked out in `dir` (`dest` or
+/// one of its sub-directories): the name must be a single path component,
+/// and not `.git` (in any case, for the case insensitive file systems).
+///
+/// Nothing is written outside of `dest`: none of the directories from
+/// `dest` to `dir` may be a symbolic link, and an existing symbolic link at
+/// the entry's path is removed so it is replaced instead of being followed.
+fn checkout_path(dest: &Path, dir: &Path, name: &Path) -> Result<PathBuf> {
+    let mut components = name.components();
+    let path = match (components.next(), components.next()) {
+        (Some(Component::Normal(n)), None) if ! is_dot_git(n) => dir.join(n),
+        _ => return Err(GitError::Other(format!("invalid tree entry name: {}", name.display())))
+    };
+    let rel = match dir.strip_prefix(dest) {
+        Ok(rel) => rel,
+        Err(_) => return Err(GitError::Other(format!("{} is not in {}", dir.display(), dest.display())))
+    };
+    let mut parent = dest.to_path_buf();
+    for component in rel.components() {
+        parent.push(component);
+        if is_symlink(&parent) {
+            return Err(GitError::Other(format!("symbolic link in the checkout path: {}", parent.display())))
+        }
+    }
+    if is_symlink(&path) {
+        try!(fs::remove_file(&path));
+    }
+    Ok(path)
+}
+fn is_dot_git(name: &::std::ffi::OsStr) -> bool {
+    name.to_str().map_or(false, |n| n.eq_ignore_ascii_case(".git"))
+}
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path, executable: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = if executable { 0o755 } else { 0o644 };
+    Ok(try!(fs::set_permissions(path, fs::Permissions::from_mode(mode))))
+}
+#[cfg(not(unix))]
+fn set_executable(_: &Path, _: bool) -> Result<()> { Ok(()) }
+
+#[cfg(unix)]
+fn write_symlink(path: &Path, target: Vec<u8>) -> Result<()> {
+    use std::os::unix::ffi::OsStringExt;
+    use std::ffi::OsString;
+    if fs::symlink_metadata(path).is_ok() {
+        try!(fs::remove_file(path));
+    }
+    Ok(try!(::std::os::unix::fs::symlink(OsString::from_vec(target), path)))
+}
+#[cfg(not(unix))]
+fn write_symlink(path: &Path, target: Vec<u8>) -> Result<()> {
+    use std::io::Write;
+    Ok(try!(try!(fs::File::create(path)).write_all(&target)))
+}
+
+/// compare the expected checksum with the computed one
+fn check_checksum<E: Hash, A: Hash>(expected: &E, actual: &A) -> Result<()> {
+    if same_hash(expected, actual) {
+        Ok(())
+    } else {
+        Err(GitError::HashMismatch(expected.to_hexadecimal(), actual.to_hexadecimal()))
+    }
+}
+
+impl ObjectSource for GitFS {
+    fn read_raw<H: Hash>(&self, h: &H) -> Result<(ObjectKind, Vec<u8>)> {
+        self.read_raw_object(h)
+    }
+}
+
+impl Repo for GitFS {
+    fn is_valid(&self) -> Result<()> { self.check_repo() }
+
+    /// the content of the `description` file, empty if there is none
+    fn get_description(&self) -> Result<String> {
+        let mut file = match open_file(&self.description_file()) {
+            Ok(file) => file,
+            Err(ref err) if err.io_kind() == Some(io::ErrorKind::NotFound) => return Ok(String::new()),
+            Err(err) => return Err(err)
+        };
+        let mut s = String::new();
+        try!(file.read_to_string(&mut s));
+        Ok(s)
+    }
+
+    fn get_ref<H: Hash>(&self, r: SpecRef) -> Result<Ref<H>> {
+        let filepath = self.ref_file(&r);
+        if ! filepath.is_file() {
+            if let Some(h) = try!(self.get_packed_ref(&r)) {
+                return Ok(Ref::Hash(h))
+            }
+        }
+        let mut file = try!(open_file(&filepath));
+        let mut s = String::new();
+        try!(file.read_to_string(&mut s));
+        Ref::from_str(&s)
+    }
+
+    fn exists<H: Hash>(&self, h: &H) -> Result<bool> {
+        if try!(self.exists_local(h)) {
+            return Ok(true)
+        }
+        self.find_alternate(h).map(|alt| alt.is_some())
+    }
+    fn lookup_hash<H: Hash>(&self, prefix: &Partial<H>) -> Result<Vec<H>> {
+        let mut looses = try!(self.lookup_hash_loose(prefix));
+        for idx in list_indexes::<H>(self)?.iter() {
+            let index = try!(parse_index_file::<H>(&self.index_file(idx)));
+            looses.extend(index.contains(prefix))
+        }
+        for alt in self.alternate_stores() {
+            looses.extend(try!(alt.lookup_hash(prefix)))
+        }
+        Ok(looses)
+    }
+    fn list_branches(&self) -> Result<Vec<SpecRef>> {
+        let branches = try!(list_loose_refs( self.refs_dir().join("heads")
+                                           , &|x| Ok(Some(SpecRef::branch(x)))
+                                           ));
+        self.merge_packed_refs(branches, |r| match r { &SpecRef::Branch(_) => true, _ => false })
+    }
+    fn list_remotes(&self) -> Result<Vec<SpecRef>> {
+        let remotes = try!(list_loose_refs( self.refs_dir().join("remotes")
+                                          , &|remote_path| {
+            let mut components = remote_path.components();
+            components
+                .next()
+                .map_or(Err(GitError::InvalidRemote(remote_path.to_path_buf())), |p| {
+                    match p {
+                        Component::Normal(remote) => Ok(
+                            Some(SpecRef::remote(remote, components.as_path()))
+                        ),
+                        _ => Err(GitError::Unknown("invalid remote name".to_string()))
+                    }
+                })
+            }
+        ));
+        self.merge_packed_refs(remotes, |r| match r { &SpecRef::Remote(_, _) => true, _ => false })
+    }
+    fn list_tags(&self) -> Result<Vec<SpecRef>> {
+        let tags = try!(list_loose_refs( self.refs_dir().join("tags")
+                                       , &|x| Ok(Some(SpecRef::tag(x)))
+                                       ));
+        self.merge_packed_refs(tags, |r| match r { &SpecRef::Tag(_) => true, _ => false })
+    }
+}
+
+/// read the git directory a `.git` file points to (`gitdir: <path>`), the
+/// relative paths being relative to the directory of the `.git` file
+fn read_gitdir_file(path: &Path) -> Result<PathBuf> {
+    let mut content = String::new();
+    try!(try!(open_file(&path.to_path_buf())).read_to_string(&mut content));
+    let line = content.lines().next().unwrap_or("");
+    if ! line.starts_with("gitdir:") {
+        return Err(GitError::ParsingError(format!("invalid gitdir file: {}", path.display())))
+    }
+    let target = Path::new(line["gitdir:".len()..].trim());
+    match path.parent() {
+        Some(dir) if target.is_relative() => Ok(dir.join(target)),
+        _ => Ok(target.to_path_buf())
+    }
+}
+
+/// list the loose references of the given directory (if it exists)
+fn list_loose_refs<P>(dir: PathBuf, make_specref: & Fn(&Path) -> Result<Option<P>>)
+    -> Result<Vec<P>>
+{
+    if ! dir.is_dir() {
+        return Ok(Vec::new())
+    }
+    get_all_files_in(dir, make_specref)
+}
+
+/// reference stored in the `packed-refs` file
+///
+/// `peeled` is set for the annotated tags: it is the object the tag object
+/// points to (usually a commit).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PackedRef<H: Hash> {
+    pub name: SpecRef,
+    pub hash: H,
+    pub peeled: Option<H>
+}
+
+/// parse the content of the `packed-refs` file:
+///
+/// ```text
+/// # pack-refs with: peeled fully-peeled sorted
+/// 8a3e2c0d1d6e64b2b8c6a43e1ae8fc9d0bd5e7a3 refs/heads/master
+/// 4f2c9d0a4ab1a2b7c7c3e6d51d0e5b0d5a8a2b3c refs/tags/v1.0
+/// ^8a3e2c0d1d6e64b2b8c6a43e1ae8fc9d0bd5e7a3
+/// ```
+///
+/// A line starting with `^` is the peeled value of the annotated tag of the
+/// previous line. References this crate does not know (see `SpecRef`) are
+/// skipped.
+fn parse_packed_refs<H: Hash>(s: &str) -> Result<Vec<PackedRef<H>>> {
+    let refs = try!(parse_packed_refs_with(s, H::from_hex));
+    Ok(refs.into_iter()
+           .map(|(name, hash, peeled)| PackedRef { name: name, hash: hash, peeled: peeled })
+           .collect())
+}
+/// the names of the references of the `packed-refs` file: the hashes are
+/// only checked to be hexadecimal, so the names can be listed without
+/// knowing the type of the hashes
+fn parse_packed_ref_names(s: &str) -> Result<Vec<SpecRef>> {
+    let is_hex = |h: &str| if ! h.is_empty() && h.chars().all(|c| c.is_digit(16)) { Some(()) } else { None };
+    let refs = try!(parse_packed_refs_with(s, is_hex));
+    Ok(refs.into_iter().map(|(name, _, _)| name).collect())
+}
+/// see `parse_packed_refs`, the hashes being decoded with `decode`
+fn parse_packed_refs_with<T, F>(s: &str, decode: F) -> Result<Vec<(SpecRef, T, Option<T>)>>
+    where F: Fn(&str) -> Option<T>
+{
+    let mut refs : Vec<(SpecRef, T, Option<T>)> = Vec::new();
+    // the previous line was a reference we skipped
+    let mut skipped = false;
+    for line in s.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('^') {
+            let peeled = match decode(&line[1..]) {
+                Some(h) => h,
+                None => return Err(GitError::ParsingError(format!("invalid packed-refs line: {}", line)))
+            };
+            match refs.last_mut() {
+                Some(ref mut last) if ! skipped => last.2 = Some(peeled),
+                _ if skipped => {},
+                _ => return Err(GitError::ParsingError(format!("invalid packed-refs line: {}", line)))
+            }
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (hash, name) = match (words.next().and_then(|w| decode(w)), words.next(), words.next()) {
+            (Some(hash), Some(name), None) => (hash, name),
+            _ => return Err(GitError::ParsingError(format!("invalid packed-refs line: {}", line)))
+        };
+        match SpecRef::from_str(name) {
+            Ok(name) => {
+                skipped = false;
+                refs.push((name, hash, None))
+            },
+            Err(_) => skipped = true
+        }
+    }
+    Ok(refs)
+}
+
+/// maximum number of deltas to follow to reconstruct a packed object
+const MAX_DELTA_CHAIN : usize = 4096;
+
+/// split the raw data of an object (`<type> <size>\0<content>`) in its type
+/// and its content
+fn split_object_data(data: &[u8]) -> Result<(PackObjectType, Vec<u8>)> {
+    let (name, rest) = match data.iter().position(|c| *c == b' ') {
+        Some(idx) => (&data[..idx], &data[idx+1..]),
+        None => return Err(GitError::ParsingError("invalid object header".to_string()))
+    };
+    let content = match rest.iter().position(|c| *c == 0) {
+        Some(idx) => &rest[idx+1..],
+        None => return Err(GitError::ParsingError("invalid object header".to_string()))
+    };
+    match PackObjectType::from_name(name) {
+        Some(ty) => Ok((ty, content.to_vec())),
+        None => Err(GitError::ParsingError(
+            format!("unknown object type: {}", String::from_utf8_lossy(name))
+        ))
+    }
+}
+
+/// map the remote reference `name` through the given fetch refspec
+/// (`[+]<src>:<dst>`, with an optional `*` wildcard), returning the local
+/// reference if the refspec's source matches.
+fn map_refspec(refspec: &str, name: &str) -> Option<String> {
+    let refspec = refspec.trim_left_matches('+');
+    let (src, dst) = match refspec.find(':') {
+        Some(idx) => (&refspec[..idx], &refspec[idx+1..]),
+        None => return None
+    };
+    match (src.find('*'), dst.find('*')) {
+        (None, _) => if src == name { Some(dst.to_string()) } else { None },
+        (Some(sidx), Some(didx)) => {
+            let (prefix, suffix) = (&src[..sidx], &src[sidx+1..]);
+            if name.len() < prefix.len() + suffix.len()
+               || ! name.starts_with(prefix) || ! name.ends_with(suffix) {
+                return None
+            }
+            let matched = &name[prefix.len()..name.len() - suffix.len()];
+            Some(format!("{}{}{}", &dst[..didx], matched, &dst[didx+1..]))
+        },
+        (Some(_), None) => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::protocol::*;
+    use ::error::*;
+    use ::refs::*;
+    use ::object::*;
+    use std::path::*;
+    use std::io::Write;
+    use either::Either;
+
+    fn get_test_commit() -> Ref<CommitRef<SHA1>> {
+        Ref::Link(SpecRef::branch("master"))
+    }
+
+    fn get_root_test() -> PathBuf {
+        PathBuf::new().join(".").join("test_ref").join(".git")
+    }
+
+    #[test]
+    fn lookup() {
+        let path = PathBuf::new().join(".").join(".git");
+        let git = GitFS::new(&path).unwrap();
+        let r = git.lookup_hash(
+            &Partial::<SHA1>::from_hex("f34").unwrap()
+        ).unwrap();
+        for h in r.iter() {
+            let h_str = format!("{}", h);
+            assert!(h_str.starts_with("f34"));
+        }
+    }
+    #[test]
+    fn new() {
+        let path = get_root_test();
+        assert_eq!( GitFS::new(&path)
+                  , Ok(GitFS { path: path.clone(), verify: false, objects: None, alternates: Vec::new(), cache: ObjectCache::new(0), work_tree: None })
+                  )
+    }
+    #[test]
+    fn new_fail() {
+        let path = PathBuf::new().join(".").join("src");
+        let missing = PathBuf::new().join(".").join("src").join("refs");
+        assert_eq!(GitFS::new(&path), Err(GitError::MissingDirectory(missing)))
+    }
+
+    #[test]
+    fn git_fs_get_description() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let desc = git.get_description();
+        assert!(desc.is_ok())
+    }
+    #[test]
+    fn git_fs_without_description() {
+        let path = ::fs::util::test_git_dir("without_description");
+        assert_eq!(GitFS::new(&path).unwrap().get_description(), Ok("test repository\n".to_string()));
+        fs::remove_file(path.join("description")).unwrap();
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!(git.get_description(), Ok(String::new()));
+        // a description which cannot be read is not an empty one
+        fs::create_dir(path.join("description")).unwrap();
+        assert!(git.get_description().is_err());
+    }
+
+    #[test]
+    fn git_fs_get_ref() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let r = SpecRef::branch("master");
+        let result : Result<Ref<SHA1>> = git.get_ref(r);
+        assert!(result.is_ok())
+    }
+    #[test]
+    fn git_fs_get_ref_follow_link() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let result : Result<SHA1> = git.get_ref_follow_links(SpecRef::Head);
+        assert!(result.is_ok())
+    }
+    #[test]
+    fn git_fs_get_head() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let head : Result<Ref<SHA1>> = git.get_head();
+        assert_eq!( head
+                  , Ok(Ref::Link(SpecRef::branch("master")))
+                  )
+    }
+    #[test]
+    fn git_fs_get_branches() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let branches = git.list_branches().expect("expect to list the branches");
+        assert!(branches.contains(&SpecRef::branch("master")));
+    }
+    #[cfg(unix)]
+    #[test]
+    fn git_fs_get_branches_broken_ref() {
+        use std::os::unix::fs::symlink;
+        let path = ::fs::util::test_git_dir("get_branches_broken_ref");
+        let git = GitFS::new(&path).unwrap();
+        let c = test_commit(&git, vec![], "master\n");
+        git.update_ref(SpecRef::branch("master"), &c, None).unwrap();
+        assert_eq!(git.list_branches(), Ok(vec![SpecRef::branch("master")]));
+        // a ref which cannot be read is an error, not a missing branch
+        symlink(path.join("nowhere"), path.join("refs").join("heads").join("broken")).unwrap();
+        assert_eq!(git.list_branches().unwrap_err().io_kind(), Some(io::ErrorKind::NotFound));
+    }
+    #[test]
+    fn git_fs_get_remotes() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let branches = git.list_remotes().expect("expect to list the remotes");
+        println!("{:?}", branches);
+        assert!( branches.contains(&SpecRef::remote("origin", "HEAD"))
+                 || branches.contains(&SpecRef::remote("origin", "master")));
+    }
+    #[test]
+    fn git_fs_for_each_ref() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let mut refs = Vec::new();
+        git.for_each_ref(|r, h: Result<SHA1>| { refs.push((r, try!(h))); Ok(()) }).unwrap();
+        let master : SHA1 = git.get_ref_follow_links(SpecRef::branch("master")).unwrap();
+        assert!(refs.contains(&(SpecRef::branch("master"), master)));
+        assert!(refs.iter().any(|&(ref r, _)| r == &SpecRef::tag("v0.1")));
+        // the errors of the callback stop the iteration
+        let mut calls = 0;
+        let res = git.for_each_ref(|_, _: Result<SHA1>| { calls += 1; Err(GitError::Other("stop".to_string())) });
+        assert_eq!(res, Err(GitError::Other("stop".to_string())));
+        assert_eq!(calls, 1);
+    }
+    #[test]
+    fn git_fs_for_each_ref_broken() {
+        let path = ::fs::util::test_git_dir("for_each_ref_broken");
+        let git = GitFS::new(&path).unwrap();
+        let c = test_commit(&git, vec![], "c\n");
+        git.update_ref(SpecRef::branch("a"), &c, None).unwrap();
+        git.update_ref(SpecRef::branch("c"), &c, None).unwrap();
+        fs::File::create(path.join("refs").join("heads").join("b")).unwrap().write_all(b"garbage\n").unwrap();
+
+        // the broken reference is given with its error, between the others
+        let mut refs = Vec::new();
+        git.for_each_ref(|r, h: Result<SHA1>| { refs.push((r, h.is_ok())); Ok(()) }).unwrap();
+        refs.sort();
+        assert_eq!(refs, vec![ (SpecRef::branch("a"), true)
+                             , (SpecRef::branch("b"), false)
+                             , (SpecRef::branch("c"), true)
+                             ]);
+    }
+    #[test]
+    fn git_fs_get_commit() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        let commit = git.get_object_ref(get_test_commit()).unwrap();
+        println!("{}", commit)
+    }
+    #[test]
+    fn git_fs_object_header() {
+        // the objects of test_ref are packed
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        let (kind, size) = git.object_header(&head).unwrap();
+        assert_eq!(kind, ObjectKind::Commit);
+        assert_eq!(size, git.read_object_data(&head).unwrap().len() - format!("commit {}\0", size).len());
+        let tag : SHA1 = git.get_ref_follow_links(SpecRef::tag("v0.1")).unwrap();
+        assert_eq!(git.object_header(&tag).unwrap().0, ObjectKind::Tag);
+
+        let path = ::fs::util::test_git_dir("object_header");
+        let git = GitFS::new(&path).unwrap();
+        let blob = git.write_object::<SHA1, _>(&Blob::new(b"hello\n".to_vec())).unwrap();
+        assert_eq!(git.object_header(&blob), Ok((ObjectKind::Blob, 6)));
+        assert!(git.object_header(&sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709")).is_err());
+    }
+    #[test]
+    fn git_fs_read_raw_object() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let numbers = sha1("1e404f5c7c1752a1332020d2625c2ba2a20982ce");
+        let (kind, body) = git.read_raw_object(&numbers).unwrap();
+        assert_eq!(kind, ObjectKind::Blob);
+        assert_eq!(git.object_header(&numbers), Ok((ObjectKind::Blob, body.len())));
+
+        let path = ::fs::util::test_git_dir("read_raw_object");
+        let git = GitFS::new(&path).unwrap();
+        let blob = git.write_object::<SHA1, _>(&Blob::new(b"hello\n".to_vec())).unwrap();
+        assert_eq!(git.read_raw_object(&blob), Ok((ObjectKind::Blob, b"hello\n".to_vec())));
+    }
+    #[test]
+    fn git_fs_list_all_objects() {
+        // see test_ref/init.sh: 3 commits, 3 trees, 3 blobs and 1 tag, packed
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let packed : Vec<SHA1> = git.list_all_objects().unwrap();
+        assert_eq!(packed.len(), 10);
+        let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        let tag : SHA1 = git.get_ref_follow_links(SpecRef::tag("v0.1")).unwrap();
+        assert!(packed.contains(&head));
+        assert!(packed.contains(&tag));
+
+        // the same objects, packed, plus a loose copy of HEAD and a new blob
+        let path = ::fs::util::test_git_dir("list_all_objects");
+        let pack_dir = path.join("objects").join("pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        for entry in fs::read_dir(get_root_test().join("objects").join("pack")).unwrap() {
+            let entry = entry.unwrap().path();
+            fs::copy(&entry, pack_dir.join(entry.file_name().unwrap())).unwrap();
+        }
+        let copy = GitFS::new(&path).unwrap();
+        let commit : Commit<SHA1> = git.get_object(CommitRef::new(head.clone())).unwrap();
+        assert_eq!(copy.write_object::<SHA1, _>(&commit), Ok(head.clone()));
+        let blob = copy.write_object::<SHA1, _>(&Blob::new(b"loose\n".to_vec())).unwrap();
+        let all : Vec<SHA1> = copy.list_all_objects().unwrap();
+        assert_eq!(all.len(), 10 + 2 - 1);
+        assert!(all.contains(&head));
+        assert!(all.contains(&blob));
+        assert!(packed.iter().all(|h| all.contains(h)));
+    }
+    #[test]
+    fn git_fs_with_object_dir() {
+        let path = ::fs::util::test_git_dir("object_dir");
+        let store = ::fs::util::test_git_dir("object_dir_store");
+        let loose = GitFS::new(&store).unwrap().write_object::<SHA1, _>(&Blob::new(b"stored\n".to_vec())).unwrap();
+        let objects = store.join("objects");
+        let pack_dir = objects.join("pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        for entry in fs::read_dir(get_root_test().join("objects").join("pack")).unwrap() {
+            let entry = entry.unwrap().path();
+            fs::copy(&entry, pack_dir.join(entry.file_name().unwrap())).unwrap();
+        }
+
+        let git = GitFS::new(&path).unwrap().with_object_dir(objects.clone()).unwrap();
+        assert_eq!(git.objs_dir(), objects);
+        assert_eq!(git.refs_dir(), path.join("refs"));
+        assert_eq!(git.get_blob(BlobRef::new(loose)), Ok(Blob::new(b"stored\n".to_vec())));
+        // packed in the store
+        let readme = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        assert_eq!(list_indexes::<SHA1>(&git).unwrap().len(), 1);
+        assert_eq!(git.get_blob(BlobRef::new(readme)), Ok(Blob::new(b"README\n".to_vec())));
+        // written in the store
+        let written = git.write_object::<SHA1, _>(&Blob::new(b"written\n".to_vec())).unwrap();
+        assert!(git.object_file(&written).starts_with(&objects));
+        assert!(git.object_file(&written).is_file());
+        assert_eq!(GitFS::new(&path).unwrap().exists(&written), Ok(false));
+
+        // the alternates file of the store is not ignored if it cannot be read
+        fs::create_dir_all(objects.join("info")).unwrap();
+        fs::File::create(objects.join("info").join("alternates")).unwrap().write_all(b"\xff\n").unwrap();
+        assert!(GitFS::new(&path).unwrap().with_object_dir(objects).is_err());
+    }
+    #[test]
+    fn git_fs_alternates() {
+        let shared = ::fs::util::test_git_dir("alternates_shared");
+        let blob = GitFS::new(&shared).unwrap().write_object::<SHA1, _>(&Blob::new(b"shared\n".to_vec())).unwrap();
+
+        let path = ::fs::util::test_git_dir("alternates");
+        fs::create_dir_all(path.join("objects").join("info")).unwrap();
+        fs::File::create(path.join("objects").join("info").join("alternates")).unwrap()
+            .write_all(format!("# shared objects\n{}\n", shared.join("objects").display()).as_bytes())
+            .unwrap();
+        let git = GitFS::new(&path).unwrap();
+        assert!(! git.object_file(&blob).is_file());
+        assert_eq!(git.exists(&blob), Ok(true));
+        assert_eq!(git.get_blob(BlobRef::new(blob.clone())), Ok(Blob::new(b"shared\n".to_vec())));
+        assert_eq!(git.object_header(&blob), Ok((ObjectKind::Blob, 7)));
+        let prefix = Partial::<SHA1>::from_hex(&blob.to_hexadecimal()[..8]).unwrap();
+        assert_eq!(git.resolve_prefix(&prefix), Ok(blob.clone()));
+        assert_eq!(git.exists(&sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709")), Ok(false));
+        // the shared objects are not copied in the repository
+        assert_eq!(git.write_object::<SHA1, _>(&Blob::new(b"shared\n".to_vec())), Ok(blob.clone()));
+        assert!(! git.object_file(&blob).is_file());
+    }
+    #[test]
+    fn git_fs_get_tag() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : CommitRef<SHA1> = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        // see test_ref/init.sh: `v0.1` is annotated, `v0.0.1` is lightweight
+        match git.get_tag::<SHA1>(SpecRef::tag("v0.1")).unwrap() {
+            Either::Left(tag) => {
+                assert_eq!(tag.name, "v0.1");
+                assert_eq!(tag.object_type, "commit");
+                assert_eq!(tag.message, "first release\n");
+                assert_eq!(tag.object.as_bytes(), head.as_bytes());
+            },
+            Either::Right(commit) => panic!("expected an annotated tag, got {}", commit)
+        }
+        match git.get_tag::<SHA1>(SpecRef::tag("v0.0.1")).unwrap() {
+            Either::Left(tag) => panic!("expected a lightweight tag, got {}", tag),
+            Either::Right(commit) => assert_eq!(commit.message, "\nadd one more number\n")
+        }
+        assert!(git.get_tag::<SHA1>(SpecRef::tag("missing")).is_err());
+    }
+    fn sha1(hex: &str) -> SHA1 { SHA1::from_hex(hex).unwrap() }
+    /// store `raw` (`<type> <size>\0<content>`, as is) in the loose object
+    /// file of `hash`: the content does not have to match the hash
+    fn write_raw_loose(git_dir: &Path, hash: &SHA1, raw: &[u8]) {
+        use protocol::flate2::Compression;
+        use protocol::flate2::write::ZlibEncoder;
+        let hex = hash.to_hexadecimal();
+        let dir = git_dir.join("objects").join(&hex[..2]);
+        fs::create_dir_all(&dir).unwrap();
+        let file = fs::File::create(dir.join(&hex[2..])).unwrap();
+        let mut encoder = ZlibEncoder::new(file, Compression::Default);
+        encoder.write_all(raw).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn git_fs_compare_and_swap_ref() {
+        let path = ::fs::util::test_git_dir("compare_and_swap_ref");
+        let git = GitFS::new(&path).unwrap();
+        let old = sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+        let new = sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        let master = SpecRef::branch("master");
+
+        // the branch is unborn: only `None` is accepted
+        assert_eq!( git.compare_and_swap_ref(&master, Some(&new), &old)
+                  , Err(GitError::RefUpdateRejected(master.clone(), None))
+                  );
+        git.compare_and_swap_ref(&master, None, &old).unwrap();
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(old.clone())));
+
+        // the branch exists now
+        assert_eq!( git.compare_and_swap_ref(&master, None, &new)
+                  , Err(GitError::RefUpdateRejected(master.clone(), Some(old.to_hexadecimal())))
+                  );
+        assert_eq!( git.compare_and_swap_ref(&master, Some(&new), &new)
+                  , Err(GitError::RefUpdateRejected(master.clone(), Some(old.to_hexadecimal())))
+                  );
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(old.clone())));
+
+        // HEAD is followed up to the branch
+        git.compare_and_swap_ref(&SpecRef::Head, Some(&old), &new).unwrap();
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(new.clone())));
+        assert_eq!(git.get_head(), Ok(Ref::<SHA1>::Link(master.clone())));
+    }
+    #[test]
+    fn git_fs_update_ref() {
+        let path = ::fs::util::test_git_dir("update_ref");
+        let git = GitFS::new(&path).unwrap();
+        let old = sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+        let new = sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        let master = SpecRef::branch("master");
+
+        // create a brand new branch
+        git.update_ref(master.clone(), &old, None).unwrap();
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(old.clone())));
+        let mut content = String::new();
+        fs::File::open(git.ref_file(&master)).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed\n");
+
+        // successful compare and swap
+        git.update_ref(master.clone(), &new, Some(&old)).unwrap();
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(new.clone())));
+
+        // the current value is not the expected one
+        assert_eq!( git.update_ref(master.clone(), &old, Some(&old))
+                  , Err(GitError::RefUpdateRejected(master.clone(), Some(new.to_hexadecimal())))
+                  );
+        assert_eq!( git.update_ref(SpecRef::branch("dev"), &old, Some(&new))
+                  , Err(GitError::RefUpdateRejected(SpecRef::branch("dev"), None))
+                  );
+        assert_eq!(git.get_ref(master.clone()), Ok(Ref::Hash(new.clone())));
+        assert!(! git.ref_file(&SpecRef::branch("dev")).exists());
+
+        // without `old` the reference is overwritten
+        git.update_ref(master.clone(), &old, None).unwrap();
+        assert_eq!(git.get_ref(master), Ok(Ref::Hash(old)));
+    }
+    #[test]
+    fn git_fs_compare_and_swap_ref_locked() {
+        let path = ::fs::util::test_git_dir("compare_and_swap_ref_locked");
+        let git = GitFS::new(&path).unwrap();
+        let new = sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        let master = SpecRef::branch("master");
+
+        let lock = ::fs::util::LockFile::acquire(git.ref_file(&master)).unwrap();
+        assert!(git.compare_and_swap_ref(&master, None, &new).is_err());
+        drop(lock);
+        git.compare_and_swap_ref(&master, None, &new).unwrap();
+        assert_eq!(git.get_ref(master), Ok(Ref::Hash(new)));
+    }
+    /// write a commit (with an empty tree) in the given test repository
+    fn test_commit(git: &GitFS, parents: Vec<CommitRef<SHA1>>, message: &str) -> CommitRef<SHA1> {
+        test_commit_at(git, parents, 1480007832, &[], message)
+    }
+    /// as `test_commit`, with the given files (path and content) in the tree
+    fn test_commit_with(git: &GitFS, parents: Vec<CommitRef<SHA1>>, files: &[(&str, &[u8])], message: &str) -> CommitRef<SHA1> {
+        test_commit_at(git, parents, 1480007832, files, message)
+    }
+    /// as `test_commit_with`, committed at the given date (seconds since epoch)
+    fn test_commit_at(git: &GitFS, parents: Vec<CommitRef<SHA1>>, date: i64, files: &[(&str, &[u8])], message: &str) -> CommitRef<SHA1> {
+        let mut builder = TreeBuilder::<SHA1>::new();
+        for &(name, data) in files {
+            let blob = BlobRef::new(git.write_object::<SHA1, _>(&Blob::new(data.to_vec())).unwrap());
+            builder.insert(name, blob, Permissions::default_file()).unwrap();
+        }
+        let mut root = None;
+        for (tr, tree) in builder.build().unwrap() {
+            git.write_object::<SHA1, _>(&tree).unwrap();
+            root = Some(tr);
+        }
+        let tree = root.unwrap();
+        let person = Person::new( "Test".to_string()
+                                , "git-test@example.com".to_string()
+                                , Date::seconds_since_epoch(date)
+                                );
+        let commit = parents.into_iter().fold(
+            Commit::new(tree, person.clone(), person, message),
+            |commit, p| commit.with_parent(p)
+        );
+        CommitRef::new(git.write_object(&commit).unwrap())
+    }
+
+    #[test]
+    fn git_fs_ahead_behind() {
+        let path = ::fs::util::test_git_dir("ahead_behind");
+        let git = GitFS::new(&path).unwrap();
+        //    a - b - c      (local)
+        //     \       \
+        //      d ----- m    (merge)
+        //     (upstream)
+        let a = test_commit(&git, vec![], "a\n");
+        let b = test_commit(&git, vec![a.clone()], "b\n");
+        let c = test_commit(&git, vec![b.clone()], "c\n");
+        let d = test_commit(&git, vec![a.clone()], "d\n");
+        let m = test_commit(&git, vec![c.clone(), d.clone()], "m\n");
+
+        assert_eq!(git.ahead_behind(c.clone(), c.clone()), Ok((0, 0)));
+        assert_eq!(git.ahead_behind(c.clone(), d.clone()), Ok((2, 1)));
+        assert_eq!(git.ahead_behind(d.clone(), c.clone()), Ok((1, 2)));
+        assert_eq!(git.ahead_behind(m.clone(), d.clone()), Ok((3, 0)));
+        assert_eq!(git.ahead_behind(a.clone(), m.clone()), Ok((0, 4)));
+
+        // the history below the merge base is not read: the parent of `x`
+        // is missing
+        let missing = CommitRef::new(sha1("e845566c06f9bf557d35e8292c37cf05d97a9769"));
+        let x = test_commit_at(&git, vec![missing], 1480007900, &[], "x\n");
+        let y = test_commit_at(&git, vec![x.clone()], 1480007901, &[], "y\n");
+        let z = test_commit_at(&git, vec![y.clone()], 1480007902, &[], "z\n");
+        assert_eq!(git.ahead_behind(z.clone(), y.clone()), Ok((1, 0)));
+        assert_eq!(git.ahead_behind(y, z), Ok((0, 1)));
+    }
+    #[test]
+    fn git_fs_discover() {
+        let path = ::fs::util::test_git_dir("discover");
+        let work_dir = path.parent().unwrap();
+        let nested = work_dir.join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        let git = GitFS::discover(&nested).unwrap();
+        assert_eq!(git.path, fs::canonicalize(&path).unwrap());
+        assert_eq!(git.is_bare(), Ok(false));
+        assert_eq!(git.work_dir(), Ok(Some(fs::canonicalize(work_dir).unwrap())));
+        // from inside the git directory
+        assert_eq!(GitFS::discover(&path.join("refs").join("heads")).unwrap().path, git.path);
+
+        let outside = ::std::env::temp_dir().join("git-rs-tests").join("discover_outside");
+        fs::create_dir_all(&outside).unwrap();
+        assert!(GitFS::discover(&outside).is_err());
+    }
+    #[test]
+    fn git_fs_gitdir_file() {
+        let path = ::fs::util::test_git_dir("gitdir_file");
+        let root = path.parent().unwrap();
+        let module = root.join("module");
+        let _ = fs::remove_dir_all(&module);
+        fs::create_dir_all(module.join("src")).unwrap();
+        // relative to the directory of the `.git` file
+        fs::File::create(module.join(".git")).unwrap()
+            .write_all(b"gitdir: ../.git\n").unwrap();
+        let git = GitFS::new(&module.join(".git")).unwrap();
+        assert_eq!(git.get_head::<SHA1>(), Ok(Ref::Link(SpecRef::branch("master"))));
+        // the working directory is the one of the `.git` file
+        assert_eq!(git.is_bare(), Ok(false));
+        assert_eq!(git.work_dir(), Ok(Some(module.clone())));
+        let git = GitFS::discover(&module.join("src")).unwrap();
+        assert_eq!(git.get_head::<SHA1>(), Ok(Ref::Link(SpecRef::branch("master"))));
+        // absolute
+        fs::File::create(module.join(".git")).unwrap()
+            .write_all(format!("gitdir: {}\n", fs::canonicalize(&path).unwrap().display()).as_bytes()).unwrap();
+        let git = GitFS::new(&module.join(".git")).unwrap();
+        assert_eq!(git.get_head::<SHA1>(), Ok(Ref::Link(SpecRef::branch("master"))));
+        assert_eq!(git.is_bare(), Ok(false));
+        assert_eq!(git.work_dir(), Ok(Some(module.clone())));
+
+        fs::File::create(module.join(".git")).unwrap().write_all(b"not a gitdir\n").unwrap();
+        assert!(GitFS::new(&module.join(".git")).is_err());
+    }
+    #[test]
+    fn git_fs_is_bare() {
+        let path = ::fs::util::test_git_dir("is_bare");
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!(git.is_bare(), Ok(false));
+        fs::File::create(path.join("config")).unwrap()
+            .write_all(b"[core]\n\trepositoryformatversion = 0\n\tbare = true\n").unwrap();
+        assert_eq!(git.is_bare(), Ok(true));
+        assert_eq!(git.work_dir(), Ok(None));
+    }
+    #[test]
+    fn git_fs_get_note() {
+        let path = ::fs::util::test_git_dir("get_note");
+        let git = GitFS::new(&path).unwrap();
+        let a = test_commit(&git, vec![], "a\n");
+        let b = test_commit(&git, vec![a.clone()], "b\n");
+        let c = test_commit(&git, vec![b.clone()], "c\n");
+        // a's note at the top of the tree, b's note in a fan-out directory
+        let (a_hex, b_hex) = (a.to_hexadecimal(), b.to_hexadecimal());
+        let b_path = format!("{}/{}", &b_hex[..2], &b_hex[2..]);
+        let notes = test_commit_with( &git, vec![]
+                                    , &[(&a_hex, b"first commit\n"), (&b_path, b"reviewed\n")]
+                                    , "Notes added by 'git notes add'\n"
+                                    );
+        fs::create_dir_all(path.join("refs").join("notes")).unwrap();
+        fs::File::create(path.join("refs").join("notes").join("commits")).unwrap()
+            .write_all(format!("{}\n", notes.to_hexadecimal()).as_bytes()).unwrap();
+
+        let commits = SpecRef::notes("commits");
+        assert_eq!(git.get_note(commits.clone(), &a), Ok(Some(Blob::new(b"first commit\n".to_vec()))));
+        assert_eq!(git.get_note(commits.clone(), &b), Ok(Some(Blob::new(b"reviewed\n".to_vec()))));
+        assert_eq!(git.get_note(commits.clone(), &c), Ok(None));
+        assert!(git.get_note(SpecRef::notes("review"), &a).is_err());
+    }
+    #[test]
+    fn git_fs_get_commit_tree() {
+        let path = ::fs::util::test_git_dir("get_commit_tree");
+        let git = GitFS::new(&path).unwrap();
+        let c = test_commit(&git, vec![], "empty tree\n");
+        assert_eq!(git.get_commit_tree(c.clone()), Ok(Tree::new()));
+        // a commit whose tree is a blob
+        let person = Person::new( "Test".to_string()
+                                , "git-test@example.com".to_string()
+                                , Date::seconds_since_epoch(1480007832)
+                                );
+        let blob = git.write_object::<SHA1, _>(&Blob::new(b"not a tree\n".to_vec())).unwrap();
+        let commit = Commit::new(TreeRef::new(blob.clone()), person.clone(), person, "broken\n");
+        let c = CommitRef::new(git.write_object::<SHA1, _>(&commit).unwrap());
+        let err = git.get_commit_tree(c.clone()).unwrap_err();
+        match err {
+            GitError::Context { ref while_doing, ref source } => {
+                assert_eq!(while_doing, &format!("reading tree {} of commit {}", blob.to_hexadecimal(), c.to_hexadecimal()));
+                assert!(match **source { GitError::ParsingError(_) => true, _ => false });
+            },
+            ref err => panic!("expected a context, got {:?}", err)
+        }
+        assert!(format!("{}", err).contains(&c.to_hexadecimal()));
+    }
+    #[test]
+    fn git_fs_merge_base() {
+        let path = ::fs::util::test_git_dir("merge_base");
+        let git = GitFS::new(&path).unwrap();
+        //    a - b - c - e
+        //         \   \
+        //          d - m
+        let a = test_commit(&git, vec![], "a\n");
+        let b = test_commit(&git, vec![a.clone()], "b\n");
+        let c = test_commit(&git, vec![b.clone()], "c\n");
+        let d = test_commit(&git, vec![b.clone()], "d\n");
+        let e = test_commit(&git, vec![c.clone()], "e\n");
+        let m = test_commit(&git, vec![d.clone(), c.clone()], "m\n");
+        let other = test_commit(&git, vec![], "unrelated\n");
+
+        assert_eq!(git.merge_base(c.clone(), d.clone()), Ok(Some(b.clone())));
+        assert_eq!(git.merge_base(d.clone(), e.clone()), Ok(Some(b.clone())));
+        assert_eq!(git.merge_base(e.clone(), m.clone()), Ok(Some(c.clone())));
+        // one is the ancestor of the other
+        assert_eq!(git.merge_base(a.clone(), e.clone()), Ok(Some(a.clone())));
+        assert_eq!(git.merge_base(m.clone(), d.clone()), Ok(Some(d.clone())));
+        assert_eq!(git.merge_base(e.clone(), e.clone()), Ok(Some(e.clone())));
+        assert_eq!(git.merge_base(other, m), Ok(None));
+    }
+    #[test]
+    fn git_fs_merge_base_shortcut() {
+        let path = ::fs::util::test_git_dir("merge_base_shortcut");
+        let git = GitFS::new(&path).unwrap();
+        //    x - y ------ a
+        //     \   \
+        //      \   q - p
+        //       \       \
+        //        ------- b
+        // `x` is met first from `b` but `y` is a better common ancestor
+        let x = test_commit(&git, vec![], "x\n");
+        let y = test_commit(&git, vec![x.clone()], "y\n");
+        let a = test_commit(&git, vec![y.clone()], "a\n");
+        let q = test_commit(&git, vec![y.clone()], "q\n");
+        let p = test_commit(&git, vec![q], "p\n");
+        let b = test_commit(&git, vec![p, x], "b\n");
+        assert_eq!(git.merge_base(a.clone(), b.clone()), Ok(Some(y.clone())));
+        assert_eq!(git.merge_base(b, a), Ok(Some(y)));
+    }
+    #[test]
+    fn git_fs_last_commit_for_path() {
+        let path = ::fs::util::test_git_dir("last_commit_for_path");
+        let git = GitFS::new(&path).unwrap();
+        // a: README.md
+        // b: README.md, src/main.rs (introduced)
+        // c: README.md (modified), src/main.rs
+        // d: same tree as c
+        let a = test_commit_with(&git, vec![], &[("README.md", b"README\n")], "a\n");
+        let b = test_commit_with(&git, vec![a.clone()], &[("README.md", b"README\n"), ("src/main.rs", b"fn main() {}\n")], "b\n");
+        let c = test_commit_with(&git, vec![b.clone()], &[("README.md", b"README!\n"), ("src/main.rs", b"fn main() {}\n")], "c\n");
+        let d = test_commit_with(&git, vec![c.clone()], &[("README.md", b"README!\n"), ("src/main.rs", b"fn main() {}\n")], "d\n");
+
+        let last = |p: &str| git.last_commit_for_path(d.clone(), Path::new(p)).unwrap();
+        assert_eq!(last("src/main.rs"), Some(b.clone()));
+        assert_eq!(last("src"), Some(b.clone()));
+        assert_eq!(last("README.md"), Some(c.clone()));
+        assert_eq!(last("LICENSE"), None);
+        assert_eq!(git.last_commit_for_path(a.clone(), Path::new("README.md")), Ok(Some(a)));
+    }
+    #[test]
+    fn git_fs_head_state() {
+        let path = ::fs::util::test_git_dir("head_state");
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!( git.head_state::<SHA1>()
+                  , Ok(HeadState::OnBranch(SpecRef::branch("master")))
+                  );
+
+        let commit = test_commit(&git, vec![], "detached\n");
+        {
+            let mut file = fs::File::create(path.join("HEAD")).unwrap();
+            file.write_all(format!("{}\n", commit.to_hexadecimal()).as_bytes()).unwrap();
+        }
+        let state = git.head_state::<CommitRef<SHA1>>().unwrap();
+        assert!(state.is_detached());
+        assert_eq!(state, HeadState::Detached(commit.clone()));
+        let head : Commit<SHA1> = git.get_object_ref(git.get_head().unwrap()).unwrap();
+        assert_eq!(head.message, "\ndetached\n");
+    }
+    #[test]
+    fn git_fs_walk_commits() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : CommitRef<SHA1> = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        // see test_ref/init.sh
+        let messages : Vec<String> = git.walk_commits(head)
+            .map(|c| c.unwrap().message)
+            .collect();
+        assert_eq!(messages, vec![ "\nadd one more number\n".to_string()
+                                 , "\nadd numbers\n".to_string()
+                                 , "\ninitial commit\n".to_string()
+                                 ]);
+    }
+    #[test]
+    fn git_fs_export_commits() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : CommitRef<SHA1> = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        let commit = git.get_commit(head.clone()).unwrap();
+        let mut out = Vec::new();
+        git.export_commits(head.clone(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(&format!("commit {}\ntree {}\n", head.to_hexadecimal(), commit.tree().to_hexadecimal())));
+        assert!(out.contains("\n\nadd one more number\n\ncommit "));
+        assert!(out.ends_with("\n\ninitial commit\n\n"));
+        assert_eq!(out.lines().filter(|l| l.starts_with("commit ")).count(), 3);
+    }
+    #[test]
+    fn git_fs_resolve() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        for rev in ["HEAD", "@", "master", "refs/heads/master", "v0.0.1", &head.to_hexadecimal()].iter() {
+            let h : SHA1 = git.resolve(rev).unwrap();
+            assert!(same_hash(&h, &head), "{}", rev);
+        }
+        let readme : SHA1 = git.resolve("e845566").unwrap();
+        assert_eq!(readme.to_hexadecimal(), "e845566c06f9bf557d35e8292c37cf05d97a9769");
+        assert!(git.resolve::<SHA1>("no-such-branch").is_err());
+        assert!(git.resolve::<SHA1>("../config").is_err());
+        assert!(git.resolve::<SHA1>("").is_err());
+    }
+    #[test]
+    fn git_fs_walk_commits_merge() {
+        let path = ::fs::util::test_git_dir("walk_commits_merge");
+        let git = GitFS::new(&path).unwrap();
+        let a = test_commit(&git, vec![], "a\n");
+        let b = test_commit(&git, vec![a.clone()], "b\n");
+        let c = test_commit(&git, vec![a.clone()], "c\n");
+        let m = test_commit(&git, vec![b.clone(), c.clone()], "m\n");
+
+        let all : Vec<String> = git.walk_commits(m.clone()).map(|c| c.unwrap().message).collect();
+        assert_eq!(all, vec!["\nm\n", "\nb\n", "\nc\n", "\na\n"]);
+        let first : Vec<String> = git.walk_commits(m.clone()).first_parent().map(|c| c.unwrap().message).collect();
+        assert_eq!(first, vec!["\nm\n", "\nb\n", "\na\n"]);
+
+        let mut walker = git.walk_commits(m.clone());
+        let mut ids = Vec::new();
+        while let Some(next) = walker.next_with_id() {
+            ids.push(next.unwrap().0);
+        }
+        assert_eq!(ids, vec![m, b, c, a]);
+
+        let missing = CommitRef::new(sha1("e845566c06f9bf557d35e8292c37cf05d97a9769"));
+        let mut walker = git.walk_commits(missing);
+        assert!(walker.next().unwrap().is_err());
+        assert!(walker.next().is_none());
+    }
+    #[test]
+    fn git_fs_walk_commits_date_order() {
+        let path = ::fs::util::test_git_dir("walk_commits_date_order");
+        let git = GitFS::new(&path).unwrap();
+        let commit = |parents: Vec<&CommitRef<SHA1>>, date: i64, message: &str| {
+            test_commit_at(&git, parents.into_iter().cloned().collect(), 1480007832 + date, &[], message)
+        };
+        //    a - b ------ m
+        //     \          /
+        //      c1 - c2 -
+        // breadth first, `a` would come before `c1`
+        let a = commit(vec![], 1, "a\n");
+        let b = commit(vec![&a], 2, "b\n");
+        let c1 = commit(vec![&a], 3, "c1\n");
+        let c2 = commit(vec![&c1], 4, "c2\n");
+        let m = commit(vec![&b, &c2], 5, "m\n");
+        let all : Vec<String> = git.walk_commits(m).map(|c| c.unwrap().message).collect();
+        assert_eq!(all, vec!["\nm\n", "\nc2\n", "\nc1\n", "\nb\n", "\na\n"]);
+
+        // the commit is yielded before the error reading its parent
+        let missing = CommitRef::new(sha1("e845566c06f9bf557d35e8292c37cf05d97a9769"));
+        let orphan = commit(vec![&missing], 6, "orphan\n");
+        let mut walker = git.walk_commits(orphan);
+        assert_eq!(walker.next().unwrap().unwrap().message, "\norphan\n");
+        assert!(walker.next().unwrap().is_err());
+        assert!(walker.next().is_none());
+    }
+    #[test]
+    fn git_fs_resolve_specref_upstream() {
+        use std::io::Write;
+        let path = ::fs::util::test_git_dir("resolve_specref_upstream");
+        ::std::fs::OpenOptions::new().append(true).open(path.join("config"))
+            .and_then(|mut f| f.write_all(b"[branch \"master\"]\n\
+                                              \tremote = origin\n\
+                                              \tmerge = refs/heads/master\n"))
+            .unwrap();
+        let git = GitFS::new(&path).unwrap();
+        let upstream = SpecRef::remote("origin", "master");
+        assert_eq!(git.resolve_specref("@"), Ok(SpecRef::Head));
+        assert_eq!(git.resolve_specref("master"), Err(GitError::InvalidRef(PathBuf::from("master"))));
+        assert_eq!(git.resolve_specref("refs/heads/dev"), Ok(SpecRef::branch("dev")));
+        assert_eq!(git.resolve_specref("@{upstream}"), Ok(upstream.clone()));
+        assert_eq!(git.resolve_specref("@{u}"), Ok(upstream.clone()));
+        assert_eq!(git.resolve_specref("HEAD@{U}"), Ok(upstream.clone()));
+        assert_eq!(git.resolve_specref("master@{u}"), Ok(upstream.clone()));
+        assert_eq!(git.resolve_specref("refs/heads/master@{upstream}"), Ok(upstream.clone()));
+        assert_eq!( git.resolve_specref("dev@{u}")
+                  , Err(GitError::InvalidBranch(PathBuf::from("dev")))
+                  );
+    }
+    #[test]
+    fn git_fs_upstream_of() {
+        use std::io::Write;
+        let path = ::fs::util::test_git_dir("upstream_of");
+        ::std::fs::OpenOptions::new().append(true).open(path.join("config"))
+            .and_then(|mut f| f.write_all(b"[remote \"origin\"]\n\
+                                              \tfetch = +refs/heads/*:refs/remotes/mirror/*\n\
+                                              \tfetch = +refs/tags/*:refs/tags/*\n\
+                                              [remote \"github\"]\n\
+                                              \turl = https://github.com/NicolasDP/git\n\
+                                              [branch \"master\"]\n\
+                                              \tremote = origin\n\
+                                              \tmerge = refs/heads/master\n\
+                                              [branch \"dev/stage\"]\n\
+                                              \tremote = github\n\
+                                              \tmerge = refs/heads/stage\n\
+                                              [branch \"feature\"]\n\
+                                              \tremote = .\n\
+                                              \tmerge = refs/heads/dev/stage\n"))
+            .unwrap();
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!( git.upstream_of(&PathBuf::from("master"))
+                  , Ok(Some(SpecRef::remote("mirror", "master")))
+                  );
+        assert_eq!( git.upstream_of(&PathBuf::from("dev/stage"))
+                  , Ok(Some(SpecRef::remote("github", "stage")))
+                  );
+        assert_eq!( git.upstream_of(&PathBuf::from("feature"))
+                  , Ok(Some(SpecRef::branch("dev/stage")))
+                  );
+        assert_eq!(git.upstream_of(&PathBuf::from("other")), Ok(None));
+    }
+    #[test]
+    fn map_fetch_refspec() {
+        let refspec = "+refs/heads/*:refs/remotes/origin/*";
+        assert_eq!( map_refspec(refspec, "refs/heads/dev/stage")
+                  , Some("refs/remotes/origin/dev/stage".to_string())
+                  );
+        assert_eq!(map_refspec(refspec, "refs/tags/v1.0"), None);
+        assert_eq!( map_refspec("refs/heads/master:refs/remotes/origin/main", "refs/heads/master")
+                  , Some("refs/remotes/origin/main".to_string())
+                  );
+        assert_eq!(map_refspec("refs/heads/master", "refs/heads/master"), None);
+    }
+    #[test]
+    fn git_fs_get_packed_object() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        // the test repository is packed (see test_ref/init.sh)
+        let readme = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        assert!(!path.join("objects").join("e8").join("45566c06f9bf557d35e8292c37cf05d97a9769").exists());
+
+        let commit_ref : CommitRef<SHA1> = CommitRef::new(git.get_ref_follow_links(SpecRef::Head).unwrap());
+        let commit = match git.get_object_(commit_ref.as_ref().clone()).unwrap() {
+            Obj::Commit(commit) => commit,
+            _ => panic!("expected a commit")
+        };
+        let tree = match git.get_object_(commit.tree_ref.as_ref().clone()).unwrap() {
+            Obj::Tree(tree) => tree,
+            _ => panic!("expected a tree")
+        };
+        assert!(tree.contains(PathBuf::from("README.md")));
+        match git.get_object_(readme.clone()).unwrap() {
+            Obj::Blob(blob) => assert_eq!(blob.as_slice(), b"README\n"),
+            _ => panic!("expected a blob")
+        };
+        let blob : Blob = git.get_object(BlobRef::new(readme)).unwrap();
+        assert_eq!(blob.as_slice(), b"README\n");
+    }
+    #[test]
+    fn git_fs_get_deltified_object() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        // `numbers.txt` has been committed twice with a minor change, once
+        // packed one of the two blobs is stored as a delta of the other
+        // (see test_ref/init.sh)
+        let blobs = [ ("1179824569dcb14413904cb2b5cb036a9551024d", 1000)
+                    , ("1e404f5c7c1752a1332020d2625c2ba2a20982ce", 1001)
+                    ];
+        let idx = ::fs::pack::index::list_indexes::<SHA1>(&git).unwrap().pop().unwrap();
+        let index = ::fs::pack::index::parse_index_file::<SHA1>(&git.index_file(&idx)).unwrap();
+        let mut deltified = 0;
+        for &(hex, count) in blobs.iter() {
+            let hash = sha1(hex);
+            let offset = index.offset(&hash).expect("blob to be packed");
+            let mut pack = ::std::fs::File::open(git.pack_file(index.pack())).unwrap();
+            pack.seek(SeekFrom::Start(offset as u64)).unwrap();
+            match read_object_header(&mut pack).unwrap().0 {
+                PackObjectType::OfsDelta | PackObjectType::RefDelta => deltified += 1,
+                _ => {}
+            }
+
+            let expected : String = (1..count+1).map(|n| format!("{}\n", n)).collect();
+            let blob : Blob = git.get_object(BlobRef::new(hash.clone())).unwrap();
+            assert_eq!(blob.as_slice(), expected.as_bytes());
+
+            // the reconstructed object hashes back to its name
+            let data = git.read_object_data(&hash).unwrap();
+            assert_eq!(SHA1::hash(&mut data.as_slice()).unwrap(), hash);
+        }
+        assert_eq!(deltified, 1);
+    }
+    #[test]
+    fn git_fs_ref_cycle() {
+        let path = ::fs::util::test_git_dir("ref_cycle");
+        let git = GitFS::new(&path).unwrap();
+        {
+            let mut file = fs::File::create(path.join("HEAD")).unwrap();
+            file.write_all(b"ref: refs/heads/a\n").unwrap();
+            let mut file = fs::File::create(path.join("refs").join("heads").join("a")).unwrap();
+            file.write_all(b"ref: HEAD\n").unwrap();
+        }
+        let result : Result<SHA1> = git.get_ref_follow_links(SpecRef::Head);
+        assert_eq!(result, Err(GitError::RefCycle(SpecRef::Head)));
+        let new = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        assert_eq!( git.compare_and_swap_ref(&SpecRef::branch("a"), None, &new)
+                  , Err(GitError::RefCycle(SpecRef::branch("a")))
+                  );
+    }
+    #[test]
+    fn git_fs_ref_to_tag() {
+        let path = ::fs::util::test_git_dir("ref_to_tag");
+        let git = GitFS::new(&path).unwrap();
+        let c = test_commit(&git, vec![], "v1.0\n");
+        git.update_ref(SpecRef::tag("v1.0"), &c, None).unwrap();
+        // HEAD -> refs/tags/v1.0 -> <hash>
+        fs::File::create(path.join("HEAD")).unwrap().write_all(b"ref: refs/tags/v1.0\n").unwrap();
+        assert_eq!(git.get_head::<SHA1>(), Ok(Ref::Link(SpecRef::tag("v1.0"))));
+        let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        assert_eq!(head.as_bytes(), c.as_bytes());
+        assert_eq!(git.get_object_ref::<SHA1>(Ref::Link(SpecRef::Head)).unwrap().message(), "v1.0\n");
+
+        // refs/tags/v1.0 -> refs/tags/v2.0 -> refs/tags/v1.0
+        fs::File::create(path.join("refs").join("tags").join("v1.0")).unwrap()
+            .write_all(b"ref: refs/tags/v2.0\n").unwrap();
+        fs::File::create(path.join("refs").join("tags").join("v2.0")).unwrap()
+            .write_all(b"ref: refs/tags/v1.0\n").unwrap();
+        let result : Result<SHA1> = git.get_ref_follow_links(SpecRef::Head);
+        assert_eq!(result, Err(GitError::RefCycle(SpecRef::Head)));
+    }
+    #[test]
+    fn git_fs_packed_tag_symref() {
+        // HEAD -> refs/tags/v0.1, only in packed-refs
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let tag : SHA1 = git.get_ref_follow_links(SpecRef::tag("v0.1")).unwrap();
+        let path = ::fs::util::test_git_dir("packed_tag_symref");
+        fs::copy(get_root_test().join("packed-refs"), path.join("packed-refs")).unwrap();
+        fs::File::create(path.join("HEAD")).unwrap().write_all(b"ref: refs/tags/v0.1\n").unwrap();
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!(git.get_ref_follow_links(SpecRef::Head), Ok(tag));
+    }
+    #[test]
+    fn git_fs_write_blob() {
+        let path = ::fs::util::test_git_dir("write_blob");
+        let git = GitFS::new(&path).unwrap();
+        let blob = Blob::new(b"README\n".to_vec());
+        let hash : SHA1 = git.write_object(&blob).unwrap();
+        assert_eq!(hash, sha1("e845566c06f9bf557d35e8292c37cf05d97a9769"));
+        assert!(path.join("objects").join("e8").join("45566c06f9bf557d35e8292c37cf05d97a9769").is_file());
+        let read : Blob = git.get_object(BlobRef::new(hash.clone())).unwrap();
+        assert_eq!(read, blob);
+        // writing the same object again is a no-op
+        assert_eq!(git.write_object::<SHA1, _>(&blob).unwrap(), hash);
+    }
+    #[test]
+    fn git_fs_write_tree() {
+        let path = ::fs::util::test_git_dir("write_tree");
+        let git = GitFS::new(&path).unwrap();
+        let blob : SHA1 = git.write_object(&Blob::new(b"README\n".to_vec())).unwrap();
+        let mut entries = ::std::collections::BTreeSet::new();
+        entries.insert(TreeEnt::Blob( Permissions::default_file()
+                                    , PathBuf::from("README.md")
+                                    , BlobRef::new(blob)
+                                    ));
+        let tree = Tree::new_with(entries);
+        let hash : SHA1 = git.write_object(&tree).unwrap();
+        let read : Tree<SHA1> = git.get_object(TreeRef::new(hash)).unwrap();
+        assert_eq!(read, tree);
+    }
+    #[test]
+    fn git_fs_open_large_blob() {
+        let path = ::fs::util::test_git_dir("open_large_blob");
+        let git = GitFS::new(&path).unwrap();
+        let data : Vec<u8> = (0..4 * 1024 * 1024).map(|n| (n % 251) as u8).collect();
+        let hash = git.write_object::<SHA1, _>(&Blob::new(data.clone())).unwrap();
+
+        let mut reader = git.open_blob(BlobRef::new(hash)).unwrap();
+        assert_eq!(reader.size(), data.len());
+        let mut buf = [0u8; 8192];
+        let mut count = 0;
+        loop {
+            let read = reader.read(&mut buf).unwrap();
+            if read == 0 { break }
+            assert_eq!(&buf[..read], &data[count..count + read]);
+            count += read;
+        }
+        assert_eq!(count, reader.size());
+    }
+    #[test]
+    fn git_fs_open_packed_blobs() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        // see git_fs_get_deltified_object
+        for &(hex, count) in [ ("1179824569dcb14413904cb2b5cb036a9551024d", 1000)
+                             , ("1e404f5c7c1752a1332020d2625c2ba2a20982ce", 1001)
+                             ].iter() {
+            let expected : String = (1..count+1).map(|n| format!("{}\n", n)).collect();
+            let mut reader = git.open_blob(BlobRef::new(sha1(hex))).unwrap();
+            assert_eq!(reader.size(), expected.len());
+            let mut content = String::new();
+            reader.read_to_string(&mut content).unwrap();
+            assert_eq!(content, expected);
+        }
+    }
+    #[test]
+    fn git_fs_verify_object() {
+        let path = ::fs::util::test_git_dir("verify_object");
+        let mut git = GitFS::new(&path).unwrap();
+        let hash : SHA1 = git.write_object(&Blob::new(b"README\n".to_vec())).unwrap();
+        let hex = hash.to_hexadecimal();
+        // corrupt the content of the object (keeping a valid zlib stream)
+        write_raw_loose(&path, &hash, b"blob 7\0READMY\n");
+
+        // not verified by default
+        let blob : Blob = git.get_object(BlobRef::new(hash.clone())).unwrap();
+        assert_eq!(blob.as_slice(), b"READMY\n");
+
+        git.set_verify(true);
+        let actual = SHA1::hash(&mut &b"blob 7\0READMY\n"[..]).unwrap();
+        let expected = || Some(GitError::HashMismatch(hex.clone(), actual.to_hexadecimal()));
+        assert_eq!(git.get_object::<SHA1, Blob>(BlobRef::new(hash.clone())).err(), expected());
+        assert_eq!(git.get_object_(hash).err(), expected());
+    }
+    #[test]
+    fn git_fs_exists() {
+        let path = ::fs::util::test_git_dir("exists");
+        let git = GitFS::new(&path).unwrap();
+        let loose : SHA1 = git.write_object(&Blob::new(b"loose\n".to_vec())).unwrap();
+        assert_eq!(git.exists(&loose), Ok(true));
+        assert_eq!(git.exists(&sha1("e845566c06f9bf557d35e8292c37cf05d97a9769")), Ok(false));
+
+        // README.md is only in the pack file (see git_fs_get_packed_object)
+        let git = GitFS::new(&get_root_test()).unwrap();
+        assert_eq!(git.exists(&sha1("e845566c06f9bf557d35e8292c37cf05d97a9769")), Ok(true));
+        assert_eq!(git.exists(&sha1("e845566c06f9bf557d35e8292c37cf05d97a9760")), Ok(false));
+    }
+    #[test]
+    fn git_fs_resolve_prefix() {
+        let path = ::fs::util::test_git_dir("resolve_prefix");
+        let git = GitFS::new(&path).unwrap();
+        let prefix = |hex: &str| Partial::<SHA1>::from_hex(hex).unwrap();
+        assert_eq!( git.resolve_prefix(&prefix(""))
+                  , Err(GitError::InvalidRef(PathBuf::from("")))
+                  );
+        // e845566c06f9bf557d35e8292c37cf05d97a9769
+        let readme : SHA1 = git.write_object(&Blob::new(b"README\n".to_vec())).unwrap();
+        // e847bdca533fd3c3aee1ee158ef2aabf2fa78a29
+        let other : SHA1 = git.write_object(&Blob::new(b"1364\n".to_vec())).unwrap();
+        assert_eq!(other.to_hexadecimal()[..3], readme.to_hexadecimal()[..3]);
+
+        assert_eq!(git.resolve_prefix(&prefix("e845")), Ok(readme.clone()));
+        assert_eq!(git.resolve_prefix(&prefix("e847")), Ok(other.clone()));
+        match git.resolve_prefix(&prefix("e84")) {
+            Err(GitError::AmbiguousRef(mut hashes)) => {
+                hashes.sort();
+                assert_eq!(hashes, vec![readme.to_hexadecimal(), other.to_hexadecimal()]);
+            },
+            r => panic!("expected an ambiguous prefix: {:?}", r)
+        }
+        match git.resolve_prefix(&prefix("")) {
+            Err(GitError::AmbiguousRef(_)) => {},
+            r => panic!("expected an ambiguous prefix: {:?}", r)
+        }
+        assert_eq!( git.resolve_prefix(&prefix("f00"))
+                  , Err(GitError::InvalidRef(PathBuf::from("f00")))
+                  );
+    }
+    #[test]
+    fn git_fs_list_tree_files() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : Commit<SHA1> = git.get_object_ref(Ref::Link(SpecRef::Head)).unwrap();
+        let files : Vec<PathBuf> = git.list_tree_files(head.tree_ref).unwrap()
+            .into_iter().map(|(path, _, _)| path).collect();
+        // see test_ref/init.sh
+        assert_eq!(files, vec![PathBuf::from("README.md"), PathBuf::from("numbers.txt")]);
+    }
+    #[test]
+    fn git_fs_checkout_tree() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : Commit<SHA1> = git.get_object_ref(Ref::Link(SpecRef::Head)).unwrap();
+        let dest = ::std::env::temp_dir().join("git-rs-tests").join("checkout_tree");
+        let _ = fs::remove_dir_all(&dest);
+        git.checkout_tree(head.tree_ref, &dest).unwrap();
+        let mut readme = String::new();
+        fs::File::open(dest.join("README.md")).unwrap().read_to_string(&mut readme).unwrap();
+        // see test_ref/init.sh
+        assert_eq!(readme, "README\n");
+        assert!(dest.join("numbers.txt").is_file());
+    }
+    #[test]
+    fn git_fs_checkout_tree_nested() {
+        let path = ::fs::util::test_git_dir("checkout_tree_nested");
+        let git = GitFS::new(&path).unwrap();
+        let blob = |data: &[u8]| BlobRef::new(git.write_object::<SHA1, _>(&Blob::new(data.to_vec())).unwrap());
+        let src : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_file(), PathBuf::from("main.rs"), blob(b"fn main() {}\n"))
+                                   ].into_iter().collect();
+        let src = TreeRef::new(git.write_object(&src).unwrap());
+        let root : Tree<SHA1> = vec![ TreeEnt::Tree(Permissions::default_dir(), PathBuf::from("src"), src)
+                                    , TreeEnt::Blob(Permissions::default_exe(), PathBuf::from("build.sh"), blob(b"cargo build\n"))
+                                    , TreeEnt::SymbolicLink(Permissions::new(), PathBuf::from("main.rs"), blob(b"src/main.rs"))
+                                    ].into_iter().collect();
+        let root = TreeRef::new(git.write_object(&root).unwrap());
+        let dest = path.parent().unwrap().join("checkout");
+        let _ = fs::remove_dir_all(&dest);
+        git.checkout_tree(root, &dest).unwrap();
+
+        let mut main = String::new();
+        fs::File::open(dest.join("src").join("main.rs")).unwrap().read_to_string(&mut main).unwrap();
+        assert_eq!(main, "fn main() {}\n");
+        #[cfg(unix)] {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dest.join("build.sh")), 0o755);
+            assert_eq!(mode(&dest.join("src").join("main.rs")), 0o644);
+            assert_eq!(fs::read_link(dest.join("main.rs")).unwrap(), PathBuf::from("src/main.rs"));
+        }
+
+        // the entries escaping the destination are rejected
+        for name in ["..", ".git", ".GIT", ".Git", "a/../../b"].iter() {
+            let evil : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_file(), PathBuf::from(name), blob(b"evil\n"))
+                                        ].into_iter().collect();
+            let evil = TreeRef::new(git.write_object(&evil).unwrap());
+            assert!(git.checkout_tree(evil, &dest).is_err());
+        }
+        assert!(! path.parent().unwrap().join("b").exists());
+    }
+    #[cfg(unix)]
+    #[test]
+    fn git_fs_checkout_tree_through_symlink() {
+        let path = ::fs::util::test_git_dir("checkout_tree_through_symlink");
+        let git = GitFS::new(&path).unwrap();
+        let blob = |data: &[u8]| BlobRef::new(git.write_object::<SHA1, _>(&Blob::new(data.to_vec())).unwrap());
+        let outside = path.parent().unwrap().join("outside");
+        let dest = path.parent().unwrap().join("checkout");
+        let _ = fs::remove_dir_all(&outside);
+        let _ = fs::remove_dir_all(&dest);
+        fs::create_dir_all(&outside).unwrap();
+
+        // a symbolic link `a -> outside` and a tree `a` with a file
+        let sub : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_file(), PathBuf::from("passwd"), blob(b"evil\n"))
+                                   ].into_iter().collect();
+        let sub = TreeRef::new(git.write_object(&sub).unwrap());
+        let target = outside.to_str().unwrap().as_bytes();
+        let evil : Tree<SHA1> = vec![ TreeEnt::SymbolicLink(Permissions::new(), PathBuf::from("a"), blob(target))
+                                    , TreeEnt::Tree(Permissions::default_dir(), PathBuf::from("a"), sub.clone())
+                                    ].into_iter().collect();
+        let evil = TreeRef::new(git.write_object(&evil).unwrap());
+        let _ = git.checkout_tree(evil, &dest);
+        assert!(! outside.join("passwd").exists());
+
+        // a symbolic link already in the destination is not followed
+        let _ = fs::remove_dir_all(&dest);
+        fs::create_dir_all(&dest).unwrap();
+        ::std::os::unix::fs::symlink(&outside, dest.join("a")).unwrap();
+        let root : Tree<SHA1> = vec![ TreeEnt::Tree(Permissions::default_dir(), PathBuf::from("a"), sub)
+                                    ].into_iter().collect();
+        let root = TreeRef::new(git.write_object(&root).unwrap());
+        git.checkout_tree(root, &dest).unwrap();
+        assert!(! outside.join("passwd").exists());
+        assert!(dest.join("a").join("passwd").is_file());
+        assert!(! is_symlink(&dest.join("a")));
+
+        // nor is a parent directory replaced by a symbolic link
+        assert!(checkout_path(&dest, &dest.join("b").join("c"), Path::new("d")).is_ok());
+        ::std::os::unix::fs::symlink(&outside, dest.join("b")).unwrap();
+        assert!(checkout_path(&dest, &dest.join("b").join("c"), Path::new("d")).is_err());
+    }
+    #[test]
+    fn git_fs_list_tree_files_nested() {
+        let path = ::fs::util::test_git_dir("list_tree_files_nested");
+        let git = GitFS::new(&path).unwrap();
+        let blob = |data: &[u8]| BlobRef::new(git.write_object::<SHA1, _>(&Blob::new(data.to_vec())).unwrap());
+        let src : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_file(), PathBuf::from("main.rs"), blob(b"fn main() {}\n"))
+                                   ].into_iter().collect();
+        let src = TreeRef::new(git.write_object(&src).unwrap());
+        let root : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_exe(), PathBuf::from("build.sh"), blob(b"#!/bin/sh\n"))
+                                    , TreeEnt::Tree(Permissions::new(), PathBuf::from("src"), src)
+                                    ].into_iter().collect();
+        let root = TreeRef::new(git.write_object(&root).unwrap());
+        let files = git.list_tree_files(root).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, PathBuf::from("build.sh"));
+        assert_eq!(files[0].2, Permissions::default_exe());
+        assert_eq!(files[1].0, PathBuf::from("src").join("main.rs"));
+        assert_eq!(files[1].1, blob(b"fn main() {}\n"));
+    }
+    #[test]
+    fn git_fs_get_commit_missing_committer() {
+        let path = ::fs::util::test_git_dir("get_commit_missing_committer");
+        let git = GitFS::new(&path).unwrap();
+        let content =
+            "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+             author Test <git-test@example.com> 1480007832 +0100\n\
+             \n\
+             imported without committer\n";
+        let mut writer = git.object_writer::<SHA1>().unwrap();
+        write!(writer, "commit {}\0{}", content.len(), content).unwrap();
+        let cr = CommitRef::new(writer.finish().unwrap());
+        let err = git.get_object::<SHA1, Commit<SHA1>>(cr.clone()).unwrap_err();
+        assert_eq!(err, GitError::MissingHeader("committer".to_string()));
+        assert!(format!("{}", err).contains("committer"));
+        assert_eq!(git.get_object_(cr).err(), Some(err));
+    }
+    #[test]
+    fn git_fs_get_object_trailing_bytes() {
+        let path = ::fs::util::test_git_dir("get_object_trailing_bytes");
+        let git = GitFS::new(&path).unwrap();
+        let content =
+            "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+             author Test <git-test@example.com> 1480007832 +0100\n\
+             committer Test <git-test@example.com> 1480007832 +0100\n\
+             \n\
+             padded\n";
+        let mut writer = git.object_writer::<SHA1>().unwrap();
+        write!(writer, "commit {}\0{}\0\0\0", content.len(), content).unwrap();
+        let cr = CommitRef::new(writer.finish().unwrap());
+        assert_eq!(git.get_commit(cr.clone()), Err(GitError::TrailingBytes(3)));
+        assert_eq!(git.get_object_(cr).err(), Some(GitError::TrailingBytes(3)));
+    }
+    #[test]
+    fn git_fs_tree_entry_at() {
+        let path = ::fs::util::test_git_dir("tree_entry_at");
+        let git = GitFS::new(&path).unwrap();
+        let blob = |data: &[u8]| BlobRef::new(git.write_object::<SHA1, _>(&Blob::new(data.to_vec())).unwrap());
+        let src : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_file(), PathBuf::from("main.rs"), blob(b"fn main() {}\n"))
+                                   ].into_iter().collect();
+        let src = TreeRef::new(git.write_object(&src).unwrap());
+        let root : Tree<SHA1> = vec![ TreeEnt::Blob(Permissions::default_exe(), PathBuf::from("build.sh"), blob(b"#!/bin/sh\n"))
+                                    , TreeEnt::Tree(Permissions::new(), PathBuf::from("src"), src.clone())
+                                    ].into_iter().collect();
+        let root = TreeRef::new(git.write_object(&root).unwrap());
+        let at = |p: &str| git.tree_entry_at(root.clone(), Path::new(p)).unwrap();
+        assert_eq!( at("src/main.rs")
+                  , Some(TreeEnt::Blob(Permissions::default_file(), PathBuf::from("main.rs"), blob(b"fn main() {}\n")))
+                  );
+        assert_eq!(at("src"), Some(TreeEnt::Tree(Permissions::new(), PathBuf::from("src"), src)));
+        assert_eq!(at("src/lib.rs"), None);
+        assert_eq!(at("build.sh/main.rs"), None);
+        assert_eq!(at("doc/index.md"), None);
+        assert!(git.tree_entry_at(root, Path::new("../src")).is_err());
+    }
+    #[test]
+    fn git_fs_read_index() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let index = git.read_index::<SHA1>().unwrap();
+        // the working tree of test_ref is clean: the index and HEAD match
+        let head : Commit<SHA1> = git.get_object_ref(Ref::Link(SpecRef::Head)).unwrap();
+        let files = git.list_tree_files(head.tree_ref).unwrap();
+        assert_eq!(index.len(), files.len());
+        for (entry, file) in index.iter().zip(files.iter()) {
+            assert_eq!(entry.path, file.0);
+            assert_eq!(entry.hash.as_bytes(), file.1.as_bytes());
+            assert_eq!(entry.stage(), 0);
+        }
+    }
+    #[test]
+    fn git_fs_with_cache() {
+        let path = ::fs::util::test_git_dir("with_cache");
+        let git = GitFS::new(&path).unwrap().with_cache(8);
+        let readme : BlobRef<SHA1> = BlobRef::new(git.write_object(&Blob::new(b"README\n".to_vec())).unwrap());
+        let numbers : BlobRef<SHA1> = BlobRef::new(git.write_object(&Blob::new(b"1\n2\n".to_vec())).unwrap());
+
+        let blob = git.get_blob(readme.clone()).unwrap();
+        // the second read does not touch the file system
+        fs::remove_file(git.object_file(&readme)).unwrap();
+        assert_eq!(git.get_blob(readme.clone()), Ok(blob.clone()));
+        assert_eq!(git.clone().get_blob(readme.clone()), Ok(blob));
+
+        // reading another object evicts the first one
+        git.get_blob(numbers).unwrap();
+        assert!(git.get_blob(readme).is_err());
+    }
+    #[test]
+    fn git_fs_object_writer() {
+        use std::io::Read;
+        let path = ::fs::util::test_git_dir("object_writer");
+        let git = GitFS::new(&path).unwrap();
+        let size = 4 * 1024 * 1024 + 7;
+        let chunk : Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let mut expected = format!("blob {}\0", size).into_bytes();
+
+        let mut writer = git.object_writer::<SHA1>().unwrap();
+        writer.write_all(&expected).unwrap();
+        let mut written = 0;
+        while written < size {
+            let len = ::std::cmp::min(chunk.len(), size - written);
+            writer.write_all(&chunk[..len]).unwrap();
+            expected.extend_from_slice(&chunk[..len]);
+            written += len;
+        }
+        let hash : SHA1 = writer.finish().unwrap();
+        assert_eq!(hash, SHA1::hash(&mut expected.as_slice()).unwrap());
+
+        let mut data = Vec::new();
+        ZlibDecoder::new(fs::File::open(git.object_file(&hash)).unwrap())
+            .read_to_end(&mut data).unwrap();
+        assert!(data == expected);
+        // no temporary file left behind
+        let tmps = fs::read_dir(path.join("objects")).unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("tmp_obj_"))
+            .count();
+        assert_eq!(tmps, 0);
+
+        // an abandoned writer does not leave its temporary file
+        {
+            let mut writer = git.object_writer::<SHA1>().unwrap();
+            writer.write_all(b"blob 3\0ab").unwrap();
+        }
+        assert_eq!(fs::read_dir(path.join("objects")).unwrap().count(), 1);
+    }
+    #[test]
+    fn git_fs_iter_pack_objects() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        for idx in list_indexes::<SHA1>(&git).unwrap().iter() {
+            let index = parse_index_file::<SHA1>(&git.index_file(idx)).unwrap();
+            let objects = git.iter_pack_objects(idx).unwrap();
+            assert_eq!(objects.len(), index.header().size());
+            // the first object follows the pack header (`PACK`, version, count)
+            assert_eq!(objects[0].offset, 12);
+            for pair in objects.windows(2) {
+                assert!(pair[0].offset < pair[1].offset);
+                assert_eq!(pair[0].offset + pair[0].packed_size, pair[1].offset);
+            }
+            let pack_size = fs::metadata(git.pack_file(index.pack())).unwrap().len() as usize;
+            let last = objects.last().unwrap();
+            assert_eq!(last.offset + last.packed_size, pack_size - SHA1::digest_size());
+            // see test_ref/init.sh
+            let readme = objects.iter().find(|o| o.hash == sha1("e845566c06f9bf557d35e8292c37cf05d97a9769")).unwrap();
+            assert_eq!((readme.kind, readme.size), (PackObjectType::Blob, 7));
+        }
+    }
+    #[test]
+    fn git_fs_verify_pack() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let indexes = list_indexes::<SHA1>(&git).unwrap();
+        assert!(! indexes.is_empty());
+        for idx in indexes.iter() {
+            git.verify_pack(idx).unwrap();
+        }
+
+        // copy the pack in a test repository and corrupt its last byte
+        let path = ::fs::util::test_git_dir("verify_pack");
+        let corrupted = GitFS::new(&path).unwrap();
+        fs::create_dir_all(path.join("objects").join("pack")).unwrap();
+        let idx = &indexes[0];
+        let pack = PackRef::new(SHA1::from_bytes(idx.as_bytes().to_vec()).unwrap());
+        fs::copy(git.index_file(idx), corrupted.index_file(idx)).unwrap();
+        fs::copy(git.pack_file(&pack), corrupted.pack_file(&pack)).unwrap();
+        corrupted.verify_pack(idx).unwrap();
+        let mut data = Vec::new();
+        fs::File::open(corrupted.pack_file(&pack)).unwrap().read_to_end(&mut data).unwrap();
+        *data.last_mut().unwrap() ^= 0xff;
+        fs::File::create(corrupted.pack_file(&pack)).unwrap().write_all(&data).unwrap();
+        match corrupted.verify_pack(idx) {
+            Err(GitError::HashMismatch(_, _)) => {},
+            r => panic!("expected a hash mismatch, got {:?}", r)
+        }
+    }
+    #[test]
+    fn git_fs_stats() {
+        // see test_ref/init.sh: everything is packed
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let stats = git.stats::<SHA1>().unwrap();
+        assert!(stats.commits >= 1);
+        assert!(stats.packs >= 1);
+        assert!(stats.pack_size > 0);
+        assert_eq!(stats.objects(), git.list_all_objects::<SHA1>().unwrap().len());
+        assert!(stats.objects() <= stats.loose_objects + stats.packed_objects);
+        // the kinds read along the packs are the ones of the objects' headers
+        let mut kinds = RepoStats::default();
+        for h in git.list_all_objects::<SHA1>().unwrap().iter() {
+            kinds.add_kind(git.object_header(h).unwrap().0);
+        }
+        assert_eq!( (stats.commits, stats.trees, stats.blobs, stats.tags)
+                  , (kinds.commits, kinds.trees, kinds.blobs, kinds.tags)
+                  );
+
+        let path = ::fs::util::test_git_dir("stats");
+        let git = GitFS::new(&path).unwrap();
+        let tree = TreeRef::new(git.write_object(&Tree::<SHA1>::new()).unwrap());
+        git.write_object::<SHA1, _>(&Blob::new(b"hello\n".to_vec())).unwrap();
+        let person = Person::new("Test".to_string(), "git-test@example.com".to_string(), Date::seconds_since_epoch(1480007832));
+        git.write_object::<SHA1, _>(&Commit::new(tree, person.clone(), person, "initial commit\n")).unwrap();
+        assert_eq!( git.stats::<SHA1>().unwrap()
+                  , RepoStats { loose_objects: 3, commits: 1, trees: 1, blobs: 1, .. RepoStats::default() }
+                  );
+    }
+    #[test]
+    fn git_fs_read_fetch_head() {
+        let path = ::fs::util::test_git_dir("read_fetch_head");
+        let git = GitFS::new(&path).unwrap();
+        assert_eq!(git.read_fetch_head::<SHA1>(), Ok(Vec::new()));
+        fs::File::create(git.fetch_head_file()).unwrap().write_all(
+            b"251fd8e7f2482bda4b0c0186dd7bdc98b52fc955\t\tbranch 'master' of ../origin\n\
+              a5f0d4ba4a55af51346a0fb401bc291833d62711\tnot-for-merge\tbranch 'dev' of ../origin\n"
+        ).unwrap();
+        let entries = git.read_fetch_head::<SHA1>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].hash, sha1("251fd8e7f2482bda4b0c0186dd7bdc98b52fc955"));
+        assert!(entries[0].merge);
+        assert_eq!(entries[1].description, "branch 'dev' of ../origin");
+        assert!(! entries[1].merge);
+
+        fs::File::create(git.fetch_head_file()).unwrap().write_all(b"not a fetch head\n").unwrap();
+        assert!(git.read_fetch_head::<SHA1>().is_err());
+    }
+    #[test]
+    fn git_fs_read_reflog() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let reflog = git.read_reflog::<SHA1>(SpecRef::Head).unwrap();
+        assert!(! reflog.is_empty());
+        let tip : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        assert_eq!(reflog[0].new, tip);
+        assert!(reflog.last().unwrap().old.as_bytes().iter().all(|b| *b == 0));
+        assert!(reflog.last().unwrap().message.starts_with("commit (initial): "));
+        let unknown = SpecRef::branch("no-such-branch");
+        assert!(git.read_reflog::<SHA1>(unknown).unwrap().is_empty());
+    }
+    #[test]
+    fn git_fs_read_reflog_invalid() {
+        let path = ::fs::util::test_git_dir("read_reflog_invalid");
+        let git = GitFS::new(&path).unwrap();
+        let file = git.reflog_file(&SpecRef::Head);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        let first : &[u8] =
+            b"0000000000000000000000000000000000000000 251fd8e7f2482bda4b0c0186dd7bdc98b52fc955 \
+              Test <git-test@example.com> 1480007832 +0000\tcommit (initial): caf\xe9\n";
+        let second : &[u8] =
+            b"251fd8e7f2482bda4b0c0186dd7bdc98b52fc955 a5f0d4ba4a55af51346a0fb401bc291833d62711 \
+              Test <git-test@example.com> 1480007900 +0100\tcommit: more\n";
+        // a message which is not UTF-8 does not stop the parsing
+        fs::File::create(&file).unwrap().write_all(&[first, second].concat()).unwrap();
+        let reflog = git.read_reflog::<SHA1>(SpecRef::Head).unwrap();
+        assert_eq!(reflog.len(), 2);
+        assert_eq!(reflog[1].message, "commit (initial): caf\u{FFFD}");
+        // nor are the entries after an invalid line silently dropped
+        fs::File::create(&file).unwrap().write_all(&[first, b"garbage\n", second].concat()).unwrap();
+        assert!(git.read_reflog::<SHA1>(SpecRef::Head).is_err());
+    }
+    #[test]
+    fn git_fs_get_unknown_object() {
+        let path = ::fs::util::test_git_dir("get_unknown_object");
+        let git = GitFS::new(&path).unwrap();
+        let hash = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        write_raw_loose(&path, &hash, b"zebra 7\0README\n");
+        match git.get_object_(hash) {
+            Err(GitError::ParsingError(_)) => {},
+            r => panic!("expected a parsing error: {:?}", r.map(|_| ()))
+        }
+    }
+    #[test]
+    fn git_fs_get_truncated_commit() {
+        let path = ::fs::util::test_git_dir("get_truncated_commit");
+        let git = GitFS::new(&path).unwrap();
+        let commit = test_commit(&git, vec![], "truncated\n");
+        let mut data = Vec::new();
+        git.get_commit(commit.clone()).unwrap().encode(&mut data).unwrap();
+        let missing = 20;
+        data.truncate(data.len() - missing);
+        // the content does not match the hash, but it is not verified here
+        let truncated = CommitRef::new(sha1("e845566c06f9bf557d35e8292c37cf05d97a9769"));
+        write_raw_loose(&path, truncated.as_ref(), &data);
+        assert_eq!( git.get_commit(truncated.clone()).err()
+                  , Some(GitError::ParsingErrorNotEnough(Some(missing)))
+                  );
+        assert_eq!( git.get_object_(truncated.as_ref().clone()).err()
+                  , Some(GitError::ParsingErrorNotEnough(Some(missing)))
+                  );
+    }
+    #[test]
+    fn git_fs_get_missing_object() {
+        let path = ::fs::util::test_git_dir("get_missing_object");
+        let git = GitFS::new(&path).unwrap();
+        let missing = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        let loose = path.join("objects").join("e8").join("45566c06f9bf557d35e8292c37cf05d97a9769");
+        assert_eq!(git.get_object_(missing).err(), Some(GitError::InvalidRef(loose)));
+    }
+    const PACKED_REFS : &'static str =
+        "# pack-refs with: peeled fully-peeled sorted \n\
+         2aae6c35c94fcfb415dbe95f408b9ce91ee846ed refs/heads/master\n\
+         da39a3ee5e6b4b0d3255bfef95601890afd80709 refs/remotes/origin/master\n\
+         e845566c06f9bf557d35e8292c37cf05d97a9769 refs/bisect/bad\n\
+         ^2aae6c35c94fcfb415dbe95f408b9ce91ee846ed\n\
+         4b825dc642cb6eb9a060e54bf8d69288fbee4904 refs/notes/commits\n\
+         1179824569dcb14413904cb2b5cb036a9551024d refs/tags/v0.1\n\
+         ^2aae6c35c94fcfb415dbe95f408b9ce91ee846ed\n";
+
+    #[test]
+    fn parse_packed_refs_file() {
+        let refs = parse_packed_refs::<SHA1>(PACKED_REFS).unwrap();
+        assert_eq!(refs, vec![
+            PackedRef { name: SpecRef::branch("master")
+                      , hash: sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed")
+                      , peeled: None
+                      },
+            PackedRef { name: SpecRef::remote("origin", "master")
+                      , hash: sha1("da39a3ee5e6b4b0d3255bfef95601890afd80709")
+                      , peeled: None
+                      },
+            PackedRef { name: SpecRef::notes("commits")
+                      , hash: sha1("4b825dc642cb6eb9a060e54bf8d69288fbee4904")
+                      , peeled: None
+                      },
+            PackedRef { name: SpecRef::tag("v0.1")
+                      , hash: sha1("1179824569dcb14413904cb2b5cb036a9551024d")
+                      , peeled: Some(sha1("2aae6c35c94fcfb415dbe95f408b9ce91ee846ed"))
+                      }
+        ]);
+        assert!(parse_packed_refs::<SHA1>("^2aae6c35c94fcfb415dbe95f408b9ce91ee846ed\n").is_err());
+        assert!(parse_packed_refs::<SHA1>("2aae6c35 refs/heads/master\n").is_err());
+        // the names, whatever the size of the hashes
+        let names = vec![ SpecRef::branch("master"), SpecRef::remote("origin", "master")
+                        , SpecRef::notes("commits"), SpecRef::tag("v0.1")
+                        ];
+        assert_eq!(parse_packed_ref_names(PACKED_REFS).unwrap(), names);
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9 refs/heads/master\n";
+        assert_eq!(parse_packed_ref_names(sha256).unwrap(), vec![SpecRef::branch("master")]);
+        assert!(parse_packed_ref_names("master refs/heads/master\n").is_err());
+    }
+    #[test]
+    fn git_fs_packed_refs() {
+        let path = ::fs::util::test_git_dir("packed_refs");
+        let git = GitFS::new(&path).unwrap();
+        {
+            let mut file = fs::File::create(git.packed_refs_file()).unwrap();
+            file.write_all(PACKED_REFS.as_bytes()).unwrap();
+        }
+        let loose = sha1("e845566c06f9bf557d35e8292c37cf05d97a9769");
+        fs::create_dir_all(path.join("refs").join("heads")).unwrap();
+        {
+            let mut file = fs::File::create(path.join("refs").join("heads").join("master")).unwrap();
+            file.write_all(format!("{}\n", loose.to_hexadecimal()).as_bytes()).unwrap();
+        }
+
+        // the loose reference takes precedence over the packed one
+        assert_eq!(git.get_ref_follow_links::<SHA1>(SpecRef::branch("master")).unwrap(), loose);
+        assert_eq!( git.get_ref_follow_links::<SHA1>(SpecRef::tag("v0.1")).unwrap()
+                  , sha1("1179824569dcb14413904cb2b5cb036a9551024d")
+                  );
+        assert_eq!(git.list_branches().unwrap(), vec![SpecRef::branch("master")]);
+        assert_eq!(git.list_tags().unwrap(), vec![SpecRef::tag("v0.1")]);
+        assert_eq!(git.list_remotes().unwrap(), vec![SpecRef::remote("origin", "master")]);
+    }
+    #[test]
+    fn git_fs_packed_tags() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let tags = git.list_tags().unwrap();
+        assert!(tags.contains(&SpecRef::tag("v0.0.1")));
+        assert!(tags.contains(&SpecRef::tag("v0.1")));
+        let head : SHA1 = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        let lightweight : SHA1 = git.get_ref_follow_links(SpecRef::tag("v0.0.1")).unwrap();
+        assert_eq!(lightweight, head);
+        let annotated = git.get_packed_refs::<SHA1>().unwrap()
+            .into_iter()
+            .find(|pr| pr.name == SpecRef::tag("v0.1"))
+            .expect("v0.1 to be packed");
+        assert_eq!(annotated.peeled, Some(head));
+    }
+    #[test]
+    fn git_fs_get_tree() {
+        let path = get_root_test();
+        let git = GitFS::new(&path).unwrap();
+        // get the head
+        let commit =
+            git.get_object_ref(get_test_commit())
+                    .expect("expected to read a commit object");
 
         let tree : Tree<SHA1> = git.get_object(commit.tree_ref.clone())
                       .expect("expected to read a Tree object");
         println!("++ Tree ++++++++++++++++++++++++++");
         println!("{}", tree)
     }
+    #[test]
+    fn git_fs_get_commit_head() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        let head : CommitRef<SHA1> = git.get_ref_follow_links(SpecRef::Head).unwrap();
+        let commit = git.get_commit(head.clone()).unwrap();
+        // see test_ref/init.sh
+        assert_eq!(commit.message, "\nadd one more number\n");
+        assert_eq!(commit.parents.len(), 1);
+        match git.get_object_(head.as_ref().clone()).unwrap() {
+            Obj::Commit(c) => assert_eq!(c, commit),
+            _ => panic!("expected a commit")
+        }
+    }
+    #[test]
+    fn git_fs_get_tree_and_blob() {
+        let git = GitFS::new(&get_root_test()).unwrap();
+        // see test_ref/init.sh
+        let commit = git.get_object_ref(get_test_commit()).unwrap();
+        let tree = git.get_tree(commit.tree_ref.clone()).unwrap();
+        let readme = tree.get(PathBuf::from("README.md")).expect("README.md in the tree");
+        let blob = match readme {
+            &TreeEnt::Blob(_, _, ref br) => git.get_blob(br.clone()).unwrap(),
+            te => panic!("expected a blob entry: {}", te)
+        };
+        assert_eq!(blob, Blob::new(b"README\n".to_vec()));
+        assert!(tree.contains(PathBuf::from("numbers.txt")));
+    }
 }
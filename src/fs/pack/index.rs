@@ -1,9 +1,10 @@
 use std::{fmt, convert, io, cmp, path};
+use std::cell::RefCell;
 use std::collections::BTreeSet;
 use nom;
 
-use ::protocol::Hash;
-use ::error::{Result};
+use ::protocol::{Hash, Partial, same_hash};
+use ::error::{Result, GitError};
 use ::fs::util::*;
 use ::fs::GitFS;
 use super::PackRef;
@@ -11,6 +12,7 @@ use super::PackRef;
 // magic + version + fanout
 const INDEX_HEADER_SIZE : usize = 4 + 4 + 256 * 4;
 const INDEX_HASH_OFFSET : usize = INDEX_HEADER_SIZE;
+const INDEX_MAGIC : u32 = 0xff744f63;
 
 #[derive(Copy)]
 pub struct Header {
@@ -64,6 +66,24 @@ impl Header {
     }
     pub fn version(&self) -> u32 { self.version }
 
+    /// check the header is the one of a supported index file: the magic
+    /// (`\377tOc`), the version (only the version 2 is supported) and the
+    /// fanout table (it is a cumulative count, so it never decreases).
+    pub fn validate(&self) -> Result<()> {
+        if self.magic != INDEX_MAGIC {
+            return Err(GitError::ParsingError(format!("invalid index magic: {:#x}", self.magic)))
+        }
+        if self.version != 2 {
+            return Err(GitError::ParsingError(format!("unsupported index version: {}", self.version)))
+        }
+        for n in 1..256 {
+            if self.fanouts[n] < self.fanouts[n - 1] {
+                return Err(GitError::ParsingError(format!("invalid index fanout table at {}", n)))
+            }
+        }
+        Ok(())
+    }
+
     pub fn offsets<H: Hash>(&self) -> (usize, usize, usize) {
         let sz = self.size();
         let hash_table_size = sz * H::digest_size();
@@ -74,6 +94,9 @@ impl Header {
     }
 }
 
+/// flag of the 4 bytes offsets referring to the table of large offsets
+const LARGE_OFFSET_FLAG : usize = 0x80000000;
+
 named!(nom_parse_index_header_magic<u32>, u32!(nom::Endianness::Big));
 named!(nom_parse_index_header_version<u32>, u32!(nom::Endianness::Big));
 named!(nom_parse_index_header_fanaout<u32>, u32!(nom::Endianness::Big));
@@ -88,10 +111,18 @@ named!(
     )
 );
 
+/// the name of a pack index (`objects/pack/pack-<hash>.idx`): the hash of its content
+///
+/// Being a `Hash`, the hexadecimal name is given by `to_hexadecimal`.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct IndexRef<H: Hash>(H);
 impl<H: Hash> IndexRef<H> {
     pub fn new(h: H) -> Self { IndexRef(h) }
+    /// tell if the name starts with the given prefix
+    pub fn matches_prefix(&self, p: &Partial<H>) -> bool { p.is_prefix_of(&self.0) }
+}
+impl<H: Hash> PartialEq<H> for IndexRef<H> {
+    fn eq(&self, rhs: &H) -> bool { same_hash(&self.0, rhs) }
 }
 impl<H: Hash + fmt::Display> fmt::Display for IndexRef<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.0, f) }
@@ -115,9 +146,18 @@ impl<H: Hash> convert::AsRef<H> for IndexRef<H> {
     fn as_ref(&self) -> &H { &self.0 }
 }
 
+/// list the pack indexes of the repository: the `.idx` files of the
+/// `objects/pack` directory, sorted
+///
+/// See `stale_info_packs` to check the list of packs of
+/// `objects/info/packs` against them.
 pub fn list_indexes<H: Hash>(git: &GitFS) -> Result<Vec<IndexRef<H>>> {
-    get_all_files_in(
-        git.objs_dir().join("pack"),
+    let pack_dir = git.objs_dir().join("pack");
+    if ! pack_dir.is_dir() {
+        return Ok(Vec::new())
+    }
+    let mut indexes = try!(get_all_files_in(
+        pack_dir,
         & |path| {
             let path_str = format!("{}", path.display());
             if !path_str.starts_with("pack-") || !path_str.ends_with(".idx") {
@@ -127,7 +167,61 @@ pub fn list_indexes<H: Hash>(git: &GitFS) -> Result<Vec<IndexRef<H>>> {
                 Ok(IndexRef::<H>::from_hex(data))
             }
         }
-    )
+    ));
+    indexes.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    Ok(indexes)
+}
+
+/// read the packs listed in `objects/info/packs` (lines of
+/// `P pack-<hash>.pack`), an empty list if the file does not exist
+///
+/// The other lines of the file are ignored.
+pub fn read_info_packs<H: Hash>(git: &GitFS) -> Result<Vec<PackRef<H>>> {
+    use std::io::Read;
+    let path = git.objs_dir().join("info").join("packs");
+    if ! path.is_file() {
+        return Ok(Vec::new())
+    }
+    let mut content = String::new();
+    try!(try!(open_file(&path)).read_to_string(&mut content));
+    Ok(content.lines()
+              .filter_map(|l| {
+                  let l = l.trim();
+                  if l.starts_with("P pack-") && l.ends_with(".pack") {
+                      PackRef::<H>::from_hex(&l[7..l.len() - 5])
+                  } else { None }
+              })
+              .collect())
+}
+
+/// the packs listed in `objects/info/packs` which have no index in
+/// `objects/pack`: the file is out of date, a client of the dumb protocols
+/// would ask for packs which are gone (`git update-server-info` writes it
+/// again)
+pub fn stale_info_packs<H: Hash>(git: &GitFS) -> Result<Vec<PackRef<H>>> {
+    let indexes = try!(list_indexes::<H>(git));
+    Ok(try!(read_info_packs::<H>(git))
+           .into_iter()
+           .filter(|pack| ! indexes.iter().any(|idx| idx.as_bytes() == pack.as_bytes()))
+           .collect())
+}
+
+/// the positions (in the hash order) of the entries of an index, sorted by
+/// offset; computed on the first reverse lookup (see `Index::hash_at_offset`)
+///
+/// It only caches what can be computed from the index: it is ignored when
+/// comparing the indexes.
+#[derive(Debug, Clone)]
+struct OffsetOrder(RefCell<Option<Vec<usize>>>);
+impl PartialEq for OffsetOrder {
+    fn eq(&self, _: &Self) -> bool { true }
+}
+impl Eq for OffsetOrder {}
+impl PartialOrd for OffsetOrder {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+}
+impl Ord for OffsetOrder {
+    fn cmp(&self, _: &Self) -> cmp::Ordering { cmp::Ordering::Equal }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
@@ -137,7 +231,8 @@ pub struct Index<H: Hash> {
     crcs:     Vec<u32>,
     offsets:  Vec<usize>,
     pack:     PackRef<H>,
-    index:    IndexRef<H>
+    index:    IndexRef<H>,
+    by_offset: OffsetOrder
 }
 impl<H: Hash> Index<H> {
     fn new( header: Header
@@ -154,39 +249,124 @@ impl<H: Hash> Index<H> {
             crcs:    crcs,
             offsets: offsets,
             pack:    pack,
-            index:   index
+            index:   index,
+            by_offset: OffsetOrder(RefCell::new(None))
         }
     }
+
+    /// the pack file this index refers to
+    pub fn pack(&self) -> &PackRef<H> { &self.pack }
+    /// the checksum of the index file itself
+    pub fn index(&self) -> &IndexRef<H> { &self.index }
+    /// the header of the index: its version and its fanout table
+    pub fn header(&self) -> &Header { &self.header }
+
+    /// the range, in the sorted list of hashes, of the hashes starting with
+    /// the given byte (see the fanout table of the `Header`)
+    fn fanout_range(&self, first: u8) -> (usize, usize) {
+        let end = self.header.fanouts[first as usize] as usize;
+        let start = if first == 0 { 0 } else { self.header.fanouts[first as usize - 1] as usize };
+        (cmp::min(start, end), cmp::min(end, self.hashes.len()))
+    }
+
+    /// find the object with the given hash in the index: returns the crc32
+    /// of the packed object and its offset in the pack file.
+    ///
+    /// The fanout table restricts the search to the hashes sharing the same
+    /// first byte, then a binary search is done on this range.
+    pub fn find(&self, hash: &H) -> Option<(usize, usize)> {
+        let first = match hash.as_bytes().first() { Some(b) => *b, None => return None };
+        let (start, end) = self.fanout_range(first);
+        self.hashes[start..end]
+            .binary_search_by(|h| h.as_bytes().cmp(hash.as_bytes()))
+            .ok()
+            .map(|idx| (self.crcs[start + idx] as usize, self.offsets[start + idx]))
+    }
+
+    /// get the offset, in the pack file, of the object with the given hash
+    pub fn offset(&self, hash: &H) -> Option<usize> {
+        self.find(hash).map(|(_, offset)| offset)
+    }
+
+    /// call `f` with the positions of the entries sorted by offset,
+    /// computed on the first call and kept for the next ones
+    fn with_offset_order<T, F: FnOnce(&[usize]) -> T>(&self, f: F) -> T {
+        let mut by_offset = self.by_offset.0.borrow_mut();
+        if by_offset.is_none() {
+            let mut order : Vec<usize> = (0..self.offsets.len()).collect();
+            order.sort_by_key(|&idx| self.offsets[idx]);
+            *by_offset = Some(order);
+        }
+        f(by_offset.as_ref().unwrap())
+    }
+
+    /// find the hash of the object stored at the given offset of the pack
+    /// file (the reverse of `offset`)
+    ///
+    /// The index is sorted by hash: the entries sorted by offset are
+    /// computed on the first call and kept for the next ones.
+    pub fn hash_at_offset(&self, offset: usize) -> Option<&H> {
+        self.with_offset_order(|order| {
+            order.binary_search_by_key(&offset, |&idx| self.offsets[idx])
+                .ok()
+                .and_then(|pos| self.hashes.get(order[pos]))
+        })
+    }
+
+    /// the offsets and the hashes of the objects in the order they are
+    /// stored in the pack file (see `hash_at_offset`)
+    pub fn by_offset(&self) -> Vec<(usize, &H)> {
+        self.with_offset_order(|order| {
+            order.iter().map(|&idx| (self.offsets[idx], &self.hashes[idx])).collect()
+        })
+    }
+
+    /// list the hashes of the index starting with the given prefix
+    pub fn contains(&self, prefix: &Partial<H>) -> Vec<H> {
+        let hex = prefix.to_hexadecimal();
+        let first = if hex.len() >= 2 { u8::from_str_radix(&hex[..2], 16).ok() } else { None };
+        let (start, end) = match first {
+            Some(first) => self.fanout_range(first),
+            None => (0, self.hashes.len())
+        };
+        self.hashes[start..end].iter()
+            .filter(|h| prefix.is_prefix_of(*h))
+            .filter_map(|h| H::from_bytes(h.as_bytes().to_vec()))
+            .collect()
+    }
 }
 
 pub fn parse_index_file<H: Hash>(path: &path::PathBuf) -> Result<Index<H>> {
     use std::io::Read;
     let mut file = try!(open_file(&path));
     let mut s = Vec::new();
-    io_try!(file.read_to_end(&mut s));
+    try!(file.read_to_end(&mut s));
+    let header = nom_try!(nom_parse_index_header(s.as_ref()));
+    try!(header.validate());
     let index = nom_try!(parse_index(s.as_ref()));
     Ok(index)
 }
 
 pub fn parse_index<H:Hash>(i: &[u8]) -> nom::IResult<&[u8], Index<H>> {
     let (i, header)  = try_parse!(i, nom_parse_index_header);
-    if header.magic != 0xff744f63 {
-        // panic!("wrong magic {:?}", header.magic);
-        return nom::IResult::Error(nom::ErrorKind::IsNot);
-    }
-    if header.version != 2 {
-        // panic!("wrong version {:?}", header.version);
+    // see `parse_index_file` or `Header::validate` for a detailed error
+    if header.validate().is_err() {
         return nom::IResult::Error(nom::ErrorKind::IsNot);
     }
     let (i, hashes)  = try_parse!(i, count!(H::decode_bytes, header.size()));
     let (i, crcs)    = try_parse!(i, count!(u32!(nom::Endianness::Big), header.size()));
-    let (mut i, mut offsets) = try_parse!(i, count!(map!(u32!(nom::Endianness::Big), |v| v as usize), header.size()));
+    let (i, mut offsets) = try_parse!(i, count!(map!(u32!(nom::Endianness::Big), |v| v as usize), header.size()));
+    // the offsets which do not fit in 31 bits are in a table of 8 bytes
+    // offsets following the 4 bytes ones: the 31 lower bits give the
+    // position in this table
+    let large_count = offsets.iter().filter(|&&o| o & LARGE_OFFSET_FLAG != 0).count();
+    let (i, large_offsets) = try_parse!(i, count!(map!(u64!(nom::Endianness::Big), |v| v as usize), large_count));
     for offset in offsets.iter_mut() {
-        let u = *offset as u32;
-        if (u & 0x80000000) != 0 {
-            let (i_, large_offset) = try_parse!(i, map!(u64!(nom::Endianness::Big), |v| v as usize));
-            *offset = large_offset;
-            i = i_;
+        if *offset & LARGE_OFFSET_FLAG != 0 {
+            *offset = match large_offsets.get(*offset & !LARGE_OFFSET_FLAG) {
+                Some(&large_offset) => large_offset,
+                None => return nom::IResult::Error(nom::ErrorKind::Custom(0))
+            };
         }
     }
     let (i, pack) = try_parse!(i, PackRef::<H>::decode_bytes);
@@ -197,12 +377,163 @@ pub fn parse_index<H:Hash>(i: &[u8]) -> nom::IResult<&[u8], Index<H>> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use ::protocol::{Hash, SHA1};
+    use ::protocol::{Hash, SHA1, Partial};
     use ::fs::util::*;
     use std::path::PathBuf;
     use std::io::Read;
     use ::fs::GitFS;
+    use ::error::GitError;
+
+    #[test]
+    fn find_in_index() {
+        let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap();
+        let idx = list_indexes::<SHA1>(&git).unwrap().pop().unwrap();
+        let mut raw = Vec::new();
+        open_file(&git.index_file(&idx)).unwrap().read_to_end(&mut raw).unwrap();
+        let index : Index<SHA1> = parse_index(&raw).unwrap().1;
+        let (_, crc_table, offset_table) = index.header.offsets::<SHA1>();
+        let read_u32 = |at: usize| {
+            ((raw[at] as usize) << 24) | ((raw[at+1] as usize) << 16)
+                | ((raw[at+2] as usize) << 8) | (raw[at+3] as usize)
+        };
+
+        // README.md of test_ref (see test_ref/init.sh)
+        let readme = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap();
+        let position = index.hashes.iter().position(|h| h == &readme).unwrap();
+        assert_eq!( index.find(&readme)
+                  , Some((read_u32(crc_table + 4 * position), read_u32(offset_table + 4 * position)))
+                  );
+        for h in index.hashes.iter() {
+            assert!(index.find(h).is_some());
+        }
+        let missing = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9760").unwrap();
+        assert_eq!(index.find(&missing), None);
+
+        let prefix = Partial::<SHA1>::from_hex("e845566").unwrap();
+        assert_eq!(index.contains(&prefix), vec![readme]);
+        let prefix = Partial::<SHA1>::from_hex("e").unwrap();
+        assert!(index.contains(&prefix).iter().all(|h| h.to_hexadecimal().starts_with("e")));
+    }
+    #[test]
+    fn hash_at_offset() {
+        let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap();
+        let idx = list_indexes::<SHA1>(&git).unwrap().pop().unwrap();
+        let index = parse_index_file::<SHA1>(&git.index_file(&idx)).unwrap();
+        for h in index.hashes.iter() {
+            let (_, offset) = index.find(h).unwrap();
+            assert_eq!(index.hash_at_offset(offset), Some(h));
+        }
+        // the pack header is not an object
+        assert_eq!(index.hash_at_offset(0), None);
+        let by_offset = index.by_offset();
+        assert_eq!(by_offset.len(), index.hashes.len());
+        for pair in by_offset.windows(2) {
+            assert!(pair[0].0 < pair[1].0);
+            assert_eq!(index.hash_at_offset(pair[1].0), Some(pair[1].1));
+        }
+        assert_eq!(index, parse_index_file::<SHA1>(&git.index_file(&idx)).unwrap());
+    }
+    fn test_index_file(name: &str, data: &[u8]) -> PathBuf {
+        use std::io::Write;
+        let path = ::std::env::temp_dir().join("git-rs-tests").join(name);
+        ::std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        ::std::fs::File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+    fn test_header(magic: u32, version: u32, fanouts: &[u32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for v in [magic, version].iter().chain(fanouts.iter()) {
+            data.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, *v as u8]);
+        }
+        data
+    }
 
+    #[test]
+    fn parse_index_header_truncated() {
+        let data = test_header(INDEX_MAGIC, 2, &[0; 10]);
+        let path = test_index_file("truncated.idx", &data);
+        match parse_index_file::<SHA1>(&path) {
+            Err(GitError::ParsingErrorNotEnough(_)) => {},
+            r => panic!("expected a parsing error, got {:?}", r)
+        }
+    }
+    #[test]
+    fn parse_index_header_invalid() {
+        let wrong_magic = test_header(0x12345678, 2, &[0; 256]);
+        let wrong_version = test_header(INDEX_MAGIC, 1, &[0; 256]);
+        let mut fanouts = [1; 256];
+        fanouts[10] = 0;
+        let wrong_fanouts = test_header(INDEX_MAGIC, 2, &fanouts);
+        for &(name, ref data) in [ ("wrong-magic.idx", wrong_magic)
+                                 , ("wrong-version.idx", wrong_version)
+                                 , ("wrong-fanouts.idx", wrong_fanouts)
+                                 ].iter() {
+            let path = test_index_file(name, data);
+            match parse_index_file::<SHA1>(&path) {
+                Err(GitError::ParsingError(_)) => {},
+                r => panic!("expected a parsing error for {}, got {:?}", name, r)
+            }
+            assert!(parse_index::<SHA1>(data).is_err());
+        }
+    }
+    #[test]
+    fn parse_index_large_offsets() {
+        let hashes : Vec<SHA1> = (1..4u8).map(|n| SHA1::from_bytes(vec![n; 20]).unwrap()).collect();
+        let mut fanouts = [3; 256];
+        fanouts[0] = 0; fanouts[1] = 1; fanouts[2] = 2;
+        let mut data = test_header(INDEX_MAGIC, 2, &fanouts);
+        for h in hashes.iter() { data.extend_from_slice(h.as_bytes()) }
+        // the crcs, then the offsets: the first and the last ones are in
+        // the table of the large offsets (in reverse order)
+        for v in [0u32, 0, 0, 0x80000001, 12, 0x80000000].iter() {
+            data.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, *v as u8]);
+        }
+        for v in [0x1_0000_0000u64, 0x2_8000_0000].iter() {
+            data.extend((0..8).rev().map(|n| (v >> (8 * n)) as u8));
+        }
+        data.extend_from_slice(&[0xaa; 40]);
+        let index = parse_index::<SHA1>(&data).unwrap().1;
+        assert_eq!(index.offset(&hashes[0]), Some(0x2_8000_0000));
+        assert_eq!(index.offset(&hashes[1]), Some(12));
+        assert_eq!(index.offset(&hashes[2]), Some(0x1_0000_0000));
+        assert_eq!(index.pack().as_bytes(), &[0xaa; 20][..]);
+
+        // an offset referring past the table of the large offsets
+        let len = data.len();
+        data[len - 40 - 16 - 1] = 2;
+        assert!(parse_index::<SHA1>(&data).is_err());
+    }
+    #[test]
+    fn stale_info_packs_of_mirror() {
+        use std::fs;
+        use std::io::Write;
+        let git = GitFS::new(&PathBuf::from("./test_ref/.git")).unwrap();
+        let idx = list_indexes::<SHA1>(&git).unwrap().pop().unwrap();
+
+        let path = test_git_dir("list_indexes_info_packs");
+        let mirror = GitFS::new(&path).unwrap();
+        fs::create_dir_all(path.join("objects").join("pack")).unwrap();
+        fs::copy(git.index_file(&idx), mirror.index_file(&idx)).unwrap();
+        assert_eq!(list_indexes::<SHA1>(&mirror).unwrap(), vec![idx.clone()]);
+
+        let missing = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
+        fs::create_dir_all(path.join("objects").join("info")).unwrap();
+        fs::File::create(path.join("objects").join("info").join("packs")).unwrap()
+            .write_all(format!("P pack-{}.pack\nP pack-{}.pack\n\n", idx, missing).as_bytes())
+            .unwrap();
+        let packs = read_info_packs::<SHA1>(&mirror).unwrap();
+        assert_eq!(packs.len(), 2);
+        assert_eq!(packs[1].to_hexadecimal(), missing);
+        // only the pack without index is reported
+        let stale = stale_info_packs::<SHA1>(&mirror).unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].to_hexadecimal(), missing);
+        assert_eq!(list_indexes::<SHA1>(&mirror).unwrap(), vec![idx.clone()]);
+
+        // once the index is removed, its pack is stale too
+        fs::remove_file(mirror.index_file(&idx)).unwrap();
+        assert_eq!(stale_info_packs::<SHA1>(&mirror).unwrap().len(), 2);
+    }
     #[test]
     fn parse_all() {
         let path = PathBuf::new().join(".git");
@@ -215,4 +546,12 @@ mod test {
             println!("{:?}", index.header);
         }
     }
+    #[test]
+    fn index_ref_hash_and_prefix() {
+        let h = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap();
+        let idx = IndexRef::new(h.clone());
+        assert!(idx == h);
+        assert!(idx.matches_prefix(&Partial::from_hex("e84556").unwrap()));
+        assert!(! idx.matches_prefix(&Partial::from_hex("f8").unwrap()));
+    }
 }
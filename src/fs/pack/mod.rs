@@ -1,14 +1,24 @@
 use std::{fmt, convert, io};
+use std::io::Read;
 
-use ::protocol::Hash;
-use error::{Result};
+use ::protocol::{Hash, Partial, ZlibDecoder, same_hash};
+use ::object::ObjectKind;
+use error::{Result, GitError};
 
 pub mod index;
 
+/// the name of a pack file (`objects/pack/pack-<hash>.pack`): the hash of its content
+///
+/// Being a `Hash`, the hexadecimal name is given by `to_hexadecimal`.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct PackRef<H: Hash>(H);
 impl<H: Hash> PackRef<H> {
     pub fn new(h: H) -> Self { PackRef(h) }
+    /// tell if the name starts with the given prefix
+    pub fn matches_prefix(&self, p: &Partial<H>) -> bool { p.is_prefix_of(&self.0) }
+}
+impl<H: Hash> PartialEq<H> for PackRef<H> {
+    fn eq(&self, rhs: &H) -> bool { same_hash(&self.0, rhs) }
 }
 impl<H: Hash + fmt::Display> fmt::Display for PackRef<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.0, f) }
@@ -31,3 +41,291 @@ impl<H: Hash> Hash for PackRef<H> {
 impl<H: Hash> convert::AsRef<H> for PackRef<H> {
     fn as_ref(&self) -> &H { &self.0 }
 }
+
+/// type of an object stored in a pack file
+///
+/// On top of the usual git objects, a pack may contain *deltified* objects:
+/// objects stored as a patch against a base object. The base is either
+/// given by its offset in the pack (`OfsDelta`) or by its hash (`RefDelta`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PackObjectType { Commit, Tree, Blob, Tag, OfsDelta, RefDelta }
+impl PackObjectType {
+    fn from_u8(t: u8) -> Option<Self> {
+        match t {
+            1 => Some(PackObjectType::Commit),
+            2 => Some(PackObjectType::Tree),
+            3 => Some(PackObjectType::Blob),
+            4 => Some(PackObjectType::Tag),
+            6 => Some(PackObjectType::OfsDelta),
+            7 => Some(PackObjectType::RefDelta),
+            _ => None
+        }
+    }
+
+    /// the name of the object type as written in the object header
+    /// (`None` for the deltified objects).
+    pub fn name(&self) -> Option<&'static str> { self.kind().map(|k| k.as_str()) }
+
+    /// the kind of the object (`None` for the deltified objects)
+    pub fn kind(&self) -> Option<ObjectKind> {
+        match self {
+            &PackObjectType::Commit => Some(ObjectKind::Commit),
+            &PackObjectType::Tree   => Some(ObjectKind::Tree),
+            &PackObjectType::Blob   => Some(ObjectKind::Blob),
+            &PackObjectType::Tag    => Some(ObjectKind::Tag),
+            &PackObjectType::OfsDelta | &PackObjectType::RefDelta => None
+        }
+    }
+
+    /// the object type from its name, as written in the object header
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"commit" => Some(PackObjectType::Commit),
+            b"tree"   => Some(PackObjectType::Tree),
+            b"blob"   => Some(PackObjectType::Blob),
+            b"tag"    => Some(PackObjectType::Tag),
+            _ => None
+        }
+    }
+}
+
+/// information about an object of a pack file, as `git verify-pack -v`
+/// lists them (see `GitFS::iter_pack_objects`)
+///
+/// * `size`: the size of the object once inflated (for the deltified
+///   objects: the size of the delta, not of the object);
+/// * `packed_size`: the number of bytes of the object in the pack file
+///   (header included).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackObjectInfo<H: Hash> {
+    pub hash: H,
+    pub offset: usize,
+    pub kind: PackObjectType,
+    pub size: usize,
+    pub packed_size: usize
+}
+
+/// maximum number of bytes reserved up front for an inflated object or the
+/// result of a delta: their sizes come from the pack, which may be corrupt,
+/// so bigger buffers grow as the data is actually read.
+const MAX_PREALLOC : usize = 1 << 20;
+
+fn read_byte<R: Read>(r: &mut R) -> Result<u8> {
+    let mut byte = [0u8;1];
+    try!(r.read_exact(&mut byte));
+    Ok(byte[0])
+}
+
+/// read the header of a packed object: its type and its size (once
+/// inflated).
+///
+/// The header is a variable length integer: the first byte holds the type
+/// (bits 4 to 6) and the 4 lower bits of the size, then each byte adds 7
+/// more bits of the size as long as its most significant bit is set.
+pub fn read_object_header<R: Read>(r: &mut R) -> Result<(PackObjectType, usize)> {
+    let mut c = try!(read_byte(r));
+    let ty = match PackObjectType::from_u8((c >> 4) & 0x07) {
+        Some(ty) => ty,
+        None => return Err(GitError::ParsingError(format!("unknown pack object type: {}", (c >> 4) & 0x07)))
+    };
+    let mut size = (c & 0x0f) as usize;
+    let mut shift = 4;
+    while c & 0x80 != 0 {
+        if shift >= usize::BITS {
+            return Err(GitError::ParsingError("pack object size too large".to_string()))
+        }
+        c = try!(read_byte(r));
+        size |= ((c & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+    Ok((ty, size))
+}
+
+/// inflate the zlib compressed data of a packed object
+///
+/// `size` is the expected size of the inflated data (as read in the
+/// object header).
+pub fn inflate<R: Read>(r: R, size: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(::std::cmp::min(size, MAX_PREALLOC));
+    try!(ZlibDecoder::new(r).take(size as u64).read_to_end(&mut data));
+    if data.len() != size {
+        return Err(GitError::ParsingErrorNotEnough(Some(size - data.len())))
+    }
+    Ok(data)
+}
+
+/// read the offset of the base object of an `OfsDelta` object
+///
+/// This is the distance, backward, from the deltified object. Unlike the
+/// object size, the offset is encoded with the most significant bits first,
+/// and 1 is added to each continuation byte so each offset has only one
+/// encoding.
+pub fn read_ofs_delta_offset<R: Read>(r: &mut R) -> Result<usize> {
+    let mut c = try!(read_byte(r));
+    let mut offset = (c & 0x7f) as usize;
+    while c & 0x80 != 0 {
+        c = try!(read_byte(r));
+        offset = match offset.checked_add(1).and_then(|o| o.checked_mul(1 << 7)) {
+            Some(o) => o | (c & 0x7f) as usize,
+            None => return Err(GitError::ParsingError("ofs-delta offset too large".to_string()))
+        };
+    }
+    Ok(offset)
+}
+
+/// read the sizes at the beginning of a delta (7 bits per byte, least
+/// significant bits first).
+fn delta_size(delta: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        if shift >= usize::BITS {
+            return Err(GitError::ParsingError("delta size too large".to_string()))
+        }
+        let c = match delta.get(*pos) {
+            Some(c) => *c,
+            None => return Err(GitError::ParsingErrorNotEnough(None))
+        };
+        *pos += 1;
+        size |= ((c & 0x7f) as usize) << shift;
+        shift += 7;
+        if c & 0x80 == 0 { return Ok(size) }
+    }
+}
+
+/// apply a delta (the inflated data of a deltified object) on top of the
+/// content of its base object.
+///
+/// The delta starts with the size of the base and the size of the result,
+/// followed by a list of instructions:
+///
+/// * copy (most significant bit set): copy a slice of the base, the lower
+///   bits telling which bytes of the offset and of the size are present;
+/// * insert (most significant bit not set): insert the following `n` bytes
+///   of the delta.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = try!(delta_size(delta, &mut pos));
+    if base_size != base.len() {
+        return Err(GitError::ParsingError(
+            format!("delta expects a base of {} bytes, got {}", base_size, base.len())
+        ))
+    }
+    let result_size = try!(delta_size(delta, &mut pos));
+    let mut result = Vec::with_capacity(::std::cmp::min(result_size, MAX_PREALLOC));
+    while pos < delta.len() {
+        let cmd = delta[pos];
+        pos += 1;
+        if cmd & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..7 {
+                if cmd & (1 << i) == 0 { continue }
+                let c = match delta.get(pos) {
+                    Some(c) => *c as usize,
+                    None => return Err(GitError::ParsingErrorNotEnough(None))
+                };
+                pos += 1;
+                if i < 4 { offset |= c << (8 * i) } else { size |= c << (8 * (i - 4)) }
+            }
+            if size == 0 { size = 0x10000 }
+            if offset + size > base.len() {
+                return Err(GitError::OutOfBound(offset + size, base.len()))
+            }
+            result.extend_from_slice(&base[offset..offset + size]);
+        } else if cmd != 0 {
+            let size = cmd as usize;
+            if pos + size > delta.len() {
+                return Err(GitError::ParsingErrorNotEnough(Some(pos + size - delta.len())))
+            }
+            result.extend_from_slice(&delta[pos..pos + size]);
+            pos += size;
+        } else {
+            return Err(GitError::ParsingError("invalid delta instruction: 0".to_string()))
+        }
+    }
+    if result.len() != result_size {
+        return Err(GitError::ParsingError(
+            format!("delta expects a result of {} bytes, got {}", result_size, result.len())
+        ))
+    }
+    Ok(result)
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::SHA1;
+
+    #[test]
+    fn pack_ref_hash_and_prefix() {
+        let h = SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap();
+        let pack = PackRef::new(h.clone());
+        assert!(pack == h);
+        assert!(pack != SHA1::empty_blob());
+        assert_eq!(pack.to_hexadecimal(), h.to_hexadecimal());
+        assert!(pack.matches_prefix(&Partial::from_hex("e845566").unwrap()));
+        assert!(pack.matches_prefix(&Partial::from_hex("e").unwrap()));
+        assert!(! pack.matches_prefix(&Partial::from_hex("e845567").unwrap()));
+    }
+
+    #[test]
+    fn apply_delta_copy_insert() {
+        let base = b"hello world, this is the base";
+        // base size: 29, result size: 23
+        let delta = [ 29, 23
+                    // copy 6 bytes from offset 0: "hello "
+                    , 0x80 | 0x10, 6
+                    // insert "git"
+                    , 3, b'g', b'i', b't'
+                    // copy 14 bytes from offset 11: ", this is the "
+                    , 0x80 | 0x01 | 0x10, 11, 14
+                    ];
+        assert_eq!( apply_delta(base, &delta).unwrap()
+                  , b"hello git, this is the ".to_vec()
+                  );
+    }
+    #[test]
+    fn apply_delta_invalid() {
+        let base = b"base";
+        // wrong base size
+        assert!(apply_delta(base, &[5, 1, 1, b'a']).is_err());
+        // copy out of the base
+        assert!(apply_delta(base, &[4, 4, 0x80 | 0x01 | 0x10, 2, 4]).is_err());
+        // result size does not match
+        assert!(apply_delta(base, &[4, 2, 1, b'a']).is_err());
+    }
+    #[test]
+    fn ofs_delta_offset() {
+        assert_eq!(read_ofs_delta_offset(&mut &[0x05u8][..]).unwrap(), 5);
+        // (((0x01 + 1) << 7) | 0x00)
+        assert_eq!(read_ofs_delta_offset(&mut &[0x81u8, 0x00][..]).unwrap(), 256);
+        // too many continuation bytes for an usize
+        assert!(read_ofs_delta_offset(&mut &[0xffu8; 16][..]).is_err());
+    }
+    #[test]
+    fn overlong_sizes() {
+        let mut header = vec![0x80 | 0x30];
+        header.extend_from_slice(&[0xff; 16]);
+        header.push(0x01);
+        assert!(read_object_header(&mut header.as_slice()).is_err());
+
+        let mut delta = vec![0xff; 16];
+        delta.push(0x01);
+        assert!(apply_delta(b"", &delta).is_err());
+    }
+    #[test]
+    fn huge_announced_sizes() {
+        // the sizes are not trusted to allocate the buffers
+        assert!(inflate(&[][..], usize::MAX).is_err());
+        let mut delta = vec![0];
+        delta.extend_from_slice(&[0xff; 9]);
+        delta.push(0x01);
+        delta.extend_from_slice(&[1, b'a']);
+        assert!(apply_delta(b"", &delta).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! Git's reference logs (`.git/logs/HEAD`, `.git/logs/refs/heads/...`)
+//!
+//! Every update of a reference is logged in a line:
+//! `<old hash> <new hash> <committer> <date>\t<message>`.
+
+use nom;
+
+use object::Person;
+use protocol::{Hash, Decoder};
+
+/// an entry of the log of a reference
+///
+/// `old` is the null hash (`0000...`) when the reference was created.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ReflogEntry<H: Hash> {
+    pub old: H,
+    pub new: H,
+    pub committer: Person,
+    pub message: String
+}
+
+fn nom_parse_reflog_entry<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], ReflogEntry<H>> {
+    let (b, old) = try_parse!(b, H::decode_hex);
+    let (b, _) = try_parse!(b, tag!(" "));
+    let (b, new) = try_parse!(b, H::decode_hex);
+    let (b, _) = try_parse!(b, tag!(" "));
+    let (b, committer) = try_parse!(b, Person::decode);
+    // the message is optional
+    // (decoded lossily: one message which is not UTF-8 is not worth losing
+    // the rest of the log)
+    let (b, message) = try_parse!(b, opt!(preceded!( tag!("\t")
+                                                   , map!(take_until!("\n"), String::from_utf8_lossy)
+                                                   )));
+    let (b, _) = try_parse!(b, tag!("\n"));
+    nom::IResult::Done(
+        b,
+        ReflogEntry {
+            old: old,
+            new: new,
+            committer: committer,
+            message: message.map(|m| m.into_owned()).unwrap_or(String::new())
+        }
+    )
+}
+
+/// parse the content of a reflog file, the entries are returned in the
+/// order of the file (the oldest first)
+pub fn parse_reflog<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Vec<ReflogEntry<H>>> {
+    many0!(b, nom_parse_reflog_entry)
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::SHA1;
+
+    const REFLOG : &'static str =
+        "0000000000000000000000000000000000000000 251fd8e7f2482bda4b0c0186dd7bdc98b52fc955 \
+         Test <git-test@example.com> 1480007832 +0000\tcommit (initial): initial commit\n\
+         251fd8e7f2482bda4b0c0186dd7bdc98b52fc955 a5f0d4ba4a55af51346a0fb401bc291833d62711 \
+         Test <git-test@example.com> 1480007900 +0100\n";
+
+    #[test]
+    fn parse_reflog_entries() {
+        let (rest, entries) = parse_reflog::<SHA1>(REFLOG.as_bytes()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].old.to_hexadecimal(), "0000000000000000000000000000000000000000");
+        assert_eq!(entries[0].new.to_hexadecimal(), "251fd8e7f2482bda4b0c0186dd7bdc98b52fc955");
+        assert_eq!(entries[0].committer.name(), "Test");
+        assert_eq!(entries[0].message, "commit (initial): initial commit");
+        assert_eq!(entries[1].old, entries[0].new);
+        assert_eq!(entries[1].message, "");
+    }
+}
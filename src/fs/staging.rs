@@ -0,0 +1,201 @@
+//! Git's index file (the staging area: `.git/index`)
+//!
+//! The index lists the files of the working directory known by git, with
+//! the hash of their content and some of their metadata (to detect quickly
+//! the modified files). Only the version 2 and 3 of the format are
+//! supported, the extensions (cached tree...) are ignored.
+
+use std::{str, path};
+use nom;
+
+use protocol::Hash;
+
+const INDEX_SIGNATURE : &'static [u8] = b"DIRC";
+/// size of the fixed part of an entry (without the hash and the path):
+/// the 10 stat fields and the flags
+const ENTRY_FIXED_SIZE : usize = 10 * 4 + 2;
+
+/// flag of the entries with an extra 16 bits of flags (version 3 only)
+const FLAG_EXTENDED : u16 = 0x4000;
+const FLAG_STAGE_MASK : u16 = 0x3000;
+const FLAG_STAGE_SHIFT : u16 = 12;
+
+/// an entry of the staging index
+///
+/// The times are given as `(seconds, nanoseconds)` since the epoch. The
+/// `mode` is the git mode of the file (`0o100644`, `0o120000`...).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct IndexEntry<H: Hash> {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    pub hash: H,
+    pub flags: u16,
+    pub extended_flags: u16,
+    pub path: path::PathBuf
+}
+impl<H: Hash> IndexEntry<H> {
+    /// the merge stage of the entry: 0 for a normal entry, 1 to 3 for the
+    /// entries of a conflicting merge (base, ours, theirs)
+    pub fn stage(&self) -> u16 { (self.flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT }
+}
+
+/// the staging index: the list of the entries sorted by path (and stage)
+///
+/// See `GitFS::read_index`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct StagingIndex<H: Hash> {
+    version: u32,
+    entries: Vec<IndexEntry<H>>
+}
+impl<H: Hash> StagingIndex<H> {
+    /// the version of the index file format
+    pub fn version(&self) -> u32 { self.version }
+    pub fn entries(&self) -> &[IndexEntry<H>] { self.entries.as_slice() }
+    pub fn iter(&self) -> ::std::slice::Iter<IndexEntry<H>> { self.entries.iter() }
+    pub fn len(&self) -> usize { self.entries.len() }
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+    /// get the entry of the given path (stage 0)
+    pub fn get<P: AsRef<path::Path>>(&self, p: P) -> Option<&IndexEntry<H>> {
+        self.entries.iter().find(|e| e.path.as_path() == p.as_ref() && e.stage() == 0)
+    }
+}
+
+named!(nom_parse_u32<u32>, u32!(nom::Endianness::Big));
+named!(nom_parse_u16<u16>, u16!(nom::Endianness::Big));
+named!( nom_parse_staging_index_header<(u32, u32)>
+      , do_parse!( tag!(INDEX_SIGNATURE)
+                 >> version: nom_parse_u32
+                 >> count: nom_parse_u32
+                 >> (version, count)
+                 )
+      );
+
+fn nom_parse_index_entry<H: Hash>(b: &[u8], version: u32) -> nom::IResult<&[u8], IndexEntry<H>> {
+    let start = b.len();
+    let (b, ctime_s) = try_parse!(b, nom_parse_u32);
+    let (b, ctime_ns) = try_parse!(b, nom_parse_u32);
+    let (b, mtime_s) = try_parse!(b, nom_parse_u32);
+    let (b, mtime_ns) = try_parse!(b, nom_parse_u32);
+    let (b, dev) = try_parse!(b, nom_parse_u32);
+    let (b, ino) = try_parse!(b, nom_parse_u32);
+    let (b, mode) = try_parse!(b, nom_parse_u32);
+    let (b, uid) = try_parse!(b, nom_parse_u32);
+    let (b, gid) = try_parse!(b, nom_parse_u32);
+    let (b, size) = try_parse!(b, nom_parse_u32);
+    if b.len() < H::digest_size() {
+        return nom::IResult::Incomplete(nom::Needed::Size(H::digest_size() - b.len()));
+    }
+    let (b, hash) = try_parse!(b, H::decode_bytes);
+    let (b, flags) = try_parse!(b, nom_parse_u16);
+    let (b, extended_flags) =
+        if flags & FLAG_EXTENDED != 0 {
+            if version < 3 {
+                return nom::IResult::Error(nom::ErrorKind::Custom(0));
+            }
+            try_parse!(b, nom_parse_u16)
+        } else { (b, 0) };
+    let (b, p) = try_parse!(b, map_res!(take_until_and_consume!("\0"), str::from_utf8));
+    // the entry is padded with 1 to 8 NUL bytes (including the one ending
+    // the path) to a multiple of 8 bytes
+    let read = start - b.len();
+    let padding = (8 - read % 8) % 8;
+    if b.len() < padding {
+        return nom::IResult::Incomplete(nom::Needed::Size(padding - b.len()));
+    }
+    nom::IResult::Done(
+        &b[padding..],
+        IndexEntry {
+            ctime: (ctime_s, ctime_ns),
+            mtime: (mtime_s, mtime_ns),
+            dev: dev, ino: ino, mode: mode, uid: uid, gid: gid, size: size,
+            hash: hash,
+            flags: flags,
+            extended_flags: extended_flags,
+            path: path::PathBuf::from(p)
+        }
+    )
+}
+
+/// parse the content of the index file
+///
+/// The extensions and the trailing checksum are not parsed: they are left
+/// in the returned remaining bytes.
+pub fn parse_staging_index<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], StagingIndex<H>> {
+    let (mut b, (version, count)) = try_parse!(b, nom_parse_staging_index_header);
+    if version != 2 && version != 3 {
+        return nom::IResult::Error(nom::ErrorKind::Custom(version));
+    }
+    // do not trust the count to reserve the memory: an entry takes at
+    // least ENTRY_FIXED_SIZE bytes
+    let mut entries = Vec::with_capacity(::std::cmp::min(count as usize, b.len() / ENTRY_FIXED_SIZE));
+    for _ in 0..count {
+        let (i, entry) = try_parse!(b, apply!(nom_parse_index_entry, version));
+        entries.push(entry);
+        b = i;
+    }
+    nom::IResult::Done(b, StagingIndex { version: version, entries: entries })
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::{SHA1, Hash};
+
+    fn entry(path: &str, flags: u16, extended: Option<u16>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for v in [1u32, 2, 3, 4, 5, 6, 0o100644, 1000, 1000, 7].iter() {
+            data.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, *v as u8]);
+        }
+        data.extend_from_slice(SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap().as_bytes());
+        data.extend_from_slice(&[(flags >> 8) as u8, flags as u8]);
+        if let Some(e) = extended {
+            data.extend_from_slice(&[(e >> 8) as u8, e as u8]);
+        }
+        data.extend_from_slice(path.as_bytes());
+        data.push(0);
+        while data.len() % 8 != 0 { data.push(0) }
+        data
+    }
+
+    #[test]
+    fn parse_staging_index_entries() {
+        let mut data = b"DIRC\0\0\0\x03\0\0\0\x02".to_vec();
+        data.extend(entry("README.md", 9, None));
+        data.extend(entry("src/main.rs", FLAG_EXTENDED | 0x2000 | 11, Some(0x2000)));
+        let index : StagingIndex<SHA1> = parse_staging_index(&data).unwrap().1;
+        assert_eq!(index.version(), 3);
+        assert_eq!(index.len(), 2);
+        let readme = index.get("README.md").unwrap();
+        assert_eq!(readme.mtime, (3, 4));
+        assert_eq!(readme.mode, 0o100644);
+        assert_eq!(readme.size, 7);
+        assert_eq!(readme.hash.to_hexadecimal(), "e845566c06f9bf557d35e8292c37cf05d97a9769");
+        let main = &index.entries()[1];
+        assert_eq!(main.path, path::PathBuf::from("src/main.rs"));
+        assert_eq!(main.stage(), 2);
+        assert_eq!(main.extended_flags, 0x2000);
+        assert!(index.get("src/main.rs").is_none());
+    }
+
+    #[test]
+    fn parse_staging_index_invalid() {
+        assert!(parse_staging_index::<SHA1>(b"DIRD\0\0\0\x02\0\0\0\0").is_err());
+        assert!(parse_staging_index::<SHA1>(b"DIRC\0\0\0\x04\0\0\0\0").is_err());
+        let mut data = b"DIRC\0\0\0\x02\0\0\0\x01".to_vec();
+        data.extend(entry("README.md", FLAG_EXTENDED | 9, Some(0)));
+        assert!(parse_staging_index::<SHA1>(&data).is_err());
+        let mut data = b"DIRC\0\0\0\x02\0\0\0\x02".to_vec();
+        data.extend(entry("README.md", 9, None));
+        assert!(parse_staging_index::<SHA1>(&data).is_incomplete());
+    }
+}
@@ -0,0 +1,37 @@
+//! statistics of the object store of a repository
+
+use object::ObjectKind;
+
+/// count of the objects of a repository (as `git count-objects -v`) and
+/// of their kinds
+///
+/// An object stored both loose and packed is counted in `loose_objects`
+/// and in `packed_objects` but only once in the counts by kind.
+///
+/// See `GitFS::stats`.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct RepoStats {
+    pub loose_objects: usize,
+    pub packed_objects: usize,
+    pub packs: usize,
+    /// the size, in bytes, of the pack files on disk (without the indexes)
+    pub pack_size: u64,
+    pub commits: usize,
+    pub trees: usize,
+    pub blobs: usize,
+    pub tags: usize
+}
+impl RepoStats {
+    /// count one more object of the given kind
+    pub fn add_kind(&mut self, kind: ObjectKind) {
+        match kind {
+            ObjectKind::Commit => self.commits += 1,
+            ObjectKind::Tree   => self.trees += 1,
+            ObjectKind::Blob   => self.blobs += 1,
+            ObjectKind::Tag    => self.tags += 1
+        }
+    }
+
+    /// the number of distinct objects (the sum of the counts by kind)
+    pub fn objects(&self) -> usize { self.commits + self.trees + self.blobs + self.tags }
+}
@@ -1,32 +1,111 @@
 use std::path::*;
-use std::fs::{File};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::str;
 use std::collections::VecDeque;
 
 use refs::{SpecRef};
 use error::{Result, GitError};
+use object::ObjectKind;
 
 /// convenient function to open a file or wrap up the error into a
 /// the Git Error.
 pub fn open_file(path: &PathBuf) -> Result<File> {
-    File::open(path)
-        .map_err(|err| GitError::ioerror(err))
+    Ok(try!(File::open(path)))
+}
+
+/// read the header of a loose object (`<type> <size>\0`) from the
+/// inflated object data, leaving the reader at the start of the content.
+pub fn read_loose_header<R: Read>(r: &mut R) -> Result<(ObjectKind, usize)> {
+    let mut header = Vec::new();
+    loop {
+        let mut c = [0u8; 1];
+        if try!(r.read(&mut c)) == 0 {
+            return Err(GitError::ParsingErrorNotEnough(None))
+        }
+        if c[0] == 0 { break }
+        header.push(c[0]);
+        // the longest header is `commit ` followed by the size in decimal
+        if header.len() > 32 {
+            return Err(GitError::ParsingError("invalid object header".to_string()))
+        }
+    }
+    let kind = match ObjectKind::from_tag_bytes(&header) {
+        Some(kind) => kind,
+        None => return Err(GitError::ParsingError("unknown object type".to_string()))
+    };
+    match header.splitn(2, |&c| c == b' ').nth(1).and_then(|s| str::from_utf8(s).ok()).and_then(|s| s.parse().ok()) {
+        Some(size) => Ok((kind, size)),
+        None => Err(GitError::ParsingError("invalid object size".to_string()))
+    }
+}
+
+/// lock file used to update a file of the git repository atomically
+///
+/// This follows git's convention: the new content is written in
+/// `<file>.lock` (created exclusively, so only one writer can hold it)
+/// and then renamed on top of `<file>` on `commit`. If the `LockFile` is
+/// dropped without being committed, the lock is released and the original
+/// file left untouched.
+pub struct LockFile {
+    path: PathBuf,
+    lock: PathBuf,
+    file: Option<File>
+}
+impl LockFile {
+    /// acquire the lock on the given file path
+    ///
+    /// Fails with an `IoError` if the lock is already held.
+    pub fn acquire<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock = path.clone().into_os_string();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        if let Some(parent) = path.parent() {
+            try!(fs::create_dir_all(parent));
+        }
+        let file = try!(OpenOptions::new().write(true).create_new(true).open(&lock));
+        Ok(LockFile { path: path, lock: lock, file: Some(file) })
+    }
+
+    /// write the new content in the lock file
+    pub fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        match self.file {
+            Some(ref mut file) => Ok(try!(file.write_all(data))),
+            None => Err(GitError::Other("lock file already released".to_string()))
+        }
+    }
+
+    /// replace the locked file with the new content and release the lock
+    pub fn commit(mut self) -> Result<()> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Err(GitError::Other("lock file already released".to_string()))
+        };
+        file.sync_all()
+            .and_then(|()| fs::rename(&self.lock, &self.path))
+            .map_err(|err| {
+                let _ = fs::remove_file(&self.lock);
+                GitError::ioerror(err)
+            })
+    }
+}
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
 }
 
 pub fn append_dir_to_queue<P>(queue: &mut VecDeque<PathBuf>, path: P)
     -> Result<()>
     where P: AsRef<Path>
 {
-    path.as_ref().read_dir()
-        .map_err(|err| GitError::ioerror(err))
-        .map(|l| {
-            l.fold(queue, |queue, d| {
-                // TODO: the error is ignored... this is not what we want
-                // we need to propagate the error if something wrong happened.
-                let _ = d.map_err(|err| GitError::ioerror(err))
-                         .map(|dir| queue.push_back(dir.path()));
-                queue
-            });
-        })
+    for entry in try!(path.as_ref().read_dir()) {
+        queue.push_back(try!(entry).path());
+    }
+    Ok(())
 }
 
 /// helper to list all files present in a directories and its subdirectories
@@ -41,7 +120,10 @@ pub fn get_all_files_in<T, P>( parent_path: T
     let full_path = parent_path.as_ref();
     try!(append_dir_to_queue(&mut queue, &full_path));
     while let Some(dir) = queue.pop_front() {
-        if dir.is_file() {
+        // `is_file` and `is_dir` would hide the errors (an entry we cannot
+        // read would be skipped)
+        let metadata = try!(fs::metadata(&dir));
+        if metadata.is_file() {
             let b = match dir.strip_prefix(&parent_path) {
                 Err(err) => return Err(GitError::Other(format!("{:?}", err))),
                 Ok(b) => b
@@ -49,9 +131,34 @@ pub fn get_all_files_in<T, P>( parent_path: T
             if let Some(data) = try!(make_specref(b)) {
                 array.push(data);
             }
-        } else if dir.is_dir() {
+        } else if metadata.is_dir() {
             try!(append_dir_to_queue(&mut queue, &dir));
         }
     }
     Ok(array)
 }
+
+/// create a fresh, empty, git directory in the temporary directory
+///
+/// Any previous content is removed so each test starts from a clean state.
+/// The `HEAD` points to the (unborn) `master` branch.
+#[cfg(test)]
+pub fn test_git_dir(name: &str) -> PathBuf {
+    use std::env;
+    let path = env::temp_dir().join("git-rs-tests").join(name).join(".git");
+    let _ = fs::remove_dir_all(&path);
+    for dir in ["refs/heads", "refs/tags", "objects", "info", "hooks"].iter() {
+        fs::create_dir_all(path.join(dir)).expect("create test git directory");
+    }
+    let files = [ ("config", "[core]\n\trepositoryformatversion = 0\n")
+                , ("description", "test repository\n")
+                , ("HEAD", "ref: refs/heads/master\n")
+                ];
+    for &(file, content) in files.iter() {
+        File::create(path.join(file))
+            .and_then(|mut f| f.write_all(content.as_bytes()))
+            .expect("create test git file");
+    }
+    path
+}
+
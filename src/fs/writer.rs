@@ -0,0 +1,111 @@
+//! streaming writer of loose objects
+
+use std::io::{self, Write};
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use error::{Result, GitError};
+use protocol::{Hash, HashWriter};
+use protocol::flate2::Compression;
+use protocol::flate2::write::ZlibEncoder;
+
+/// counter used to give a unique name to the temporary files of the
+/// writers of this process
+static TMP_COUNTER : AtomicUsize = AtomicUsize::new(0);
+
+/// writer of a loose object, without holding the object in memory
+///
+/// The raw object (`<type> <size>\0<content>`, as written by
+/// `Encoder::encode`) is hashed and compressed as it is written into a
+/// temporary file of the objects directory. On `finish` the temporary file
+/// is renamed into `objects/xx/yyyy...` (or removed if the object is
+/// already in the repository).
+///
+/// If the writer is dropped without being finished, the temporary file is
+/// removed.
+///
+/// See `GitFS::object_writer`.
+pub struct ObjectSinkWriter<H: Hash> {
+    objects: PathBuf,
+    tmp: PathBuf,
+    /// the compressor and the hash of the data written so far (`None` once
+    /// finished)
+    sinks: Option<(ZlibEncoder<File>, HashWriter<H>)>
+}
+impl<H: Hash> ObjectSinkWriter<H> {
+    /// create a new temporary object in the given objects directory
+    pub fn new(objects: PathBuf) -> Result<Self> {
+        let hasher = try!(HashWriter::try_new());
+        try!(fs::create_dir_all(&objects));
+        loop {
+            let n = TMP_COUNTER.fetch_add(1, Ordering::SeqCst);
+            let tmp = objects.join(format!("tmp_obj_{}_{}", process::id(), n));
+            match OpenOptions::new().write(true).create_new(true).open(&tmp) {
+                Ok(file) => return Ok(ObjectSinkWriter {
+                    objects: objects,
+                    tmp: tmp,
+                    sinks: Some((ZlibEncoder::new(file, Compression::Default), hasher))
+                }),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(GitError::ioerror(err))
+            }
+        }
+    }
+
+    /// flush the compressed object and move it to its place in the objects
+    /// directory
+    pub fn finish(mut self) -> Result<H> {
+        let (encoder, hasher) = match self.sinks.take() {
+            Some(sinks) => sinks,
+            None => return Err(GitError::Other("object writer already finished".to_string()))
+        };
+        let result = self.finish_(encoder, hasher.finish());
+        if result.is_err() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+        result
+    }
+    fn finish_(&self, encoder: ZlibEncoder<File>, hash: H) -> Result<H> {
+        let file = try!(encoder.finish());
+        try!(file.sync_all());
+        drop(file);
+        let hex = hash.to_hexadecimal();
+        let dir = self.objects.join(&hex[..2]);
+        let path = dir.join(&hex[2..]);
+        if path.is_file() {
+            try!(fs::remove_file(&self.tmp));
+        } else {
+            try!(fs::create_dir_all(&dir));
+            try!(fs::rename(&self.tmp, &path));
+        }
+        Ok(hash)
+    }
+}
+impl<H: Hash> Write for ObjectSinkWriter<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sinks {
+            Some((ref mut encoder, ref mut hasher)) => {
+                let n = try!(encoder.write(buf));
+                // only hash what the encoder accepted
+                try!(hasher.write_all(&buf[..n]));
+                Ok(n)
+            },
+            None => Err(io::Error::new(io::ErrorKind::Other, "object writer already finished"))
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self.sinks {
+            Some((ref mut encoder, _)) => encoder.flush(),
+            None => Ok(())
+        }
+    }
+}
+impl<H: Hash> Drop for ObjectSinkWriter<H> {
+    fn drop(&mut self) {
+        if self.sinks.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
@@ -8,6 +8,7 @@ extern crate rustc_serialize;
 
 #[macro_use]
 extern crate nom;
+extern crate either;
 
 #[macro_use]
 mod error;
@@ -15,5 +16,7 @@ pub mod protocol;
 pub mod object;
 pub mod refs;
 pub mod fs;
+pub mod mem;
 
 pub use error::{Result, GitError};
+pub use either::Either;
@@ -0,0 +1,176 @@
+//! in memory repository
+//!
+//! Nothing is written on the disk: the objects and the references only live
+//! as long as the `MemRepo`. This is meant to build fixtures in the tests
+//! of the code using a `Repo`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use error::{Result, GitError};
+use object::ObjectKind;
+use protocol::{Repo, ObjectSource, Hash, Partial, Encoder};
+use refs::{SpecRef, Ref};
+
+/// repository stored in memory
+///
+/// The objects are stored encoded (`<type> <size>\0<content>`), keyed by
+/// their hash in hexadecimal.
+///
+/// # Example
+///
+/// ```
+/// use git::mem::MemRepo;
+/// use git::object::{Blob, BlobRef};
+/// use git::protocol::{Repo, SHA1, Hash};
+///
+/// let mut repo = MemRepo::<SHA1>::new();
+/// let h = repo.write_object(&Blob::new(b"hello\n".to_vec())).unwrap();
+/// assert_eq!(h.to_hexadecimal(), "ce013625030ba8dba906f756967f9e9ca394464a");
+/// assert_eq!(repo.get_blob(BlobRef::new(h)).unwrap(), Blob::new(b"hello\n".to_vec()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemRepo<H: Hash> {
+    description: String,
+    objects: HashMap<String, Vec<u8>>,
+    refs: BTreeMap<SpecRef, Ref<H>>
+}
+impl<H: Hash> MemRepo<H> {
+    /// create an empty repository (`HEAD` points to `master`, which does
+    /// not exist yet)
+    pub fn new() -> Self {
+        let mut refs = BTreeMap::new();
+        refs.insert(SpecRef::Head, Ref::Link(SpecRef::branch("master")));
+        MemRepo {
+            description: String::new(),
+            objects: HashMap::new(),
+            refs: refs
+        }
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.description = description.to_string()
+    }
+
+    /// store the given object, returns its hash
+    pub fn write_object<O: Encoder>(&mut self, obj: &O) -> Result<H> {
+        let data = try!(obj.encode_to_vec());
+        let h = try!(H::hash(&mut data.as_slice()));
+        self.objects.insert(h.to_hexadecimal(), data);
+        Ok(h)
+    }
+
+    /// create or replace the given reference
+    pub fn set_ref(&mut self, r: SpecRef, target: Ref<H>) {
+        self.refs.insert(r, target);
+    }
+
+    /// the number of objects in the repository
+    pub fn len(&self) -> usize { self.objects.len() }
+
+    fn list_refs<F: Fn(&SpecRef) -> bool>(&self, filter: F) -> Vec<SpecRef> {
+        self.refs.keys().filter(|r| filter(r)).cloned().collect()
+    }
+}
+
+impl<H: Hash> ObjectSource for MemRepo<H> {
+    fn read_raw<R: Hash>(&self, h: &R) -> Result<(ObjectKind, Vec<u8>)> {
+        let hex = h.to_hexadecimal();
+        let data = match self.objects.get(&hex) {
+            Some(data) => data,
+            None => return Err(GitError::Other(format!("missing object {}", hex)))
+        };
+        let end = match data.iter().position(|&c| c == 0) {
+            Some(end) => end,
+            None => return Err(GitError::ParsingError("invalid object header".to_string()))
+        };
+        match ObjectKind::from_tag_bytes(&data[..end]) {
+            Some(kind) => Ok((kind, data[end + 1..].to_vec())),
+            None => Err(GitError::ParsingError("unknown object type".to_string()))
+        }
+    }
+}
+
+impl<H: Hash> Repo for MemRepo<H> {
+    fn is_valid(&self) -> Result<()> { Ok(()) }
+
+    fn get_description(&self) -> Result<String> { Ok(self.description.clone()) }
+
+    fn get_ref<R: Hash>(&self, r: SpecRef) -> Result<Ref<R>> {
+        match self.refs.get(&r) {
+            Some(&Ref::Link(ref link)) => Ok(Ref::Link(link.clone())),
+            Some(&Ref::Hash(ref h)) =>
+                R::from_bytes(h.as_bytes().to_vec())
+                    .map(Ref::Hash)
+                    .ok_or(GitError::InvalidHashSize(R::digest_size(), h.as_bytes().len())),
+            None => Err(GitError::InvalidRef(r.into()))
+        }
+    }
+
+    fn exists<R: Hash>(&self, h: &R) -> Result<bool> {
+        Ok(self.objects.contains_key(&h.to_hexadecimal()))
+    }
+
+    fn lookup_hash<R: Hash>(&self, prefix: &Partial<R>) -> Result<Vec<R>> {
+        let prefix = prefix.to_hexadecimal();
+        Ok(self.objects.keys()
+               .filter(|hex| hex.starts_with(&prefix))
+               .filter_map(|hex| R::from_hex(hex))
+               .collect())
+    }
+
+    fn list_branches(&self) -> Result<Vec<SpecRef>> {
+        Ok(self.list_refs(|r| match r { &SpecRef::Branch(_) => true, _ => false }))
+    }
+    fn list_remotes(&self) -> Result<Vec<SpecRef>> {
+        Ok(self.list_refs(|r| match r { &SpecRef::Remote(_, _) => true, _ => false }))
+    }
+    fn list_tags(&self) -> Result<Vec<SpecRef>> {
+        Ok(self.list_refs(|r| match r { &SpecRef::Tag(_) => true, _ => false }))
+    }
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use object::{Blob, BlobRef, Tree, TreeRef, TreeEnt, Commit, CommitRef, Permissions, Person, Date};
+    use protocol::SHA1;
+    use std::path::PathBuf;
+
+    #[test]
+    fn mem_repo_objects() {
+        let mut repo = MemRepo::<SHA1>::new();
+        let blob = BlobRef::new(repo.write_object(&Blob::new(b"README\n".to_vec())).unwrap());
+        assert_eq!(blob.to_hexadecimal(), "e845566c06f9bf557d35e8292c37cf05d97a9769");
+        let tree : Tree<SHA1> = vec![TreeEnt::Blob(Permissions::default_file(), PathBuf::from("README.md"), blob.clone())]
+            .into_iter().collect();
+        let tr = TreeRef::new(repo.write_object(&tree).unwrap());
+        let me = Person::new( "Test".to_string()
+                            , "git-test@example.com".to_string()
+                            , Date::seconds_since_epoch(1480007832)
+                            );
+        let first = Commit::new(tr.clone(), me.clone(), me.clone(), "initial commit\n");
+        let first_ref = CommitRef::new(repo.write_object(&first).unwrap());
+        let second = Commit::new(tr.clone(), me.clone(), me, "same tree\n").with_parent(first_ref.clone());
+        let second_ref = CommitRef::new(repo.write_object(&second).unwrap());
+        assert_eq!(repo.len(), 4);
+
+        assert_eq!(repo.get_blob(blob.clone()), Ok(Blob::new(b"README\n".to_vec())));
+        assert_eq!(repo.get_tree(tr.clone()), Ok(tree));
+        assert_eq!(repo.exists(&blob), Ok(true));
+        assert_eq!(repo.exists(&SHA1::empty_tree()), Ok(false));
+        let found : SHA1 = repo.resolve_prefix(&Partial::from_hex("e845566").unwrap()).unwrap();
+        assert_eq!(found.as_bytes(), blob.as_bytes());
+
+        // HEAD -> master -> second
+        repo.set_ref(SpecRef::branch("master"), Ref::Hash(SHA1::from_bytes(second_ref.as_bytes().to_vec()).unwrap()));
+        assert_eq!(repo.list_branches(), Ok(vec![SpecRef::branch("master")]));
+        let head : SHA1 = repo.get_ref_follow_links(SpecRef::Head).unwrap();
+        assert_eq!(head.as_bytes(), second_ref.as_bytes());
+        let history : Vec<Commit<SHA1>> = repo.walk_commits(CommitRef::new(head)).map(|c| c.unwrap()).collect();
+        assert_eq!(history, vec![second, first]);
+    }
+}
@@ -51,7 +51,8 @@ impl<H: Hash> convert::AsRef<H> for BlobRef<H> {
 /// the data.
 ///
 /// Ideally, in the future, the Blob may become a `trait` so we could use
-/// streamable objects or in memory data depending on what is better.
+/// streamable objects or in memory data depending on what is better. In the
+/// meantime, `fs::GitFS::open_blob` gives a streaming access to the content.
 ///
 /// The composition of a Blob may also differes depending of the backend
 /// in use. So far we will use in the filesystem as it is the legacy one
@@ -66,6 +67,100 @@ impl Blob {
 
     /// access the inner data as an immutable slice of bytes
     pub fn as_slice(&self) -> &[u8] { self.0.as_slice() }
+
+    /// write the content of the blob as it is (without the object header)
+    ///
+    /// Unlike `Display`, which replaces the invalid UTF-8 sequences, the
+    /// binary content is preserved.
+    pub fn write_raw<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
+        try!(writer.write_all(self.0.as_slice()));
+        Ok(self.0.len())
+    }
+
+    /// tell if the blob is binary: as git does, if it contains a NUL byte
+    /// in its first 8000 bytes
+    pub fn is_binary(&self) -> bool {
+        self.0.iter().take(BINARY_CHECK_SIZE).any(|&c| c == 0)
+    }
+
+    /// the lines of the blob, with their new line (the last one may not
+    /// have any)
+    fn lines(&self) -> Vec<&[u8]> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for (idx, &c) in self.0.iter().enumerate() {
+            if c == b'\n' {
+                lines.push(&self.0[start..idx + 1]);
+                start = idx + 1;
+            }
+        }
+        if start < self.0.len() {
+            lines.push(&self.0[start..]);
+        }
+        lines
+    }
+}
+
+/// number of bytes looked at to tell if a blob is binary (as git does)
+const BINARY_CHECK_SIZE : usize = 8000;
+
+/// count the lines added and removed to go from `old` to `new`, as
+/// `git diff --stat` does: `(insertions, deletions)`
+///
+/// Returns `None` if one of the blobs is binary (see `Blob::is_binary`),
+/// git then only tells the files differ.
+///
+/// The counts come from the shortest edit script between the lines of the
+/// blobs (once the common first and last lines are put aside), found with
+/// Myers' O(ND) algorithm as git does: a line changed counts as one
+/// deletion and one insertion. A last line without new line differs from
+/// the same line with one.
+///
+/// ```
+/// use git::object::{Blob, blob_linestat};
+///
+/// let old = Blob::new(b"a\nb\nc\n".to_vec());
+/// let new = Blob::new(b"a\nB\nc\nd\n".to_vec());
+/// assert_eq!(blob_linestat(&old, &new), Some((2, 1)));
+/// ```
+pub fn blob_linestat(old: &Blob, new: &Blob) -> Option<(usize, usize)> {
+    if old.is_binary() || new.is_binary() {
+        return None
+    }
+    let (old, new) = (old.lines(), new.lines());
+    let prefix = old.iter().zip(new.iter()).take_while(|&(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|&(a, b)| a == b).count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let common = (old.len() + new.len() - edit_distance(old, new)) / 2;
+    Some((new.len() - common, old.len() - common))
+}
+
+/// the number of insertions and deletions of the shortest edit script
+/// turning `a` into `b` (Myers, "An O(ND) Difference Algorithm and Its
+/// Variations"): the time is proportional to the size of the inputs times
+/// the number of differences, not to the product of the sizes.
+fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = a.len() + b.len();
+    // furthest `x` reached on each diagonal `k = x - y`, at index `k + max`
+    let mut v = vec![0isize; 2 * max + 2];
+    for d in 0..(max as isize + 1) {
+        let mut k = -d;
+        while k <= d {
+            let i = (k + max as isize) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) { v[i + 1] } else { v[i - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m { return d as usize }
+            k += 2;
+        }
+    }
+    max
 }
 impl Decoder for Blob {
     fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> {
@@ -81,7 +176,7 @@ impl Decoder for Blob {
 }
 impl fmt::Display for Blob {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", unsafe { String::from_utf8_unchecked(self.0.clone()) } )
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
     }
 }
 impl Encoder for Blob {
@@ -111,6 +206,61 @@ mod test {
     use super::*;
     use ::protocol::test_encoder_decoder;
 
+    #[test]
+    fn blob_binary_content() {
+        let data = vec![b'a', 0xff, 0xfe, b'\n', 0x80];
+        let blob = Blob::new(data.clone());
+        assert_eq!(format!("{}", blob), "a\u{fffd}\u{fffd}\n\u{fffd}");
+        let mut raw = Vec::new();
+        assert_eq!(blob.write_raw(&mut raw).unwrap(), data.len());
+        assert_eq!(raw, data);
+    }
+
+    #[test]
+    fn blob_encode_to_vec() {
+        let blob = Blob::new(b"hello\n".to_vec());
+        let mut data = Vec::new();
+        blob.encode(&mut data).unwrap();
+        assert_eq!(blob.encode_to_vec().unwrap(), data);
+        assert_eq!(data, b"blob 6\0hello\n".to_vec());
+    }
+
+    #[test]
+    fn linestat() {
+        let blob = |data: &[u8]| Blob::new(data.to_vec());
+        let old = blob(b"one\ntwo\nthree\n");
+        assert_eq!(blob_linestat(&old, &old), Some((0, 0)));
+        assert_eq!(blob_linestat(&old, &blob(b"one\ntwo\nthree\nfour\n")), Some((1, 0)));
+        assert_eq!(blob_linestat(&old, &blob(b"one\nthree\n")), Some((0, 1)));
+        assert_eq!(blob_linestat(&old, &blob(b"one\n2\nthree\n")), Some((1, 1)));
+        assert_eq!(blob_linestat(&blob(b""), &old), Some((3, 0)));
+        // the last line without new line is changed
+        assert_eq!(blob_linestat(&old, &blob(b"one\ntwo\nthree")), Some((1, 1)));
+        // moved lines
+        assert_eq!(blob_linestat(&old, &blob(b"three\none\ntwo\n")), Some((1, 1)));
+
+        let binary = blob(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert!(binary.is_binary());
+        assert!(! old.is_binary());
+        assert_eq!(blob_linestat(&old, &binary), None);
+        assert_eq!(blob_linestat(&binary, &binary), None);
+    }
+    #[test]
+    fn linestat_large_blobs() {
+        // every 1000th line changed and one line inserted in the middle:
+        // quick to diff whatever the size of the blobs
+        fn lines<F: Fn(usize) -> String>(f: F) -> Blob {
+            Blob::new((0..50000).map(f).collect::<String>().into_bytes())
+        }
+        let old = lines(|n| format!("{}\n", n));
+        let new = lines(|n| match n {
+            _ if n % 1000 == 500 => format!("changed {}\n", n),
+            25000 => format!("inserted\n{}\n", n),
+            _ => format!("{}\n", n)
+        });
+        assert_eq!(blob_linestat(&old, &new), Some((51, 50)));
+    }
+
     #[test]
     fn blob_serialisable() {
         let data = (0x00u8..0xff).collect();
@@ -3,7 +3,7 @@ use super::person::Person;
 use protocol::{Encoder, Decoder, Hash};
 use std::{io, fmt, convert, ops, iter, slice, collections, str};
 use nom;
-use error::Result;
+use error::{Result, NOM_MISSING_AUTHOR, NOM_MISSING_COMMITTER};
 
 /// Commit reference
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
@@ -70,12 +70,9 @@ impl<'a, H: Hash> IntoIterator for &'a mut Parents<H> {
     fn into_iter(mut self) -> Self::IntoIter { self.0.iter_mut() }
 }
 impl<H: Hash> Encoder for Parents<H> {
+    /// every parent is encoded as `parent <hex>\n`
     fn required_size(&self) -> usize {
-        let mut sz = 7;
-        for _ in self.iter() {
-            sz += H::digest_hex_size() + 1;
-        }
-        sz
+        self.len() * (7 + H::digest_hex_size() + 1)
     }
     fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
         let mut sz = 0;
@@ -140,6 +137,45 @@ named!( nom_parse_encoding<Encoding>
               )
       );
 
+/// parse a header whose value spans multiple lines (`gpgsig`, `mergetag`):
+/// the continuation lines start with a space (which is not kept).
+///
+/// Unlike the `Extras`, any character is accepted in the value (the
+/// base64 armor uses `+`, `/` and `=`).
+fn nom_parse_multiline_header<'a>(b: &'a [u8], key: &str) -> nom::IResult<&'a [u8], String> {
+    let (mut b, first) = try_parse!(b, preceded!( tag!(key)
+                                                , map_res!(take_until_and_consume!("\n"), str::from_utf8)
+                                                ));
+    let mut value = first.to_string();
+    while b.starts_with(b" ") {
+        let (i, line) = try_parse!(&b[1..], map_res!(take_until_and_consume!("\n"), str::from_utf8));
+        value.push('\n');
+        value.push_str(line);
+        b = i;
+    }
+    nom::IResult::Done(b, value)
+}
+fn nom_parse_gpgsig(b: &[u8]) -> nom::IResult<&[u8], String> {
+    nom_parse_multiline_header(b, "gpgsig ")
+}
+/// parse a `mergetag` header: the tag object is given back with its
+/// trailing new line (it is not part of the header's value)
+fn nom_parse_mergetag(b: &[u8]) -> nom::IResult<&[u8], String> {
+    map!(b, apply!(nom_parse_multiline_header, "mergetag "), |tag: String| tag + "\n")
+}
+/// the value of a `mergetag` header: the tag object without its last new
+/// line (which is the one ending the header)
+fn mergetag_value(tag: &str) -> &str {
+    if tag.ends_with('\n') { &tag[..tag.len() - 1] } else { tag }
+}
+/// the size of a multiline header once encoded (see `write_multiline_header`)
+fn multiline_header_size(key: &str, value: &str) -> usize {
+    key.len() + 1 + value.len() + value.matches('\n').count() + 1
+}
+fn write_multiline_header<W: io::Write>(w: &mut W, key: &str, value: &str) -> io::Result<()> {
+    write!(w, "{} {}\n", key, value.replace("\n", "\n "))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Extras(collections::BTreeMap<String, String>);
 impl Extras {
@@ -192,11 +228,18 @@ fn is_valid_encoding_char(c: u8) -> bool {
 named! ( parse_string_eol<&str>
        , map_res!(take_while1!(is_valid_encoding_char), str::from_utf8)
        );
+// the value lines of an extra start with a space: anything up to the end
+// of the line is part of the value (urls, emails...)
+named! ( parse_extra_value_line<&str>
+       , chain!( char!(' ')
+               ~ v: map_res!(take_until_and_consume!("\n"), str::from_utf8)
+               , || v
+               )
+       );
 named!( parse_extra<(String, String)>
       , chain!( k: parse_string_eol ~ char!('\n')
               ~ mut acc: value!(String::new())
-              ~ many0!(chain!( char!(' ') ~ v: parse_string_eol ~ char!('\n')
-                             , || { acc.push_str(v); acc.push('\n')} ))
+              ~ many0!(tap!(v: parse_extra_value_line => { acc.push_str(v); acc.push('\n') }))
               , || (k.to_string(), acc)
               )
       );
@@ -251,20 +294,123 @@ pub struct Commit<H: Hash> {
     pub author: Person,
     pub committer: Person,
     pub encoding: Option<Encoding>,
+    /// the armored signature of the commit (`git commit -S`), without the
+    /// leading space of the continuation lines
+    pub gpgsig: Option<String>,
+    /// the tags merged by the commit (`git merge` of a signed tag), each
+    /// one is the full tag object (signature included)
+    pub mergetags: Vec<String>,
     pub extras: Extras,
     pub message: String
 }
-impl<H: Hash> fmt::Display for Commit<H> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!( f, "tree {}\n", self.tree_ref.to_hexadecimal()));
+impl<H: Hash> Commit<H> {
+    /// create a new commit without parents (see `with_parent`)
+    ///
+    /// The message is stored with the empty line separating it from the
+    /// headers (as it is in the encoded commit), see `message()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use git::object::{Commit, CommitRef, TreeRef, Person, Date};
+    /// use git::protocol::{Hash, SHA1};
+    ///
+    /// let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+    /// let parent = CommitRef::new(SHA1::from_hex("11f766d25092064380879e792eedac662dd2ec29").unwrap());
+    /// let me = Person::new( "Nicolas".to_string()
+    ///                     , "my@email.address".to_string()
+    ///                     , Date::seconds_since_epoch(1480007832)
+    ///                     );
+    /// let commit = Commit::new(tree, me.clone(), me, "add an empty tree\n")
+    ///     .with_parent(parent);
+    /// assert_eq!(commit.parents().len(), 1);
+    /// assert_eq!(commit.message(), "add an empty tree\n");
+    /// ```
+    pub fn new(tree: TreeRef<H>, author: Person, committer: Person, message: &str) -> Self {
+        Commit {
+            tree_ref: tree,
+            parents: Parents::new(),
+            author: author,
+            committer: committer,
+            encoding: None,
+            gpgsig: None,
+            mergetags: Vec::new(),
+            extras: Extras::new(),
+            message: format!("\n{}", message)
+        }
+    }
+    /// add a parent to the commit (after the existing ones)
+    pub fn with_parent(mut self, parent: CommitRef<H>) -> Self {
+        self.parents.push(parent);
+        self
+    }
+    /// set the encoding of the commit message
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+    /// add (or replace) an extra header
+    pub fn with_extra(mut self, key: String, value: String) -> Self {
+        self.extras.extend(Some((key, value)));
+        self
+    }
+
+    pub fn tree_ref(&self) -> &TreeRef<H> { &self.tree_ref }
+    pub fn parents(&self) -> &Parents<H> { &self.parents }
+    /// the tree of the commit (same as `tree_ref`)
+    pub fn tree(&self) -> &TreeRef<H> { &self.tree_ref }
+    /// the parents of the commit, in order
+    pub fn parent_ids(&self) -> &[CommitRef<H>] { &self.parents }
+    /// the first parent (the branch a merge commit was made on), `None`
+    /// for a root commit
+    pub fn first_parent(&self) -> Option<&CommitRef<H>> { self.parents.first() }
+    pub fn author(&self) -> &Person { &self.author }
+    pub fn committer(&self) -> &Person { &self.committer }
+    pub fn encoding(&self) -> Option<&Encoding> { self.encoding.as_ref() }
+    /// the tag objects embedded in the `mergetag` headers
+    pub fn mergetags(&self) -> &[String] { &self.mergetags }
+    pub fn extras(&self) -> &Extras { &self.extras }
+    /// the commit message, without the empty line separating it from the
+    /// headers
+    pub fn message(&self) -> &str {
+        if self.message.starts_with('\n') { &self.message[1..] } else { &self.message }
+    }
+    /// tell if the commit has more than one parent
+    pub fn is_merge(&self) -> bool { self.parents.len() > 1 }
+    /// tell if the commit has no parent (the first commit of a history)
+    pub fn root(&self) -> bool { self.parents.is_empty() }
+}
+impl<H: Hash> Commit<H> {
+    /// write the content of the commit (without the object header): the
+    /// author and the committer are written as they were read, even if
+    /// they are not UTF-8 (see `Person`)
+    fn write_content<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        try!(write!( w, "tree {}\n", self.tree_ref.to_hexadecimal()));
         for p in self.parents.iter() {
-            try!(write!(f, "parent {}\n", p.to_hexadecimal()));
+            try!(write!(w, "parent {}\n", p.to_hexadecimal()));
         }
-        try!(write!(f, "author {}\ncommitter {}\n", self.author, self.committer));
+        try!(w.write_all(b"author "));
+        try!(self.author.encode(w));
+        try!(w.write_all(b"\ncommitter "));
+        try!(self.committer.encode(w));
+        try!(w.write_all(b"\n"));
         if let &Some(ref e) = &self.encoding {
-            try!(write!(f, "encoding {}\n", e.raw));
+            try!(write!(w, "encoding {}\n", e.raw));
+        }
+        for tag in self.mergetags.iter() {
+            try!(write_multiline_header(w, "mergetag", mergetag_value(tag)));
+        }
+        if let &Some(ref sig) = &self.gpgsig {
+            try!(write_multiline_header(w, "gpgsig", sig));
         }
-        write!(f, "{}{}", self.extras, self.message)
+        write!(w, "{}{}", self.extras, self.message)
+    }
+}
+impl<H: Hash> fmt::Display for Commit<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut data = Vec::with_capacity(self.required_size());
+        try!(self.write_content(&mut data).map_err(|_| fmt::Error));
+        f.write_str(&String::from_utf8_lossy(&data))
     }
 }
 impl<H: Hash> Decoder for Commit<H> {
@@ -280,18 +426,33 @@ named!(nom_parse_commit_head<usize>
       , chain!(nom_parse_commit_tag ~ r: nom_parse_commit_size ~ char!('\0'), || r)
       );
 fn nom_parse_commit<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Commit<H>> {
-    let (b, _) = try_parse!(b, nom_parse_commit_head);
+    let (b, size) = try_parse!(b, nom_parse_commit_head);
+    if b.len() < size {
+        return nom::IResult::Incomplete(nom::Needed::Size(size - b.len()));
+    }
+    // the message goes up to the size given in the header, not further
+    let (b, rest) = (&b[..size], &b[size..]);
+    let (_, commit) = try_parse!(b, nom_parse_commit_content);
+    nom::IResult::Done(rest, commit)
+}
+/// the content of a commit, without its header: the message goes up to the
+/// end of the input
+pub fn nom_parse_commit_content<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Commit<H>> {
     let (b, _) = try_parse!(b, tag!("tree "));
     let (b, tr) = try_parse!(b, H::decode_hex);
     let (b, _) = try_parse!(b, tag!("\n"));
     let (b, parents) = try_parse!(b, Parents::<H>::decode);
-    let (b, _) = try_parse!(b, tag!("author "));
+    let (b, _) = try_parse!(b, add_return_error!(nom::ErrorKind::Custom(NOM_MISSING_AUTHOR), tag!("author ")));
     let (b, a) = try_parse!(b, Person::decode);
     let (b, _) = try_parse!(b, tag!("\n"));
-    let (b, _) = try_parse!(b, tag!("committer "));
+    let (b, _) = try_parse!(b, add_return_error!(nom::ErrorKind::Custom(NOM_MISSING_COMMITTER), tag!("committer ")));
     let (b, c) = try_parse!(b, Person::decode);
     let (b, _) = try_parse!(b, tag!("\n"));
-    let (b, en) = try_parse!(b, opt!(chain!(e: nom_parse_encoding ~ char!('\n'), || e)));
+    // the optional headers may be at the end of the input (a commit with
+    // an empty message): `complete!` so they do not ask for more
+    let (b, en) = try_parse!(b, opt!(complete!(chain!(e: nom_parse_encoding ~ char!('\n'), || e))));
+    let (b, mergetags) = try_parse!(b, many0!(complete!(nom_parse_mergetag)));
+    let (b, sig) = try_parse!(b, opt!(complete!(nom_parse_gpgsig)));
     let (b, e) = try_parse!(b, nom_parse_extras);
     let (b, m) = try_parse!(b, map_res!(nom::rest, str::from_utf8));
     nom::IResult::Done(
@@ -302,6 +463,8 @@ fn nom_parse_commit<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Commit<H>> {
             author: a, committer: c,
             extras: e,
             encoding: en,
+            gpgsig: sig,
+            mergetags: mergetags,
             message: m.to_string()
         }
     )
@@ -310,17 +473,25 @@ impl<H: Hash> Encoder for Commit<H> {
     fn required_size(&self) -> usize {
         0 + H::digest_hex_size() + 6
           + self.parents.required_size()
-          + self.author.required_size() + 1
-          + self.committer.required_size() + 1
+          + 7 + self.author.required_size() + 1
+          + 10 + self.committer.required_size() + 1
           + match &self.encoding { &Some(ref e) => e.required_size() + 1, &None => 0 }
+          + self.mergetags.iter()
+                .map(|tag| multiline_header_size("mergetag", mergetag_value(tag)))
+                .sum::<usize>()
+          + match &self.gpgsig {
+                &Some(ref sig) => multiline_header_size("gpgsig", sig),
+                &None => 0
+            }
           + self.extras.required_size()
           + self.message.len()
     }
     fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
-        let data = format!("{}", self);
+        let mut data = Vec::with_capacity(self.required_size());
+        try!(self.write_content(&mut data));
         let head = format!("commit {}\0", data.len());
         try!(writer.write_all(head.as_bytes()));
-        try!(writer.write_all(data.as_bytes()));
+        try!(writer.write_all(&data));
         Ok(head.len() + data.len())
     }
 }
@@ -335,6 +506,7 @@ mod test {
     //! things don't break under our feet without knowing it.
 
     use super::*;
+    use nom;
     use ::protocol::test_decode_encode;
     use rustc_serialize::base64::FromBase64;
     use ::protocol::SHA1;
@@ -351,4 +523,254 @@ mod test {
         let data = SMOCK_TEST.from_base64().unwrap();
         test_decode_encode::<Commit<SHA1>>(data);
     }
+
+    // commit signed with `git commit -S` (+ encoded in base64)
+    const SIGNED_TEST : &'static str =
+        "Y29tbWl0IDQzNwB0cmVlIGM0OTg5N2YyOWY5ODE5YTBhYjY4NTBkN2UyMjQ0MzUwOGEx\
+         YTI5ZDUKYXV0aG9yIFRlc3QgPGdpdC10ZXN0QGV4YW1wbGUuY29tPiAxNDgwMDA3ODMy\
+         ICswMTAwCmNvbW1pdHRlciBUZXN0IDxnaXQtdGVzdEBleGFtcGxlLmNvbT4gMTQ4MDAw\
+         NzgzMiArMDEwMApncGdzaWcgLS0tLS1CRUdJTiBQR1AgU0lHTkFUVVJFLS0tLS0KIAog\
+         aUlzRUFCWUlBRE1XSVFTb0lqVW0relF6clErc1M0bHpuQzRTc3V5V01RVUNhdEljMlJV\
+         Y1oybDBMWFJsYzNSQQogWlhoaGJYQnNaUzVqYjIwQUNna1FjNXd1RXJMc2xqSGZvQUQr\
+         UG9aK25XMG5PMzBtVVB6TzV3TFlSMkZsZHZ4MgogSVVXWlRwV284VHFOUGhJQkFOTHh6\
+         ZUNzamNIUzMxeDdURjM3alhXNy9WVmRJRkFDd0V2d21mUU9kd2NHCiA9bkJBRQogLS0t\
+         LS1FTkQgUEdQIFNJR05BVFVSRS0tLS0tCgpzaWduZWQgY29tbWl0Cg==";
+
+    #[test]
+    fn commit_builder() {
+        use ::object::{TreeRef, Person, Date};
+        use ::protocol::test_encoder_decoder;
+        let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+        let p1 = CommitRef::new(SHA1::from_hex("11f766d25092064380879e792eedac662dd2ec29").unwrap());
+        let p2 = CommitRef::new(SHA1::from_hex("2ef959163566f29b4a5acb8cbe217c8b036747bc").unwrap());
+        let author = Person::new( "Nicolas".to_string()
+                                , "my@email.address".to_string()
+                                , Date::seconds_since_epoch(1480007832)
+                                );
+        let committer = Person::new( "Test".to_string()
+                                   , "git-test@example.com".to_string()
+                                   , Date::seconds_since_epoch(1480007900)
+                                   );
+        let commit = Commit::new(tree.clone(), author.clone(), committer.clone(), "merge\n\nwith details\n")
+            .with_parent(p1.clone())
+            .with_parent(p2.clone())
+            .with_encoding(Encoding::new_str("ISO-8859-1"))
+            .with_extra("origin".to_string(), "https://github.com/NicolasDP/git\n".to_string());
+        assert_eq!(commit.tree_ref(), &tree);
+        assert_eq!(commit.parents().to_vec(), vec![p1, p2]);
+        assert_eq!(commit.author(), &author);
+        assert_eq!(commit.committer(), &committer);
+        assert_eq!(commit.encoding(), Some(&Encoding::new_str("ISO-8859-1")));
+        assert_eq!(commit.message(), "merge\n\nwith details\n");
+
+        let mut data = Vec::new();
+        commit.encode(&mut data).unwrap();
+        assert_eq!(Commit::<SHA1>::decode(&data).unwrap().1, commit);
+        test_encoder_decoder(commit);
+    }
+
+    #[test]
+    fn parents_required_size() {
+        use ::object::{TreeRef, Person, Date};
+        let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+        let me = Person::new( "Nicolas".to_string()
+                            , "my@email.address".to_string()
+                            , Date::seconds_since_epoch(1480007832)
+                            );
+        let hashes = [ "11f766d25092064380879e792eedac662dd2ec29"
+                     , "2ef959163566f29b4a5acb8cbe217c8b036747bc"
+                     , "1fa6811cf22a4cbef5bb28e68fe28d728cf2f64d"
+                     ];
+        for n in [0, 1, 3].iter() {
+            let parents : Parents<SHA1> = hashes[..*n].iter()
+                .map(|h| CommitRef::new(SHA1::from_hex(h).unwrap()))
+                .collect();
+            let mut data = Vec::new();
+            let sz = parents.encode(&mut data).unwrap();
+            assert_eq!(sz, data.len());
+            assert_eq!(parents.required_size(), data.len());
+
+            let mut commit = Commit::new(tree.clone(), me.clone(), me.clone(), "parents\n");
+            commit.parents = parents;
+            assert_eq!(commit.required_size(), format!("{}", commit).len());
+        }
+    }
+
+    #[test]
+    fn merge_commit() {
+        let content =
+            "tree 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\
+             parent 11f766d25092064380879e792eedac662dd2ec29\n\
+             parent 1fa6811cf22a4cbef5bb28e68fe28d728cf2f64d\n\
+             author Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0100\n\
+             committer Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0100\n\
+             \n\
+             Merge branch 'dev'\n";
+        let data = format!("commit {}\0{}", content.len(), content).into_bytes();
+        let commit = Commit::<SHA1>::decode(&data).unwrap().1;
+        assert_eq!(commit.parents.len(), 2);
+        assert_eq!( commit.parents[1]
+                  , CommitRef::new(SHA1::from_hex("1fa6811cf22a4cbef5bb28e68fe28d728cf2f64d").unwrap())
+                  );
+        assert!(commit.is_merge());
+        assert!(! commit.root());
+        test_decode_encode::<Commit<SHA1>>(data);
+    }
+
+    #[test]
+    fn commit_accessors() {
+        use ::object::{TreeRef, Person, Date};
+        let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+        let p1 = CommitRef::new(SHA1::from_hex("11f766d25092064380879e792eedac662dd2ec29").unwrap());
+        let p2 = CommitRef::new(SHA1::from_hex("2ef959163566f29b4a5acb8cbe217c8b036747bc").unwrap());
+        let me = Person::new( "Nicolas".to_string()
+                            , "my@email.address".to_string()
+                            , Date::seconds_since_epoch(1480007832)
+                            );
+        let root = Commit::new(tree.clone(), me.clone(), me.clone(), "root\n");
+        assert_eq!(root.tree(), &tree);
+        assert!(root.parent_ids().is_empty());
+        assert_eq!(root.first_parent(), None);
+        let child = root.clone().with_parent(p1.clone());
+        assert_eq!(child.parent_ids(), &[p1.clone()][..]);
+        assert_eq!(child.first_parent(), Some(&p1));
+        let merge = child.with_parent(p2.clone());
+        assert_eq!(merge.tree(), &tree);
+        assert_eq!(merge.parent_ids(), &[p1.clone(), p2][..]);
+        assert_eq!(merge.first_parent(), Some(&p1));
+    }
+
+    #[test]
+    fn root_commit() {
+        assert_eq!(Parents::<SHA1>::decode(b"author "), nom::IResult::Done(&b"author "[..], Parents::new()));
+        let data = SIGNED_TEST.from_base64().unwrap();
+        let commit = Commit::<SHA1>::decode(&data).unwrap().1;
+        assert!(commit.root());
+        assert!(! commit.is_merge());
+    }
+
+    #[test]
+    fn empty_message() {
+        use ::object::{TreeRef, Person, Date};
+        use ::protocol::test_encoder_decoder;
+        let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+        let me = Person::new( "Nicolas".to_string()
+                            , "my@email.address".to_string()
+                            , Date::seconds_since_epoch(1480007832)
+                            );
+        let mut commit = Commit::new(tree, me.clone(), me, "");
+        assert_eq!(commit.message(), "");
+        test_encoder_decoder(commit.clone());
+
+        // without even the empty line after the headers
+        commit.message = String::new();
+        let mut data = Vec::new();
+        commit.encode(&mut data).unwrap();
+        let decoded = Commit::<SHA1>::decode(&data).unwrap().1;
+        assert_eq!(decoded.message, "");
+        assert_eq!(decoded.message(), "");
+        assert_eq!(decoded, commit);
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        use ::error::GitError;
+        let mut data = SMOCK_TEST.from_base64().unwrap();
+        let commit = Commit::<SHA1>::decode_all(&data).unwrap();
+        data.extend_from_slice(b"garbage");
+        assert_eq!(Commit::<SHA1>::decode_all(&data), Err(GitError::TrailingBytes(7)));
+        // the garbage is not taken for the end of the message
+        assert_eq!(Commit::<SHA1>::decode(&data), nom::IResult::Done(&b"garbage"[..], commit));
+    }
+
+    #[test]
+    fn latin1_author() {
+        let mut content = b"tree 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\
+                            author Fran\xe7ois <francois@example.com> 1480007832 +0000\n\
+                            committer Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0000\n\
+                            \n\
+                            old commit\n".to_vec();
+        let mut data = format!("commit {}\0", content.len()).into_bytes();
+        data.append(&mut content);
+        let commit = Commit::<SHA1>::decode(&data).unwrap().1;
+        assert_eq!(commit.author().name(), "Fran\u{fffd}ois");
+        assert_eq!(commit.committer().name(), "Nicolas Di Prima");
+        assert_eq!(commit.message(), "old commit\n");
+        // encoded back byte for byte, so the hash of the commit is kept
+        test_decode_encode::<Commit<SHA1>>(data);
+    }
+
+    #[test]
+    fn missing_committer() {
+        let content =
+            "tree 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\
+             author Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0100\n\
+             \n\
+             no committer\n";
+        let data = format!("commit {}\0{}", content.len(), content).into_bytes();
+        assert_eq!( Commit::<SHA1>::decode(&data)
+                  , nom::IResult::Error(nom::ErrorKind::Custom(NOM_MISSING_COMMITTER))
+                  );
+        let content = "tree 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\nno author\n";
+        let data = format!("commit {}\0{}", content.len(), content).into_bytes();
+        assert_eq!( Commit::<SHA1>::decode(&data)
+                  , nom::IResult::Error(nom::ErrorKind::Custom(NOM_MISSING_AUTHOR))
+                  );
+    }
+
+    #[test]
+    fn extras_with_punctuation() {
+        let extras : Extras = vec![
+            ("origin".to_string(), "https://github.com/NicolasDP/git.git\nnicolas@di-prima.fr: ok\n".to_string())
+        ].into_iter().collect();
+        let mut data = Vec::new();
+        let sz = extras.encode(&mut data).unwrap();
+        assert_eq!(sz, data.len());
+        assert_eq!(data, b"origin\n https://github.com/NicolasDP/git.git\n nicolas@di-prima.fr: ok\n".to_vec());
+        assert_eq!(nom_parse_extras(&data), nom::IResult::Done(&b""[..], extras));
+    }
+
+    #[test]
+    fn signed_commit() {
+        let data = SIGNED_TEST.from_base64().unwrap();
+        let commit = Commit::<SHA1>::decode(&data).unwrap().1;
+        let sig = commit.gpgsig.clone().expect("the commit is signed");
+        assert!(sig.starts_with("-----BEGIN PGP SIGNATURE-----\n\niIsEABYIADMWIQSoIjUm+zQzrQ+sS4lz"));
+        assert!(sig.ends_with("\n=nBAE\n-----END PGP SIGNATURE-----"));
+        assert!(commit.extras.clone().into_iter().next().is_none());
+        assert_eq!(commit.message, "\nsigned commit\n");
+        test_decode_encode::<Commit<SHA1>>(data);
+    }
+
+    #[test]
+    fn merge_signed_tag() {
+        let tag = "object 11f766d25092064380879e792eedac662dd2ec29\n\
+                   type commit\n\
+                   tag v0.2\n\
+                   tagger Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0000\n\
+                   \n\
+                   release v0.2\n\
+                   -----BEGIN PGP SIGNATURE-----\n\
+                   \n\
+                   iIsEABYIADMWIQSoIjUm+zQzrQ+sS4lz\n\
+                   =nBAE\n\
+                   -----END PGP SIGNATURE-----\n";
+        let content = format!("tree 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\
+                               parent 2ef959163566f29b4a5acb8cbe217c8b036747bc\n\
+                               parent 11f766d25092064380879e792eedac662dd2ec29\n\
+                               author Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0000\n\
+                               committer Nicolas Di Prima <nicolas@di-prima.fr> 1480007832 +0000\n\
+                               mergetag {}\n\
+                               \n\
+                               Merge tag 'v0.2'\n"
+                             , &tag[..tag.len() - 1].replace("\n", "\n "));
+        let data = format!("commit {}\0{}", content.len(), content).into_bytes();
+        let commit = Commit::<SHA1>::decode(&data).unwrap().1;
+        assert_eq!(commit.mergetags(), &[tag.to_string()]);
+        assert!(commit.gpgsig.is_none());
+        assert!(commit.extras.clone().into_iter().next().is_none());
+        assert_eq!(commit.message(), "Merge tag 'v0.2'\n");
+        assert_eq!(commit.required_size(), content.len());
+        test_decode_encode::<Commit<SHA1>>(data);
+    }
 }
@@ -110,6 +110,22 @@ impl Date {
     /// ```
     pub fn encode_for_obj(&self) -> String { self.0.format("%s %z").to_string() }
 
+    /// the number of seconds since EPOCH (UTC)
+    ///
+    /// ```
+    /// use git::object::Date;
+    ///
+    /// assert_eq!(Date::seconds_since_epoch(1464729412).timestamp(), 1464729412);
+    /// ```
+    pub fn timestamp(&self) -> i64 { self.0.timestamp() }
+
+    /// the offset of the timezone, in seconds east of UTC (`+0100` is 3600)
+    pub fn offset_seconds(&self) -> i32 { self.0.offset().local_minus_utc() }
+
+    /// format the date as in RFC 2822 (`Tue, 1 Jul 2003 10:52:37 +0200`), in
+    /// the timezone of the date
+    pub fn to_rfc2822(&self) -> String { self.0.to_rfc2822() }
+
     /// create a new date with the given local timezone
     fn from(dt: NaiveDateTime, fo: FixedOffset) -> Self {
         Date::new(DateTime::from_utc(dt, fo))
@@ -187,11 +203,35 @@ named!( nom_parse_date<&[u8], Date>
 #[cfg(test)]
 mod test {
     use super::*;
-    use ::protocol::test_encoder_decoder;
+    use ::protocol::{test_encoder_decoder, test_decode_encode, Decoder};
 
     #[test]
     fn date_serialisable() {
         let date = Date::now();
         test_encoder_decoder(date);
     }
+
+    #[test]
+    fn date_accessors() {
+        let date = Date::seconds_since_epoch(1464729412);
+        assert_eq!(date.timestamp(), 1464729412);
+
+        let date = Date::decode(b"1480007832 +0530").unwrap().1;
+        assert_eq!(date.timestamp(), 1480007832);
+        assert_eq!(date.offset_seconds(), 5 * 3600 + 30 * 60);
+        assert_eq!(date.to_rfc2822(), "Thu, 24 Nov 2016 22:47:12 +0530");
+        let date = Date::decode(b"1480007832 -0930").unwrap().1;
+        assert_eq!(date.offset_seconds(), - (9 * 3600 + 30 * 60));
+        assert_eq!(date.to_rfc2822(), "Thu, 24 Nov 2016 07:47:12 -0930");
+    }
+
+    #[test]
+    fn date_timezones_round_trip() {
+        // the minutes of the offset are kept, and so is the sign of the
+        // offsets of less than an hour
+        for tz in ["+0000", "+0100", "+0530", "+0845", "-0030", "-0930", "+1400", "-1200"].iter() {
+            let data = format!("1480007832 {}", tz).into_bytes();
+            test_decode_encode::<Date>(data);
+        }
+    }
 }
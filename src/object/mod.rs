@@ -3,47 +3,133 @@ mod person;
 mod blob;
 mod tree;
 mod commit;
+mod tag;
 
 pub use self::date::Date;
 pub use self::person::Person;
-pub use self::blob::{BlobRef, Blob};
-pub use self::tree::{TreeRef, Permission, Permissions, PermissionSet, Tree, TreeEnt};
+pub use self::blob::{BlobRef, Blob, blob_linestat};
+pub use self::tree::{TreeRef, Permission, Permissions, PermissionSet, Tree, TreeEnt, TreeDiff, TreeBuilder};
 pub use self::commit::{CommitRef, Parents, Commit, Encoding, Extras};
+pub use self::tag::{TagRef, Tag};
 
 use nom;
-use std::fmt;
-use protocol::{Hash, Decoder};
+use std::{fmt, io};
+use protocol::{Hash, Decoder, Encoder};
+use error::Result;
+
+/// the kind of a git object, as written in the object's header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ObjectKind { Commit, Tree, Blob, Tag }
+impl ObjectKind {
+    /// the name of the kind, as written in the object header (`commit`...)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            &ObjectKind::Commit => "commit",
+            &ObjectKind::Tree   => "tree",
+            &ObjectKind::Blob   => "blob",
+            &ObjectKind::Tag    => "tag"
+        }
+    }
+
+    /// the kind given by the header of an encoded object (`commit 169\0...`):
+    /// the name of the kind followed by a space, `None` if the header does
+    /// not start with a known kind
+    ///
+    /// ```
+    /// use git::object::ObjectKind;
+    ///
+    /// assert_eq!(ObjectKind::from_tag_bytes(b"tree 0\0"), Some(ObjectKind::Tree));
+    /// assert_eq!(ObjectKind::from_tag_bytes(b"trees 0\0"), None);
+    /// ```
+    pub fn from_tag_bytes(b: &[u8]) -> Option<ObjectKind> {
+        [ObjectKind::Commit, ObjectKind::Tree, ObjectKind::Blob, ObjectKind::Tag].iter()
+            .find(|k| b.starts_with(k.as_str().as_bytes()) && b.get(k.as_str().len()) == Some(&b' '))
+            .cloned()
+    }
+}
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.as_str()) }
+}
 
 pub trait Object<H: Hash> : Decoder{
     type Id;
+
+    /// decode the object from its kind and its content, without the
+    /// `<type> <size>\0` header (see `protocol::ObjectSource::read_raw`)
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self>;
 }
 impl<H: Hash> Object<H> for Commit<H> {
     type Id = CommitRef<H>;
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self> {
+        if kind != ObjectKind::Commit { return nom::IResult::Error(nom::ErrorKind::Tag) }
+        commit::nom_parse_commit_content(content)
+    }
 }
 impl<H: Hash> Object<H> for Tree<H> {
     type Id = TreeRef<H>;
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self> {
+        if kind != ObjectKind::Tree { return nom::IResult::Error(nom::ErrorKind::Tag) }
+        tree::nom_parse_tree_content(content)
+    }
 }
 impl<H: Hash> Object<H> for Blob {
     type Id = BlobRef<H>;
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self> {
+        if kind != ObjectKind::Blob { return nom::IResult::Error(nom::ErrorKind::Tag) }
+        nom::IResult::Done(&content[content.len()..], Blob::new(content.to_vec()))
+    }
+}
+impl<H: Hash> Object<H> for Tag<H> {
+    type Id = TagRef<H>;
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self> {
+        if kind != ObjectKind::Tag { return nom::IResult::Error(nom::ErrorKind::Tag) }
+        tag::nom_parse_tag_content(content)
+    }
 }
 
 pub enum Obj<H: Hash> {
     Commit(Commit<H>),
     Tree(Tree<H>),
-    Blob(Blob)
+    Blob(Blob),
+    Tag(Tag<H>)
 }
 impl<H: Hash> Decoder for Obj<H> {
+    /// the object is selected on the full header tag (`tree ` and `tag `
+    /// start both with a `t`), the header is then parsed again by the
+    /// object's decoder.
+    ///
+    /// The error of the object's decoder is returned as is (not hidden
+    /// behind an `Alt` error) so `nom_try!` can tell what went wrong.
     fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> {
-        use nom::{IResult, Needed};
-        if b.len() < 1 {
-            return IResult::Incomplete(Needed::Size(1))
+        match ObjectKind::from_tag_bytes(b) {
+            Some(ObjectKind::Commit) => map!(b, call!(Commit::<H>::decode), Obj::Commit),
+            Some(ObjectKind::Tree)   => map!(b, call!(Tree::<H>::decode), Obj::Tree),
+            Some(ObjectKind::Blob)   => map!(b, call!(Blob::decode), Obj::Blob),
+            Some(ObjectKind::Tag)    => map!(b, call!(Tag::<H>::decode), Obj::Tag),
+            None => nom::IResult::Error(nom::ErrorKind::Alt)
         }
-        let c : char = b[0] as char;
-        match c {
-            'c' => Commit::<H>::decode(b).map(|com| Obj::Commit(com)),
-            't' => Tree::<H>::decode(b).map(|t| Obj::Tree(t)),
-            'b' => Blob::decode(b).map(|bl| Obj::Blob(bl)),
-            _   => panic!()
+    }
+}
+impl<H: Hash> Obj<H> {
+    /// the kind of the object
+    pub fn kind(&self) -> ObjectKind {
+        match self {
+            &Obj::Commit(_) => ObjectKind::Commit,
+            &Obj::Tree(_)   => ObjectKind::Tree,
+            &Obj::Blob(_)   => ObjectKind::Blob,
+            &Obj::Tag(_)    => ObjectKind::Tag
+        }
+    }
+
+    /// a one line description of the object: its kind and its hash
+    /// (`commit 11f766d25092064380879e792eedac662dd2ec29`)
+    ///
+    /// `Display` prints the body of the object only (as
+    /// `git cat-file -p`), this tells what is printed.
+    pub fn describe(&self) -> String {
+        match object_id::<H, _>(self) {
+            Ok(h) => format!("{} {}", self.kind(), h.to_hexadecimal()),
+            Err(_) => self.kind().to_string()
         }
     }
 }
@@ -52,10 +138,173 @@ impl<H: Hash+fmt::Display> fmt::Display for Obj<H> {
         match self {
             &Obj::Commit(ref c) => write!(f, "{}", c),
             &Obj::Tree(ref t)   => write!(f, "{}", t),
-            &Obj::Blob(ref b)   => write!(f, "{}", b)
+            &Obj::Blob(ref b)   => write!(f, "{}", b),
+            &Obj::Tag(ref t)    => write!(f, "{}", t)
+        }
+    }
+}
+impl<H: Hash> Encoder for Obj<H> {
+    fn required_size(&self) -> usize {
+        match self {
+            &Obj::Commit(ref c) => c.required_size(),
+            &Obj::Tree(ref t)   => t.required_size(),
+            &Obj::Blob(ref b)   => b.required_size(),
+            &Obj::Tag(ref t)    => t.required_size()
+        }
+    }
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
+        match self {
+            &Obj::Commit(ref c) => c.encode(writer),
+            &Obj::Tree(ref t)   => t.encode(writer),
+            &Obj::Blob(ref b)   => b.encode(writer),
+            &Obj::Tag(ref t)    => t.encode(writer)
         }
     }
 }
 impl<H: Hash> Object<H> for Obj<H> {
     type Id = H;
+    fn decode_content(kind: ObjectKind, content: &[u8]) -> nom::IResult<&[u8], Self> {
+        match kind {
+            ObjectKind::Commit => Commit::<H>::decode_content(kind, content).map(Obj::Commit),
+            ObjectKind::Tree   => Tree::<H>::decode_content(kind, content).map(Obj::Tree),
+            ObjectKind::Blob   => <Blob as Object<H>>::decode_content(kind, content).map(Obj::Blob),
+            ObjectKind::Tag    => Tag::<H>::decode_content(kind, content).map(Obj::Tag)
+        }
+    }
+}
+
+/// compute the hash the given object would get once stored in a
+/// repository (as `git hash-object` does), without writing it.
+///
+/// The hash covers the whole encoded object: the `<type> <size>\0` header
+/// written by `Encoder::encode` and the body.
+///
+/// # Example
+///
+/// ```
+/// use git::object::{object_id, Blob};
+/// use git::protocol::{SHA1, Hash};
+///
+/// let id : SHA1 = object_id(&Blob::new(b"hello\n".to_vec())).unwrap();
+/// assert_eq!(id.to_hexadecimal(), "ce013625030ba8dba906f756967f9e9ca394464a");
+/// ```
+pub fn object_id<H: Hash, O: Encoder>(obj: &O) -> Result<H> {
+    let data = try!(obj.encode_to_vec());
+    H::hash(&mut data.as_slice())
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::{Decoder, Hash, SHA1};
+
+    #[test]
+    fn empty_objects_id() {
+        assert_eq!(object_id::<SHA1, _>(&Tree::<SHA1>::new()).unwrap(), SHA1::empty_tree());
+        assert_eq!( SHA1::empty_tree()
+                  , SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap()
+                  );
+        assert_eq!(object_id::<SHA1, _>(&Blob::new(Vec::new())).unwrap(), SHA1::empty_blob());
+        assert_eq!( SHA1::empty_blob()
+                  , SHA1::from_hex("e69de29bb2d1d6434b8b29ae775ad8c2e48c5391").unwrap()
+                  );
+    }
+
+    #[test]
+    fn decode_unknown_object() {
+        assert_eq!( Obj::<SHA1>::decode(b"zebra 5\0hello").map(|_| ())
+                  , nom::IResult::Error(nom::ErrorKind::Alt)
+                  );
+        assert!(Obj::<SHA1>::decode(b"blob 5\0hello").is_done());
+    }
+
+    fn decode_kind(data: &[u8]) -> &'static str {
+        match Obj::<SHA1>::decode(data) {
+            nom::IResult::Done(_, Obj::Commit(_)) => "commit",
+            nom::IResult::Done(_, Obj::Tree(_))   => "tree",
+            nom::IResult::Done(_, Obj::Blob(_))   => "blob",
+            nom::IResult::Done(_, Obj::Tag(_))    => "tag",
+            _ => "error"
+        }
+    }
+
+    const COMMIT : &'static [u8] =
+        b"commit 169\0tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+          author Test <git-test@example.com> 1480007832 +0100\n\
+          committer Test <git-test@example.com> 1480007832 +0100\n\
+          \ninitial commit\n";
+    const TAG : &'static [u8] =
+        b"tag 136\0object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+          type commit\ntag v0.1\n\
+          tagger Test <git-test@example.com> 1480007832 +0100\n\
+          \nfirst release\n";
+
+    #[test]
+    fn decode_each_kind() {
+        assert_eq!(decode_kind(COMMIT), "commit");
+        assert_eq!(decode_kind(b"tree 0\0"), "tree");
+        assert_eq!(decode_kind(b"blob 5\0hello"), "blob");
+        assert_eq!(decode_kind(TAG), "tag");
+        // `tag` is not mistaken for a tree (and conversely)
+        assert_eq!(decode_kind(b"tagx 0\0"), "error");
+        assert_eq!(decode_kind(b"treetop 0\0"), "error");
+    }
+
+    #[test]
+    fn kind_from_tag_bytes() {
+        assert_eq!(ObjectKind::from_tag_bytes(b"commit 169\0tree"), Some(ObjectKind::Commit));
+        assert_eq!(ObjectKind::from_tag_bytes(b"tree 0\0"), Some(ObjectKind::Tree));
+        assert_eq!(ObjectKind::from_tag_bytes(b"blob 7"), Some(ObjectKind::Blob));
+        assert_eq!(ObjectKind::from_tag_bytes(b"tag "), Some(ObjectKind::Tag));
+        for unknown in [&b"tags 1\0"[..], b"tag", b"commit", b"Blob 7\0", b"note 3\0", b""].iter() {
+            assert_eq!(ObjectKind::from_tag_bytes(unknown), None);
+        }
+    }
+
+    #[test]
+    fn kind_of_each_object() {
+        let objects : Vec<(&[u8], ObjectKind)> =
+            vec![ (COMMIT, ObjectKind::Commit)
+                , (b"tree 0\0", ObjectKind::Tree)
+                , (b"blob 5\0hello", ObjectKind::Blob)
+                , (TAG, ObjectKind::Tag)
+                ];
+        for (data, kind) in objects {
+            assert_eq!(Obj::<SHA1>::decode(data).unwrap().1.kind(), kind);
+        }
+        let tree = Obj::<SHA1>::decode(b"tree 0\0").unwrap().1;
+        assert_eq!(tree.describe(), "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        let blob = Obj::<SHA1>::decode(b"blob 6\0hello\n").unwrap().1;
+        assert_eq!(blob.describe(), "blob ce013625030ba8dba906f756967f9e9ca394464a");
+    }
+
+    #[test]
+    fn encode_each_kind() {
+        let mut tree = b"tree 37\0100644 README.md\0".to_vec();
+        tree.extend_from_slice(SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap().as_bytes());
+        let objects = vec![COMMIT.to_vec(), tree, b"blob 5\0hello".to_vec(), TAG.to_vec()];
+        for data in objects {
+            let obj = Obj::<SHA1>::decode(&data).unwrap().1;
+            let mut encoded = Vec::new();
+            let size = obj.encode(&mut encoded).unwrap();
+            assert_eq!(size, encoded.len());
+            assert_eq!(encoded, data);
+        }
+    }
+
+    #[test]
+    fn object_id_as_git() {
+        // `echo "hello" | git hash-object --stdin`
+        let blob : SHA1 = object_id(&Blob::new(b"hello\n".to_vec())).unwrap();
+        assert_eq!(blob.to_hexadecimal(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        let empty : SHA1 = object_id(&Blob::new(Vec::new())).unwrap();
+        assert_eq!(empty.to_hexadecimal(), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        // `git hash-object -t tree /dev/null`
+        let tree : SHA1 = object_id(&Tree::<SHA1>::new()).unwrap();
+        assert_eq!(tree.to_hexadecimal(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    }
 }
@@ -4,6 +4,7 @@ use super::Date;
 use protocol::{Encoder, Decoder};
 use nom;
 use std::{str, io, fmt};
+use std::borrow::Cow;
 
 /// Git's Person data type
 ///
@@ -28,6 +29,13 @@ use std::{str, io, fmt};
 /// There is not validation of the username or the email address.
 /// This is customer's responsabilities to store valide data (or not).
 ///
+/// ## Encoding
+///
+/// The name and the email are kept as they were read: old repositories may
+/// have them in another encoding than UTF-8 (Latin-1...). They are encoded
+/// back byte for byte, only `name`, `email` and `Display` decode them
+/// (lossily, the invalid sequences are replaced by `U+FFFD`).
+///
 /// # Example
 ///
 /// ```
@@ -41,8 +49,8 @@ use std::{str, io, fmt};
 ///
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Person {
-    name:  String,
-    email: String,
+    name:  Vec<u8>,
+    email: Vec<u8>,
     date:  Date
 }
 
@@ -70,8 +78,8 @@ impl Person {
     /// ```
     pub fn new(name: String, email: String, date: Date) -> Self {
         Person {
-            name: name,
-            email: email,
+            name: name.into_bytes(),
+            email: email.into_bytes(),
             date: date
         }
     }
@@ -86,7 +94,10 @@ impl Person {
     /// let me_now = Person::now(name.to_string(), email);
     /// assert_eq!(name, me_now.name());
     /// ```
-    pub fn name(&self) -> &str { self.name.as_str() }
+    pub fn name(&self) -> Cow<str> { String::from_utf8_lossy(&self.name) }
+
+    /// access the `Person`'s name as it is stored (not necessarily UTF-8)
+    pub fn name_bytes(&self) -> &[u8] { &self.name }
 
     /// access the `Person`'s email address
     ///
@@ -98,7 +109,11 @@ impl Person {
     /// let me_now = Person::now(name, email.to_string());
     /// assert_eq!(email, me_now.email());
     /// ```
-    pub fn email(&self) -> &str { self.email.as_str() }
+    pub fn email(&self) -> Cow<str> { String::from_utf8_lossy(&self.email) }
+
+    /// access the `Person`'s email address as it is stored (not necessarily
+    /// UTF-8)
+    pub fn email_bytes(&self) -> &[u8] { &self.email }
 
     /// access the `Person`'s date
     ///
@@ -113,19 +128,10 @@ impl Person {
     /// assert_eq!(&date, me_now.date());
     /// ```
     pub fn date(&self) -> &Date { &self.date }
-
-    // not public
-    fn new_str(name: &str, email: &str, date: Date) -> Self {
-        Person::new(name.to_string(),email.to_string(), date)
-    }
-    /// not public
-    fn encode_name_email(&self) -> String {
-        format!("{} <{}> ", self.name, self.email).to_string()
-    }
 }
 impl fmt::Display for Person {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} <{}> {}", self.name, self.email, self.date.encode_for_obj())
+        write!(f, "{} <{}> {}", self.name(), self.email(), self.date.encode_for_obj())
     }
 }
 impl Decoder for Person {
@@ -133,21 +139,24 @@ impl Decoder for Person {
 }
 impl Encoder for Person {
     fn required_size(&self) -> usize {
-        self.encode_name_email().len() + self.date.required_size()
+        self.name.len() + 2 + self.email.len() + 2 + self.date.required_size()
     }
     fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
-        let ne = self.encode_name_email();
-        try!(writer.write_all(ne.as_bytes()));
+        try!(writer.write_all(&self.name));
+        try!(writer.write_all(b" <"));
+        try!(writer.write_all(&self.email));
+        try!(writer.write_all(b"> "));
         let d_len = try!(self.date.encode(writer));
-        Ok(ne.len() + d_len)
+        Ok(self.name.len() + 2 + self.email.len() + 2 + d_len)
     }
 }
 
+// the name and the email are kept as they are, whatever their encoding
 named!( nom_parse_person<Person>
-      , chain!( name:  map_res!(take_until_and_consume!(" <"), str::from_utf8)
-              ~ email: map_res!(take_until_and_consume!("> "), str::from_utf8)
+      , chain!( name:  take_until_and_consume!(" <")
+              ~ email: take_until_and_consume!("> ")
               ~ date:  call!(Date::decode)
-              , || Person::new_str(name, email, date)
+              , || Person { name: name.to_vec(), email: email.to_vec(), date: date }
               )
       );
 
@@ -166,4 +175,17 @@ mod test {
         let p = Person::now("Nicolas".to_string(), "my@email.address".to_string());
         test_encoder_decoder(p);
     }
+
+    #[test]
+    fn latin1_name() {
+        let p = Person::decode(b"Jos\xe9 M\xfcller <jose@example.com> 1480007832 +0000\n").unwrap().1;
+        assert_eq!(p.name(), "Jos\u{fffd} M\u{fffd}ller");
+        assert_eq!(p.name_bytes(), b"Jos\xe9 M\xfcller");
+        assert_eq!(p.email(), "jose@example.com");
+        assert_eq!(p.date(), &Date::seconds_since_epoch(1480007832));
+        // encoded back as it was read
+        let mut data = Vec::new();
+        assert_eq!(p.encode(&mut data).unwrap(), p.required_size());
+        assert_eq!(&data[..], &b"Jos\xe9 M\xfcller <jose@example.com> 1480007832 +0000"[..]);
+    }
 }
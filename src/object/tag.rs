@@ -0,0 +1,186 @@
+//! Git's annotated Tag
+
+use super::person::Person;
+use protocol::{Encoder, Decoder, Hash};
+use std::{io, fmt, convert, str};
+use nom;
+use error::Result;
+
+/// Tag reference
+///
+/// This is simply a strongly typed version of the `Hash` given Hash
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub struct TagRef<H: Hash>(H);
+impl<H: Hash> TagRef<H> {
+    pub fn new(h: H) -> Self { TagRef(h) }
+}
+impl<H: Hash + fmt::Display> fmt::Display for TagRef<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.0, f) }
+}
+
+impl<H: Hash> Hash for TagRef<H> {
+    fn hash<R: io::BufRead>(data: &mut R) -> Result<Self> {
+        H::hash(data).map(|h| TagRef(h))
+    }
+
+    fn from_bytes(v: Vec<u8>) -> Option<Self> {
+        H::from_bytes(v).map(|h| TagRef(h))
+    }
+
+    #[inline]
+    fn digest_size() -> usize { H::digest_size() }
+
+    #[inline]
+    fn as_bytes(&self) -> &[u8] { self.0.as_bytes() }
+}
+impl<H: Hash> convert::AsRef<H> for TagRef<H> {
+    fn as_ref(&self) -> &H { &self.0 }
+}
+
+/// Git annotated tag
+///
+/// Unlike the lightweight tags (a reference pointing directly to a
+/// commit), an annotated tag is an object on its own:
+///
+/// * `object`: the hash of the tagged object;
+/// * `object_type`: the type of the tagged object (`commit`, `tree`...);
+/// * `name`: the name of the tag;
+/// * `tagger`: who created the tag, and when;
+/// * `message`: the tag's message.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tag<H: Hash> {
+    pub object: H,
+    pub object_type: String,
+    pub name: String,
+    pub tagger: Person,
+    pub message: String
+}
+impl<H: Hash> Tag<H> {
+    /// write the content of the tag (without the object header): the
+    /// tagger is written as it was read, even if it is not UTF-8 (see
+    /// `Person`)
+    fn write_content<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        try!(write!(w, "object {}\n", self.object.to_hexadecimal()));
+        try!(write!(w, "type {}\ntag {}\n", self.object_type, self.name));
+        try!(w.write_all(b"tagger "));
+        try!(self.tagger.encode(w));
+        write!(w, "\n\n{}", self.message)
+    }
+}
+impl<H: Hash> fmt::Display for Tag<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut data = Vec::with_capacity(self.required_size());
+        try!(self.write_content(&mut data).map_err(|_| fmt::Error));
+        f.write_str(&String::from_utf8_lossy(&data))
+    }
+}
+impl<H: Hash> Decoder for Tag<H> {
+    fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> { nom_parse_tag(b) }
+}
+impl<H: Hash> Encoder for Tag<H> {
+    fn required_size(&self) -> usize {
+        0 + 7 + H::digest_hex_size() + 1
+          + 5 + self.object_type.len() + 1
+          + 4 + self.name.len() + 1
+          + 7 + self.tagger.required_size() + 1
+          + 1 + self.message.len()
+    }
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut data = Vec::with_capacity(self.required_size());
+        try!(self.write_content(&mut data));
+        let head = format!("tag {}\0", data.len());
+        try!(writer.write_all(head.as_bytes()));
+        try!(writer.write_all(&data));
+        Ok(head.len() + data.len())
+    }
+}
+
+named!(nom_parse_tag_tag, tag!("tag "));
+named!(nom_parse_tag_size<usize>
+      , map_res!( map_res!( nom::digit, str::from_utf8), str::FromStr::from_str)
+      );
+named!(nom_parse_tag_head<usize>
+      , chain!(nom_parse_tag_tag ~ r: nom_parse_tag_size ~ char!('\0'), || r)
+      );
+named!(nom_parse_tag_line<&str>
+      , map_res!(take_until_and_consume!("\n"), str::from_utf8)
+      );
+fn nom_parse_tag<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Tag<H>> {
+    let (b, size) = try_parse!(b, nom_parse_tag_head);
+    if b.len() < size {
+        return nom::IResult::Incomplete(nom::Needed::Size(size - b.len()));
+    }
+    // the message goes up to the size given in the header, not further
+    let (b, rest) = (&b[..size], &b[size..]);
+    let (_, tag) = try_parse!(b, nom_parse_tag_content);
+    nom::IResult::Done(rest, tag)
+}
+/// the content of a tag, without its header: the message goes up to the
+/// end of the input
+pub fn nom_parse_tag_content<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Tag<H>> {
+    let (b, _) = try_parse!(b, tag!("object "));
+    let (b, o) = try_parse!(b, H::decode_hex);
+    let (b, _) = try_parse!(b, tag!("\n"));
+    let (b, _) = try_parse!(b, tag!("type "));
+    let (b, t) = try_parse!(b, nom_parse_tag_line);
+    let (b, _) = try_parse!(b, tag!("tag "));
+    let (b, n) = try_parse!(b, nom_parse_tag_line);
+    let (b, _) = try_parse!(b, tag!("tagger "));
+    let (b, p) = try_parse!(b, Person::decode);
+    let (b, _) = try_parse!(b, tag!("\n\n"));
+    let (b, m) = try_parse!(b, map_res!(nom::rest, str::from_utf8));
+    nom::IResult::Done(
+        b,
+        Tag {
+            object: o,
+            object_type: t.to_string(),
+            name: n.to_string(),
+            tagger: p,
+            message: m.to_string()
+        }
+    )
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::protocol::{test_encoder_decoder, test_decode_encode};
+    use ::protocol::SHA1;
+    use ::object::Date;
+    use rustc_serialize::base64::FromBase64;
+
+    // tag as encoded by the original git (+ encoded in base64)
+    const SMOCK_TEST : &'static str =
+        "dGFnIDE0NwBvYmplY3QgMTFmNzY2ZDI1MDkyMDY0MzgwODc5ZTc5MmVlZGFjNjYyZGQy\
+         ZWMyOQp0eXBlIGNvbW1pdAp0YWcgdjAuMQp0YWdnZXIgTmljb2xhcyBEaSBQcmltYSA8\
+         bmljb2xhc0BkaS1wcmltYS5mcj4gMTQ4MDAwNzgzMiArMDEwMAoKZmlyc3QgcmVsZWFz\
+         ZQo=";
+
+    #[test]
+    fn regression_test() {
+        let data = SMOCK_TEST.from_base64().unwrap();
+        test_decode_encode::<Tag<SHA1>>(data);
+    }
+    #[test]
+    fn tag_serialisable() {
+        let tag : Tag<SHA1> = Tag {
+            object: SHA1::from_hex("11f766d25092064380879e792eedac662dd2ec29").unwrap(),
+            object_type: "commit".to_string(),
+            name: "v0.1".to_string(),
+            tagger: Person::new( "Nicolas".to_string()
+                               , "my@email.address".to_string()
+                               , Date::seconds_since_epoch(1480007832)
+                               ),
+            message: "first release\n\nwith a longer description\n".to_string()
+        };
+        let mut v = Vec::new();
+        let sz = tag.encode(&mut v).unwrap();
+        assert_eq!(sz, v.len());
+        assert_eq!(sz, format!("tag {}\0", tag.required_size()).len() + tag.required_size());
+        test_encoder_decoder(tag);
+    }
+}
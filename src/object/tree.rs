@@ -1,7 +1,9 @@
 //! Git's Tree
-use protocol::{Encoder, Decoder, Hash};
+use protocol::{Encoder, Decoder, Hash, same_hash};
 use super::blob::BlobRef;
-use error::Result;
+use super::commit::CommitRef;
+use super::object_id;
+use error::{Result, GitError};
 use std::{io, fmt, str, collections, path, cmp, borrow, iter, ops, convert};
 use nom;
 
@@ -125,9 +127,6 @@ fn permission_write(ps: &PermissionSet) -> usize {
     if ps.contains(&Permission::Executable) { set += 1 }
     set
 }
-named!( nom_parse_permission_set<PermissionSet>
-      , chain!(b : take!(1), || PermissionSet::new_from_byte(b[0]))
-      );
 
 /// Permissions for a given entity
 ///
@@ -137,9 +136,14 @@ named!( nom_parse_permission_set<PermissionSet>
 /// * group: the set of `Permission` applies to the group;
 /// * other: the set of `Permission` applies to the other.
 ///
-/// # TODO
+/// # Example
+///
+/// ```
+/// use git::object::Permissions;
 ///
-/// * from_str: being able to recognize octal strings ("0777", "0644")
+/// assert_eq!(Permissions::from_octal_str("0644"), Some(Permissions::default_file()));
+/// assert_eq!(Permissions::from_octal_str("755"), Some(Permissions::default_exe()));
+/// ```
 ///
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Permissions {
@@ -177,15 +181,38 @@ impl Permissions {
             other: PermissionSet::new_from_byte(b'5')
         }
     }
+
+    /// create the permissions from their octal representation: 3 octal
+    /// digits (user, group and other), with an optional leading `0`
+    /// (`"644"`, `"0755"`...). Returns `None` if the string is not valid.
+    pub fn from_octal_str(s: &str) -> Option<Self> {
+        let digits = if s.len() == 4 && s.starts_with('0') { &s[1..] } else { s };
+        let bytes = digits.as_bytes();
+        if bytes.len() != 3 || ! bytes.iter().all(|b| b'0' <= *b && *b <= b'7') {
+            return None
+        }
+        Some(Permissions {
+            user:  PermissionSet::new_from_byte(bytes[0]),
+            group: PermissionSet::new_from_byte(bytes[1]),
+            other: PermissionSet::new_from_byte(bytes[2])
+        })
+    }
+
+    /// the permission bits of a file mode (`0o644`, `0o755`...)
+    fn to_mode(&self) -> u32 {
+        ((permission_write(&self.user) as u32) << 6)
+            | ((permission_write(&self.group) as u32) << 3)
+            | (permission_write(&self.other) as u32)
+    }
+    /// create the permissions from the permission bits of a file mode
+    fn from_mode(mode: u32) -> Self {
+        Permissions {
+            user:  PermissionSet::new_from_byte(b'0' + ((mode >> 6) & 0o7) as u8),
+            group: PermissionSet::new_from_byte(b'0' + ((mode >> 3) & 0o7) as u8),
+            other: PermissionSet::new_from_byte(b'0' + (mode & 0o7) as u8)
+        }
+    }
 }
-named!( tree_ent_parse_permissions<Permissions>
-      , chain!( tag!("0")
-              ~ user: nom_parse_permission_set
-              ~ group: nom_parse_permission_set
-              ~ other: nom_parse_permission_set
-              , || Permissions { user:user, group:group, other:other }
-              )
-      );
 impl fmt::Display for Permissions {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{extras}{user}{group}{other}"
@@ -197,74 +224,163 @@ impl fmt::Display for Permissions {
     }
 }
 
+impl Decoder for PermissionSet {
+    /// a single octal digit
+    fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> {
+        match b.first() {
+            None => nom::IResult::Incomplete(nom::Needed::Size(1)),
+            Some(c) if nom::is_oct_digit(*c) => nom::IResult::Done(&b[1..], PermissionSet::new_from_byte(*c)),
+            Some(_) => nom::IResult::Error(nom::ErrorKind::OctDigit)
+        }
+    }
+}
+impl Encoder for PermissionSet {
+    fn required_size(&self) -> usize { 1 }
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
+        try!(writer.write_all(&[self.to_char() as u8]));
+        Ok(1)
+    }
+}
+impl Decoder for Permissions {
+    /// the octal representation of the permissions, as accepted by
+    /// `Permissions::from_octal_str` (`644`, `0755`...)
+    fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> {
+        let (i, digits) = try_parse!(b, map_res!(take_while1!(nom::is_oct_digit), str::from_utf8));
+        match Permissions::from_octal_str(digits) {
+            Some(p) => nom::IResult::Done(i, p),
+            None => nom::IResult::Error(nom::ErrorKind::OctDigit)
+        }
+    }
+}
+impl Encoder for Permissions {
+    /// as `Display`: 4 octal digits (`0644`...)
+    fn required_size(&self) -> usize { 4 }
+    fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let data = format!("{}", self);
+        try!(writer.write_all(data.as_bytes()));
+        Ok(data.len())
+    }
+}
+
 /// the different type of entity managed by our current implementation
 ///
 /// * Tree: reference with a permission to a sub tree (recursive entry).
 ///         This is equivalent to a filepath directory.
 /// * Blob: reference with a permission to a blob of data.
 ///         This is equivalent to a file.
+/// * SymbolicLink: reference to a blob containing the path the link
+///         points to.
+/// * GitLink: reference to a commit of another repository (a submodule).
 ///
 #[derive(Debug, Clone)]
 pub enum TreeEnt<H: Hash> {
     Tree(Permissions, path::PathBuf, TreeRef<H>),
-    Blob(Permissions, path::PathBuf, BlobRef<H>)
-    /*
-    TODO: add missing:
-    SymbolicLink(Permissions, PathBuf, HashRef<SHA1>),
-    GitLink(Permissions, PathBuf, HashRef<SHA1>)
-    */
+    Blob(Permissions, path::PathBuf, BlobRef<H>),
+    SymbolicLink(Permissions, path::PathBuf, BlobRef<H>),
+    GitLink(Permissions, path::PathBuf, CommitRef<H>)
 }
 impl<H: Hash> TreeEnt<H> {
     fn get_file_path(&self) -> &path::PathBuf {
         match self {
             &TreeEnt::Tree(_, ref pb, _) => pb,
-            &TreeEnt::Blob(_, ref pb, _) => pb
+            &TreeEnt::Blob(_, ref pb, _) => pb,
+            &TreeEnt::SymbolicLink(_, ref pb, _) => pb,
+            &TreeEnt::GitLink(_, ref pb, _) => pb
         }
     }
     fn get_ent_type_str(&self) -> &'static str {
         match self {
             &TreeEnt::Tree(_, _, _) => "tree",
-            &TreeEnt::Blob(_, _, _) => "blob"
+            &TreeEnt::Blob(_, _, _) => "blob",
+            &TreeEnt::SymbolicLink(_, _, _) => "blob",
+            &TreeEnt::GitLink(_, _, _) => "commit"
         }
     }
-    fn get_ent_type(&self) -> &'static str {
-        match self {
-            &TreeEnt::Tree(_, _, _) => "4",
-            &TreeEnt::Blob(_, _, _) => "10"
-        }
-    }
-    fn display_ent_type(&self) -> &'static str {
-        match self {
-            &TreeEnt::Tree(_, _, _) => "04",
-            &TreeEnt::Blob(_, _, _) => "10"
-        }
+    /// the git mode of the entry: the type of the entry (see `MODE_TREE`...)
+    /// and the permissions
+    fn get_mode(&self) -> u32 {
+        let ty = match self {
+            &TreeEnt::Tree(_, _, _) => MODE_TREE,
+            &TreeEnt::Blob(_, _, _) => MODE_BLOB,
+            &TreeEnt::SymbolicLink(_, _, _) => MODE_SYMLINK,
+            &TreeEnt::GitLink(_, _, _) => MODE_GITLINK
+        };
+        ty | self.get_premission().to_mode()
     }
     fn get_premission(&self) -> &Permissions {
         match self {
             &TreeEnt::Tree(ref p, _, _) => p,
-            &TreeEnt::Blob(ref p, _, _) => p
+            &TreeEnt::Blob(ref p, _, _) => p,
+            &TreeEnt::SymbolicLink(ref p, _, _) => p,
+            &TreeEnt::GitLink(ref p, _, _) => p
         }
     }
     fn get_hash_hex(&self) -> String { self.get_hash().to_hexadecimal() }
-    fn get_hash(&self) -> &H {
+    /// the hash of the object the entry points to
+    pub fn get_hash(&self) -> &H {
         match self {
             &TreeEnt::Tree(_, _, ref pb) => pb.as_ref(),
-            &TreeEnt::Blob(_, _, ref pb) => pb.as_ref()
+            &TreeEnt::Blob(_, _, ref pb) => pb.as_ref(),
+            &TreeEnt::SymbolicLink(_, _, ref pb) => pb.as_ref(),
+            &TreeEnt::GitLink(_, _, ref pb) => pb.as_ref()
         }
     }
-    fn new_from(ty: &str, perm: Permissions, path: path::PathBuf, h: H) -> Self {
-        match ty {
-            "10" => TreeEnt::Blob(perm, path, BlobRef::new(h)),
-            "4"  => TreeEnt::Tree(perm, path, TreeRef::new(h)),
-            _ => panic!("unexpected type")
+    /// compare the entries in git's order: the names are compared byte per
+    /// byte, as if the names of the sub-trees ended with a `/`
+    ///
+    /// This is the order of the entries in an encoded tree (`lib.rs` comes
+    /// before the sub-tree `lib`, which is not the order of the paths).
+    pub fn canonical_cmp(&self, other: &Self) -> cmp::Ordering {
+        fn name_and_end<H: Hash>(te: &TreeEnt<H>) -> (&[u8], Option<u8>) {
+            let name = te.get_file_path().to_str().unwrap().as_bytes();
+            match te {
+                &TreeEnt::Tree(_, _, _) => (name, Some(b'/')),
+                _ => (name, None)
+            }
+        }
+        let (a, a_end) = name_and_end(self);
+        let (b, b_end) = name_and_end(other);
+        let len = cmp::min(a.len(), b.len());
+        match a[..len].cmp(&b[..len]) {
+            cmp::Ordering::Equal => {},
+            ord => return ord
+        }
+        let a_next = a.get(len).cloned().or(a_end);
+        let b_next = b.get(len).cloned().or(b_end);
+        a_next.cmp(&b_next)
+    }
+    /// create the entry from its git mode, `None` if the mode is not
+    /// supported.
+    fn new_from(mode: u32, path: path::PathBuf, h: H) -> Option<Self> {
+        if mode & !(MODE_TYPE_MASK | 0o777) != 0 {
+            return None
+        }
+        let perm = Permissions::from_mode(mode);
+        match mode & MODE_TYPE_MASK {
+            MODE_BLOB => Some(TreeEnt::Blob(perm, path, BlobRef::new(h))),
+            MODE_TREE => Some(TreeEnt::Tree(perm, path, TreeRef::new(h))),
+            MODE_SYMLINK => Some(TreeEnt::SymbolicLink(perm, path, BlobRef::new(h))),
+            MODE_GITLINK => Some(TreeEnt::GitLink(perm, path, CommitRef::new(h))),
+            _ => None
         }
     }
 }
+
+/// mask of the type of the entry in a git mode
+const MODE_TYPE_MASK : u32 = 0o170000;
+/// type of the sub-trees (`40000`)
+const MODE_TREE : u32 = 0o040000;
+/// type of the regular files (`100644`, `100755`)
+const MODE_BLOB : u32 = 0o100000;
+/// type of the symbolic links (`120000`)
+const MODE_SYMLINK : u32 = 0o120000;
+/// type of the submodules (`160000`)
+const MODE_GITLINK : u32 = 0o160000;
+
 impl<H: Hash> fmt::Display for TreeEnt<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{type_byte}{perms} {type} {hash}\t{name}"
-              , type_byte = self.display_ent_type()
-              , perms = self.get_premission()
+        write!(f, "{mode:06o} {type} {hash}\t{name}"
+              , mode = self.get_mode()
               , type = self.get_ent_type_str()
               , hash = self.get_hash_hex()
               , name = self.get_file_path().to_str().unwrap()
@@ -290,24 +406,25 @@ impl<H: Hash> borrow::Borrow<path::PathBuf> for TreeEnt<H> {
 }
 impl<H: Hash> Decoder for TreeEnt<H> {
     fn decode(b: &[u8]) -> nom::IResult<&[u8], Self> {
-        let (i, (ty, perm, p)) = try_parse!(b, nom_parse_tree_ent_head);
+        let (i, (mode, p)) = try_parse!(b, nom_parse_tree_ent_head);
         let (i, h) = try_parse!(i, H::decode_bytes);
-        nom::IResult::Done(i, TreeEnt::new_from(ty, perm, p, h))
+        match TreeEnt::new_from(mode, p, h) {
+            Some(te) => nom::IResult::Done(i, te),
+            None => nom::IResult::Error(nom::ErrorKind::Custom(0))
+        }
     }
 }
 impl<H: Hash> Encoder for TreeEnt<H> {
     fn required_size(&self) -> usize {
-        let data = format!( "{}{} {}\0"
-                          , self.get_ent_type()
-                          , self.get_premission()
+        let data = format!( "{:o} {}\0"
+                          , self.get_mode()
                           , self.get_file_path().to_str().unwrap()
                           );
         data.len() + H::digest_size()
     }
     fn encode<W: io::Write>(&self, writer: &mut W) -> io::Result<usize> {
-        let data = format!( "{}{} {}\0"
-                          , self.get_ent_type()
-                          , self.get_premission()
+        let data = format!( "{:o} {}\0"
+                          , self.get_mode()
                           , self.get_file_path().to_str().unwrap()
                           );
         try!(writer.write_all(data.as_bytes()));
@@ -320,15 +437,32 @@ named!( nom_parse_path<path::PathBuf>
               , || path::PathBuf::new().join(path_str)
               )
       );
-named!( nom_parse_tree_ent_head<(&str, Permissions, path::PathBuf)>
-      , chain!( t: map_res!( alt!( tag!("4") | tag!("10")) , str::from_utf8)
-              ~ perm: tree_ent_parse_permissions
+named!( nom_parse_tree_ent_mode<u32>
+      , map_res!( map_res!(take_while1!(nom::is_oct_digit), str::from_utf8)
+                , |s| u32::from_str_radix(s, 8)
+                )
+      );
+named!( nom_parse_tree_ent_head<(u32, path::PathBuf)>
+      , chain!( mode: nom_parse_tree_ent_mode
               ~ tag!(" ")
               ~ path: nom_parse_path
-              , || (t, perm, path)
+              , || (mode, path)
               )
       );
 
+/// a difference between two trees (see `Tree::diff`)
+///
+/// * Added: the entry is only in the new tree;
+/// * Removed: the entry is only in the old tree;
+/// * Modified: the path is in both trees but the hash (or the mode) of the
+///   entry changed: `(path, old hash, new hash)`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum TreeDiff<H: Hash> {
+    Added(TreeEnt<H>),
+    Removed(TreeEnt<H>),
+    Modified(path::PathBuf, H, H)
+}
+
 ///
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct Tree<H: Hash>(collections::BTreeSet<TreeEnt<H>>);
@@ -336,6 +470,13 @@ impl<H: Hash> Tree<H> {
     pub fn new_with(bt: collections::BTreeSet<TreeEnt<H>>) -> Self { Tree(bt) }
     pub fn new() -> Self { Tree(collections::BTreeSet::new()) }
     pub fn iter(&self) -> collections::btree_set::Iter<TreeEnt<H>> { self.0.iter() }
+    /// iterate over the entries in git's order (see `TreeEnt::canonical_cmp`),
+    /// the order in which they are encoded
+    pub fn iter_canonical(&self) -> ::std::vec::IntoIter<&TreeEnt<H>> {
+        let mut entries : Vec<&TreeEnt<H>> = self.0.iter().collect();
+        entries.sort_by(|a, b| a.canonical_cmp(b));
+        entries.into_iter()
+    }
     pub fn difference<'a>(&'a self, other: &'a Self)
         -> collections::btree_set::Difference<'a, TreeEnt<H>>
     {
@@ -356,6 +497,42 @@ impl<H: Hash> Tree<H> {
     {
         self.0.union(&other.0)
     }
+    /// list the differences between this tree and the `other` one (the
+    /// new version), sorted by path. This is not recursive: a sub-tree is
+    /// reported as `Modified` and its content has to be compared too.
+    ///
+    /// Unlike the set operations (`difference`...), which only compare the
+    /// paths, the hashes and the modes of the entries are compared too.
+    pub fn diff<'a>(&'a self, other: &'a Tree<H>) -> Vec<TreeDiff<H>>
+        where H: Clone
+    {
+        let mut diffs = Vec::new();
+        let mut olds = self.iter().peekable();
+        let mut news = other.iter().peekable();
+        loop {
+            let order = match (olds.peek(), news.peek()) {
+                (None, None) => break,
+                (Some(_), None) => cmp::Ordering::Less,
+                (None, Some(_)) => cmp::Ordering::Greater,
+                (Some(old), Some(new)) => old.cmp(new)
+            };
+            match order {
+                cmp::Ordering::Less => diffs.push(TreeDiff::Removed(olds.next().unwrap().clone())),
+                cmp::Ordering::Greater => diffs.push(TreeDiff::Added(news.next().unwrap().clone())),
+                cmp::Ordering::Equal => {
+                    let (old, new) = (olds.next().unwrap(), news.next().unwrap());
+                    if ! same_hash(old.get_hash(), new.get_hash())
+                        || old.get_mode() != new.get_mode() {
+                        diffs.push(TreeDiff::Modified( old.get_file_path().clone()
+                                                     , old.get_hash().clone()
+                                                     , new.get_hash().clone()
+                                                     ));
+                    }
+                }
+            }
+        }
+        diffs
+    }
     pub fn len(&self) -> usize { self.0.len() }
     pub fn is_empty(&self) -> bool { self.0.is_empty() }
     pub fn clear(&mut self) { self.0.clear() }
@@ -407,7 +584,7 @@ impl<'a, 'b, H: Hash+Clone> ops::BitOr<&'b Tree<H>> for &'a Tree<H> {
 }
 impl<H: Hash> fmt::Display for Tree<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for te in self.iter() {
+        for te in self.iter_canonical() {
             try!(write!(f, "{}\n", te));
         }
         Ok(())
@@ -421,7 +598,7 @@ impl<H: Hash> Encoder for Tree<H> {
         let head = format!("tree {}\0", self.required_size());
         let mut sz = head.len();
         try!(writer.write_all(head.as_bytes()));
-        for te in self.iter() {
+        for te in self.iter_canonical() {
             sz += try!(te.encode(writer));
         }
         Ok(sz)
@@ -435,6 +612,99 @@ impl<H: Hash> Encoder for Tree<H> {
     }
 }
 
+/// node of the hierarchy of a `TreeBuilder`
+enum TreeBuilderNode<H: Hash> {
+    File(BlobRef<H>, Permissions),
+    Dir(collections::BTreeMap<path::PathBuf, TreeBuilderNode<H>>)
+}
+
+/// build the `Tree`s of a whole hierarchy of files
+///
+/// The files are inserted with their full path (`src/main.rs`), the
+/// sub-trees (`src`) are created as needed. `build` returns every `Tree`
+/// with its `TreeRef`, the sub-trees before their parent: they can be
+/// written in this order (the root tree is the last one).
+///
+/// # Example
+///
+/// ```
+/// use git::object::{TreeBuilder, BlobRef, Permissions};
+/// use git::protocol::{SHA1, Hash};
+///
+/// let blob = BlobRef::new(SHA1::from_hex("ce013625030ba8dba906f756967f9e9ca394464a").unwrap());
+/// let mut builder = TreeBuilder::<SHA1>::new();
+/// builder.insert("src/main.rs", blob, Permissions::default_file()).unwrap();
+/// let trees = builder.build().unwrap();
+/// assert_eq!(trees.len(), 2);
+/// ```
+pub struct TreeBuilder<H: Hash> {
+    root: collections::BTreeMap<path::PathBuf, TreeBuilderNode<H>>
+}
+impl<H: Hash> TreeBuilder<H> {
+    pub fn new() -> Self { TreeBuilder { root: collections::BTreeMap::new() } }
+
+    /// add a file to the hierarchy
+    ///
+    /// Fails if the path is not a relative path of normal components (no
+    /// `..`) or if it conflicts with a file already inserted (`src` and
+    /// `src/main.rs`). A file inserted twice is replaced.
+    pub fn insert<P: AsRef<path::Path>>(&mut self, p: P, blob: BlobRef<H>, perm: Permissions)
+        -> Result<()>
+    {
+        let invalid = || GitError::Other(format!("invalid tree path: {}", p.as_ref().display()));
+        let mut names = Vec::new();
+        for c in p.as_ref().components() {
+            match c {
+                path::Component::Normal(name) => names.push(path::PathBuf::from(name)),
+                _ => return Err(invalid())
+            }
+        }
+        let file = match names.pop() { Some(file) => file, None => return Err(invalid()) };
+        let mut dir = &mut self.root;
+        for name in names {
+            let node = dir.entry(name).or_insert_with(|| TreeBuilderNode::Dir(collections::BTreeMap::new()));
+            dir = match node {
+                &mut TreeBuilderNode::Dir(ref mut sub) => sub,
+                &mut TreeBuilderNode::File(_, _) => return Err(invalid())
+            };
+        }
+        if let Some(&TreeBuilderNode::Dir(_)) = dir.get(&file) {
+            return Err(invalid())
+        }
+        dir.insert(file, TreeBuilderNode::File(blob, perm));
+        Ok(())
+    }
+
+    /// compute the trees of the hierarchy, bottom-up: the root tree is the
+    /// last one.
+    pub fn build(self) -> Result<Vec<(TreeRef<H>, Tree<H>)>> {
+        let mut trees = Vec::new();
+        try!(build_tree(self.root, &mut trees));
+        Ok(trees)
+    }
+}
+
+fn build_tree<H: Hash>( dir: collections::BTreeMap<path::PathBuf, TreeBuilderNode<H>>
+                      , trees: &mut Vec<(TreeRef<H>, Tree<H>)>
+                      ) -> Result<TreeRef<H>>
+{
+    let mut tree = Tree::new();
+    for (name, node) in dir {
+        let te = match node {
+            TreeBuilderNode::File(blob, perm) => TreeEnt::Blob(perm, name, blob),
+            TreeBuilderNode::Dir(sub) => {
+                let tr = try!(build_tree(sub, trees));
+                TreeEnt::Tree(Permissions::default_dir(), name, tr)
+            }
+        };
+        tree.insert(te);
+    }
+    let id : TreeRef<H> = try!(object_id(&tree));
+    let copy = TreeRef::from_bytes(id.as_bytes().to_vec()).expect("hash of the same size");
+    trees.push((id, tree));
+    Ok(copy)
+}
+
 named!(nom_parse_tree_tag, tag!("tree "));
 named!(nom_parse_tree_size<usize>
       , map_res!( map_res!( nom::digit, str::from_utf8), str::FromStr::from_str)
@@ -443,16 +713,25 @@ named!(nom_parse_tree_head<usize>
       , chain!(nom_parse_tree_tag ~ r: nom_parse_tree_size ~ char!('\0'), || r)
       );
 fn nom_parse_tree<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Tree<H>> {
-    let (mut b, size) = try_parse!(b, nom_parse_tree_head);
+    let (b, size) = try_parse!(b, nom_parse_tree_head);
     if b.len() < size {
         return nom::IResult::Incomplete(nom::Needed::Size(size - b.len()));
     }
+    let (entries, tree) = try_parse!(&b[..size], nom_parse_tree_content);
+    // the entries which could not be decoded are left, followed by the
+    // bytes after the tree
+    nom::IResult::Done(&b[size - entries.len()..], tree)
+}
+/// the entries of a tree, without its header: the entries which could not
+/// be decoded are left
+pub fn nom_parse_tree_content<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Tree<H>> {
     let mut tree = Tree::new();
-    while let nom::IResult::Done(i, te) = TreeEnt::<H>::decode(b) {
+    let mut entries = b;
+    while let nom::IResult::Done(i, te) = TreeEnt::<H>::decode(entries) {
         tree.insert(te);
-        b = i;
+        entries = i;
     }
-    nom::IResult::Done(b, tree)
+    nom::IResult::Done(entries, tree)
 }
 
 // -- --------------------------------------------------------------------- --
@@ -463,11 +742,53 @@ fn nom_parse_tree<H: Hash>(b: &[u8]) -> nom::IResult<&[u8], Tree<H>> {
 mod test {
     use super::*;
     use ::object::blob::BlobRef;
+    use ::object::commit::CommitRef;
     use ::protocol::{test_encoder_decoder, test_decode_encode};
     use ::protocol::{SHA1, Hash};
     use std::path::PathBuf;
     use rustc_serialize::base64::FromBase64;
 
+    #[test]
+    fn permissions_serialisable() {
+        for user in 0..8u8 {
+            let set = PermissionSet::new_from_byte(b'0' + user);
+            test_encoder_decoder(set.clone());
+            for group in 0..8u8 {
+                for other in 0..8u8 {
+                    let perm = Permissions {
+                        user: set.clone(),
+                        group: PermissionSet::new_from_byte(b'0' + group),
+                        other: PermissionSet::new_from_byte(b'0' + other)
+                    };
+                    test_encoder_decoder(perm);
+                }
+            }
+        }
+        assert_eq!( Permissions::decode(b"644 README.md")
+                  , nom::IResult::Done(&b" README.md"[..], Permissions::default_file())
+                  );
+        assert!(Permissions::decode(b"64").is_err());
+        assert!(PermissionSet::decode(b"8").is_err());
+    }
+
+    #[test]
+    fn permissions_from_octal_str() {
+        assert_eq!(Permissions::from_octal_str("644"), Some(Permissions::default_file()));
+        assert_eq!(Permissions::from_octal_str("0644"), Some(Permissions::default_file()));
+        assert_eq!(Permissions::from_octal_str("755"), Some(Permissions::default_exe()));
+        let private = Permissions::from_octal_str("600").unwrap();
+        assert!(private.user.contains(&Permission::Read));
+        assert!(private.user.contains(&Permission::Write));
+        assert!(! private.user.contains(&Permission::Executable));
+        assert_eq!(private.group, PermissionSet::new());
+        assert_eq!(private.other, PermissionSet::new());
+        assert_eq!(format!("{}", private), "0600");
+        assert_eq!(Permissions::from_octal_str("8"), None);
+        assert_eq!(Permissions::from_octal_str("648"), None);
+        assert_eq!(Permissions::from_octal_str("00644"), None);
+        assert_eq!(Permissions::from_octal_str(""), None);
+    }
+
     // tree as encoded by the original git (+ encoded in base64)
     const SMOCK_TEST : &'static str =
         "dHJlZSAyNjMAMTAwNjQ0IC5naXRpZ25vcmUAqdN8VgxquNSvv0ftpkPoxC6FdxYxMDA3N\
@@ -482,6 +803,101 @@ mod test {
         let data = SMOCK_TEST.from_base64().unwrap();
         test_decode_encode::<Tree<SHA1>>(data);
     }
+    // tree with a file, an executable and a sub-tree, as encoded by git:
+    //
+    // 100644 blob ce013625030ba8dba906f756967f9e9ca394464a	README.md
+    // 100755 blob 1a2485251c33a70432394c93fb89330ef214bfc9	build.sh
+    // 040000 tree 5d90422423db5ef6b431e8b9e60e0baf04b8742a	src
+    const MODES_TEST : &'static str =
+        "dHJlZSAxMDMAMTAwNjQ0IFJFQURNRS5tZADOATYlAwuo26kG91aWf56co5RGSjEwMDc1NS\
+         BidWlsZC5zaAAaJIUlHDOnBDI5TJP7iTMO8hS/yTQwMDAwIHNyYwBdkEIkI9te9rQx6Lnm\
+         DguvBLh0Kg==";
+
+    #[test]
+    fn regression_test_modes() {
+        let data = MODES_TEST.from_base64().unwrap();
+        test_decode_encode::<Tree<SHA1>>(data.clone());
+        let tree : Tree<SHA1> = Tree::decode(&data).unwrap().1;
+        let modes : Vec<String> = tree.iter().map(|te| format!("{}", te)[..6].to_string()).collect();
+        assert_eq!(modes, vec!["100644", "100755", "040000"]);
+    }
+    // same as MODES_TEST with a symbolic link and a submodule:
+    //
+    // 120000 blob 42061c01a1c70097d1e4579f29a5adf40abdec95	link
+    // 160000 commit 5d90422423db5ef6b431e8b9e60e0baf04b8742a	module
+    const LINKS_TEST : &'static str =
+        "dHJlZSAxNjkAMTAwNjQ0IFJFQURNRS5tZADOATYlAwuo26kG91aWf56co5RGSjEwMDc1NS\
+         BidWlsZC5zaAAaJIUlHDOnBDI5TJP7iTMO8hS/yTEyMDAwMCBsaW5rAEIGHAGhxwCX0eRX\
+         nymlrfQKveyVMTYwMDAwIG1vZHVsZQBdkEIkI9te9rQx6LnmDguvBLh0KjQwMDAwIHNyYw\
+         BdkEIkI9te9rQx6LnmDguvBLh0Kg==";
+
+    #[test]
+    fn regression_test_links() {
+        let data = LINKS_TEST.from_base64().unwrap();
+        test_decode_encode::<Tree<SHA1>>(data.clone());
+        let tree : Tree<SHA1> = Tree::decode(&data).unwrap().1;
+        match tree.get(PathBuf::from("link")) {
+            Some(&TreeEnt::SymbolicLink(_, _, ref r)) =>
+                assert_eq!(r.to_hexadecimal(), "42061c01a1c70097d1e4579f29a5adf40abdec95"),
+            te => panic!("expected a symbolic link: {:?}", te)
+        }
+        match tree.get(PathBuf::from("module")) {
+            Some(&TreeEnt::GitLink(_, _, ref r)) =>
+                assert_eq!(r.to_hexadecimal(), "5d90422423db5ef6b431e8b9e60e0baf04b8742a"),
+            te => panic!("expected a gitlink: {:?}", te)
+        }
+    }
+    #[test]
+    fn tree_serialisable_links() {
+        let mut tree : Tree<SHA1> = Tree::new();
+        let data = b"README.md";
+        tree.insert(TreeEnt::SymbolicLink(
+            Permissions::new(),
+            PathBuf::new().join("link"),
+            BlobRef::new(SHA1::hash(&mut &data[..]).unwrap())
+        ));
+        tree.insert(TreeEnt::GitLink(
+            Permissions::new(),
+            PathBuf::new().join("module"),
+            CommitRef::new(SHA1::hash(&mut &data[..]).unwrap())
+        ));
+        let mut v = Vec::new();
+        tree.encode(&mut v).unwrap();
+        test_decode_encode::<Tree<SHA1>>(v);
+    }
+    #[test]
+    fn tree_ent_invalid_mode() {
+        let mut data = b"100664 README.md\0".to_vec();
+        data.extend_from_slice(&[0u8; 20]);
+        assert!(TreeEnt::<SHA1>::decode(&data).is_done());
+        let mut data = b"104644 README.md\0".to_vec();
+        data.extend_from_slice(&[0u8; 20]);
+        assert!(TreeEnt::<SHA1>::decode(&data).is_err());
+        let mut data = b"20000 README.md\0".to_vec();
+        data.extend_from_slice(&[0u8; 20]);
+        assert!(TreeEnt::<SHA1>::decode(&data).is_err());
+    }
+    #[test]
+    fn tree_diff() {
+        let hash = |data: &[u8]| SHA1::hash(&mut &data[..]).unwrap();
+        let blob = |name: &str, data: &[u8]| {
+            TreeEnt::Blob(Permissions::default_file(), PathBuf::from(name), BlobRef::new(hash(data)))
+        };
+        let old : Tree<SHA1> = vec![ blob("README.md", b"# hello\n")
+                                   , blob("removed.txt", b"removed\n")
+                                   , blob("same.txt", b"same\n")
+                                   ].into_iter().collect();
+        let new : Tree<SHA1> = vec![ blob("README.md", b"# hello world\n")
+                                   , blob("added.txt", b"added\n")
+                                   , blob("same.txt", b"same\n")
+                                   ].into_iter().collect();
+        assert_eq!(old.diff(&new), vec![
+            TreeDiff::Modified(PathBuf::from("README.md"), hash(b"# hello\n"), hash(b"# hello world\n")),
+            TreeDiff::Added(blob("added.txt", b"added\n")),
+            TreeDiff::Removed(blob("removed.txt", b"removed\n"))
+        ]);
+        assert!(old.diff(&old).is_empty());
+    }
     #[test]
     fn tree_serialisable_empty() {
         let tree : Tree<SHA1> = Tree::new();
@@ -517,4 +933,63 @@ mod test {
         tree.insert(tree_ent_tree);
         test_encoder_decoder(tree);
     }
+    #[test]
+    fn tree_builder() {
+        let main = BlobRef::new(SHA1::from_hex("ce013625030ba8dba906f756967f9e9ca394464a").unwrap());
+        let readme = BlobRef::new(SHA1::from_hex("e845566c06f9bf557d35e8292c37cf05d97a9769").unwrap());
+        let mut builder = TreeBuilder::<SHA1>::new();
+        builder.insert("src/main.rs", main.clone(), Permissions::default_file()).unwrap();
+        builder.insert("README.md", readme.clone(), Permissions::default_file()).unwrap();
+        assert!(builder.insert("src", readme.clone(), Permissions::default_file()).is_err());
+        assert!(builder.insert("README.md/oops", main.clone(), Permissions::default_file()).is_err());
+        assert!(builder.insert("../outside", main.clone(), Permissions::default_file()).is_err());
+        let mut trees = builder.build().unwrap();
+        assert_eq!(trees.len(), 2);
+
+        let (root_ref, root) = trees.pop().unwrap();
+        let (src_ref, src) = trees.pop().unwrap();
+        assert_eq!(src.len(), 1);
+        match src.get(PathBuf::from("main.rs")) {
+            Some(&TreeEnt::Blob(_, _, ref br)) => assert_eq!(br, &main),
+            te => panic!("expected main.rs blob: {:?}", te)
+        }
+        match root.get(PathBuf::from("src")) {
+            Some(&TreeEnt::Tree(_, _, ref tr)) => assert_eq!(tr, &src_ref),
+            te => panic!("expected src tree: {:?}", te)
+        }
+        assert!(root.contains(PathBuf::from("README.md")));
+        assert_eq!(root_ref, ::object::object_id(&root).unwrap());
+        test_encoder_decoder(root);
+
+        // same root tree as git (see test_ref/init.sh)
+        let numbers = BlobRef::new(SHA1::from_hex("1e404f5c7c1752a1332020d2625c2ba2a20982ce").unwrap());
+        let mut builder = TreeBuilder::<SHA1>::new();
+        builder.insert("README.md", readme, Permissions::default_file()).unwrap();
+        builder.insert("numbers.txt", numbers, Permissions::default_file()).unwrap();
+        let (root_ref, _) = builder.build().unwrap().pop().unwrap();
+        assert_eq!(root_ref.to_hexadecimal(), "b95b9f18a548aa9610a306a5b7f6eca55a21c758");
+    }
+
+    #[test]
+    fn canonical_order() {
+        let empty = BlobRef::new(SHA1::empty_blob());
+        let lib_rs = TreeEnt::Blob(Permissions::default_file(), PathBuf::from("lib.rs"), empty.clone());
+        let lib_dir = TreeEnt::Tree(Permissions::default_dir(), PathBuf::from("lib"), TreeRef::new(SHA1::empty_tree()));
+        let lib_file = TreeEnt::Blob(Permissions::default_file(), PathBuf::from("lib"), empty.clone());
+        // git: `lib` < `lib.rs` < `lib/`
+        assert_eq!(lib_file.canonical_cmp(&lib_rs), cmp::Ordering::Less);
+        assert_eq!(lib_rs.canonical_cmp(&lib_dir), cmp::Ordering::Less);
+        assert_eq!(lib_file.canonical_cmp(&lib_dir), cmp::Ordering::Less);
+        assert_eq!(lib_dir.canonical_cmp(&lib_dir), cmp::Ordering::Equal);
+
+        let lib_a = TreeEnt::Blob(Permissions::default_file(), PathBuf::from("lib-a"), empty);
+        let tree : Tree<SHA1> = vec![lib_dir.clone(), lib_rs.clone(), lib_a.clone()].into_iter().collect();
+        let names : Vec<&TreeEnt<SHA1>> = tree.iter_canonical().collect();
+        assert_eq!(names, vec![&lib_a, &lib_rs, &lib_dir]);
+        // same hash as `git mktree`
+        assert_eq!( ::object::object_id::<SHA1, _>(&tree).unwrap().to_hexadecimal()
+                  , "3847acdaeed226aba398bdf621b0fff422c53416"
+                  );
+        test_encoder_decoder(tree);
+    }
 }
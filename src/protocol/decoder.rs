@@ -1,5 +1,23 @@
 use nom;
+use error::{Result, GitError};
 
 pub trait Decoder: Sized {
     fn decode(i: &[u8]) -> nom::IResult<&[u8], Self>;
+
+    /// decode the whole input: fails with `GitError::TrailingBytes` if
+    /// bytes are left after the decoded value
+    fn decode_all(i: &[u8]) -> Result<Self> { decoded_all(Self::decode(i)) }
+}
+
+/// the value of a parser which had to consume its whole input: fails with
+/// `GitError::TrailingBytes` if bytes are left after the decoded value
+pub fn decoded_all<T>(r: nom::IResult<&[u8], T>) -> Result<T> {
+    match r {
+        nom::IResult::Done(rest, v) => {
+            if rest.is_empty() { Ok(v) } else { Err(GitError::TrailingBytes(rest.len())) }
+        },
+        nom::IResult::Incomplete(nom::Needed::Unknown) => Err(GitError::ParsingErrorNotEnough(None)),
+        nom::IResult::Incomplete(nom::Needed::Size(s)) => Err(GitError::ParsingErrorNotEnough(Some(s))),
+        nom::IResult::Error(err) => Err(GitError::from_nom_error(err))
+    }
 }
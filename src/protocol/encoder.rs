@@ -7,4 +7,12 @@ pub trait Encoder {
     /// when encoding into git objects, the size is needed to be known prior
     /// encoding. It is because the size is encoded prior the object.
     fn required_size(&self) -> usize;
+
+    /// encode into a new `Vec`, allocated for `required_size` (and the
+    /// header of the git objects)
+    fn encode_to_vec(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.required_size() + 32);
+        try!(self.encode(&mut data));
+        Ok(data)
+    }
 }
@@ -5,11 +5,12 @@ use nom;
 extern crate crypto;
 use self::crypto::digest::Digest;
 use self::crypto::sha1::Sha1;
+use self::crypto::sha2::Sha256;
 extern crate rustc_serialize;
 use self::rustc_serialize::hex::{FromHex, ToHex};
 use std::io::{BufRead};
 use std::{str, io, fmt, marker};
-use error::{Result};
+use error::{Result, GitError};
 
 /// Hash Protocol
 ///
@@ -42,6 +43,18 @@ pub trait Hash : Sized {
             Self::from_bytes(b)
         } else { None }
     }
+    /// as `from_hex` but tells why the string is not a valid hash:
+    ///
+    /// * `GitError::NotHexadecimal` if it contains anything else than
+    ///   hexadecimal digits;
+    /// * `GitError::InvalidHexSize` (expected, given) otherwise, the sizes
+    ///   being in hexadecimal digits.
+    fn try_from_hex(s: &str) -> Result<Self> {
+        if ! s.chars().all(|c| c.is_digit(16)) {
+            return Err(GitError::NotHexadecimal(s.to_string()))
+        }
+        Self::from_hex(s).ok_or(GitError::InvalidHexSize(Self::digest_hex_size(), s.len()))
+    }
 
     /// the size of the digest
     #[inline]
@@ -68,19 +81,43 @@ pub trait Hash : Sized {
     fn encode_hex<W: io::Write>(&self, w: &mut W) -> io::Result<usize> { encode_hex_(self, w) }
 }
 
+/// tell if the two hashes are the same digest, whatever their types
+/// (`CommitRef<SHA1>`, `SHA1`, `PackRef<SHA1>`...)
+///
+/// ```
+/// use git::protocol::{SHA1, Hash, same_hash};
+/// use git::object::BlobRef;
+///
+/// let h = SHA1::empty_blob();
+/// assert!(same_hash(&BlobRef::new(SHA1::empty_blob()), &h));
+/// ```
+pub fn same_hash<A: Hash, B: Hash>(a: &A, b: &B) -> bool {
+    A::digest_size() == B::digest_size() && a.as_bytes() == b.as_bytes()
+}
+
 /// partial hash, used for lookup or when the type of Hash is not known
 ///
 /// a Partial Hash is a Hash, which means you can, technically, use it
+///
+/// `as_bytes` only returns the whole bytes of the prefix: the last digit of
+/// a prefix with an odd number of hexadecimal digits is not included.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub struct Partial<H: Hash> {
     hex: String,
+    bytes: Vec<u8>,
     phantom_: marker::PhantomData<H>
 }
 impl<H: Hash> Partial<H> {
+    /// `hex` must only contain hexadecimal digits
     fn new(hex: String) -> Self {
-        Partial { hex: hex, phantom_: marker::PhantomData }
+        let even = hex.len() - hex.len() % 2;
+        let bytes = hex[..even].from_hex().expect("Partial::new expects an hexadecimal string");
+        Partial { hex: hex, bytes: bytes, phantom_: marker::PhantomData }
     }
 
+    /// the length of the prefix in hexadecimal digits (nibbles)
+    pub fn len_nibbles(&self) -> usize { self.hex.len() }
+
     pub fn is_prefix_of<R: Hash>(&self, rhs: &R) -> bool {
         let hex = rhs.to_hexadecimal();
         let b = hex.starts_with(&self.hex);
@@ -94,28 +131,52 @@ impl<H: Hash> Hash for Partial<H> {
             Some(Self::new(b.to_hex()))
         } else { None }
     }
+    /// the prefix must only contain hexadecimal digits and must not be
+    /// longer than the hexadecimal representation of `H`
     #[inline]
     fn from_hex(s: &str) -> Option<Self> {
-        /// TODO check it is an hexadecimal
-        Some(Self::new(s.to_string()))
+        if s.len() <= Self::digest_hex_size() && s.chars().all(|c| c.is_digit(16)) {
+            Some(Self::new(s.to_lowercase()))
+        } else { None }
     }
     fn hash<R: BufRead>(_: &mut R) -> Result<Self> {
-        use ::error::GitError;
         Err(GitError::Other("cannot hash a partial hash".to_string()))
     }
     #[inline]
-    fn digest_size() -> usize { 20 }
+    fn digest_size() -> usize { H::digest_size() }
 
     #[inline]
     fn to_hexadecimal(&self) -> String { self.hex.clone() }
 
     #[inline]
-    fn as_bytes(&self) -> &[u8] { unimplemented!() }
+    fn as_bytes(&self) -> &[u8] { self.bytes.as_slice() }
 }
 impl<H: Hash> fmt::Display for Partial<H> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.to_hexadecimal()) }
 }
 
+/// feed the digest with all the data of the reader
+///
+/// The data is given to the digest straight from the reader's buffer
+/// (`fill_buf`), without being copied: a slice is hashed in one go and the
+/// size of the reads is the capacity of the `BufReader`.
+fn input_all<D: Digest, R: BufRead>(digest: &mut D, data: &mut R) -> Result<()> {
+    loop {
+        let n = {
+            let buf = try!(data.fill_buf());
+            digest.input(buf);
+            buf.len()
+        };
+        if n == 0 { return Ok(()) }
+        data.consume(n);
+    }
+}
+
+/// the hash of the object of the given kind with no content
+fn empty_object<H: Hash>(kind: &str) -> H {
+    H::hash(&mut format!("{} 0\0", kind).as_bytes()).expect("hashing a slice cannot fail")
+}
+
 /// Hash SHA1.
 ///
 /// See [rust-crypto](https://crates.io/crates/rust-crypto)
@@ -130,17 +191,10 @@ impl Hash for SHA1 {
     }
     fn hash<R: BufRead>(data: &mut R) -> Result<Self> {
         let mut st = Sha1::new();
-        let mut buf : &mut [u8;128] = &mut [0u8;128];
-        let mut res = [0;128];
-
-        loop {
-            let n = io_try!(data.read(buf));
-            if n == 0 { break; }
-            st.input(&buf[0..n]);
-        }
-
+        let mut res = [0;20];
+        try!(input_all(&mut st, data));
         st.result(&mut res);
-        Ok(SHA1(res[0..20].iter().cloned().collect()))
+        Ok(SHA1(res.iter().cloned().collect()))
     }
     #[inline]
     fn digest_size() -> usize { 20 }
@@ -151,10 +205,119 @@ impl Hash for SHA1 {
     #[inline]
     fn as_bytes(&self) -> &[u8] { self.0.as_slice() }
 }
+impl SHA1 {
+    /// the hash of the empty tree: the tree of a commit of an empty
+    /// directory
+    ///
+    /// ```
+    /// use git::protocol::{SHA1, Hash};
+    ///
+    /// assert_eq!(SHA1::empty_tree().to_hexadecimal(), "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    /// ```
+    pub fn empty_tree() -> Self { empty_object("tree") }
+    /// the hash of the empty blob (`e69de29bb2d1d6434b8b29ae775ad8c2e48c5391`)
+    pub fn empty_blob() -> Self { empty_object("blob") }
+}
 impl fmt::Display for SHA1 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.to_hexadecimal()) }
 }
 
+/// Hash SHA256.
+///
+/// Used by git's SHA256 object format. See
+/// [rust-crypto](https://crates.io/crates/rust-crypto)
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+pub struct SHA256(Vec<u8>);
+impl Hash for SHA256 {
+    #[inline]
+    fn from_bytes(b: Vec<u8>) -> Option<Self> {
+        if b.len() == Self::digest_size() {
+            Some(SHA256(b))
+        } else { None }
+    }
+    fn hash<R: BufRead>(data: &mut R) -> Result<Self> {
+        let mut st = Sha256::new();
+        let mut res = [0;32];
+        try!(input_all(&mut st, data));
+        st.result(&mut res);
+        Ok(SHA256(res.iter().cloned().collect()))
+    }
+    #[inline]
+    fn digest_size() -> usize { 32 }
+
+    #[inline]
+    fn to_hexadecimal(&self) -> String { self.0.as_slice().to_hex().to_string() }
+
+    #[inline]
+    fn as_bytes(&self) -> &[u8] { self.0.as_slice() }
+}
+impl SHA256 {
+    /// the hash of the empty tree: the tree of a commit of an empty
+    /// directory
+    pub fn empty_tree() -> Self { empty_object("tree") }
+    /// the hash of the empty blob
+    pub fn empty_blob() -> Self { empty_object("blob") }
+}
+impl fmt::Display for SHA256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{}", self.to_hexadecimal()) }
+}
+
+/// sink computing the hash of the bytes written into it, as they are
+/// written (the data is not kept)
+///
+/// # Example
+///
+/// ```
+/// use std::io::Write;
+/// use git::protocol::{SHA1, Hash, HashWriter};
+///
+/// let mut writer = HashWriter::<SHA1>::new();
+/// write!(writer, "hello {}", "world").unwrap();
+/// assert_eq!(writer.finish().to_hexadecimal(), "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
+/// ```
+pub struct HashWriter<H: Hash> {
+    digest: Box<Digest>,
+    phantom_: marker::PhantomData<H>
+}
+impl<H: Hash> HashWriter<H> {
+    fn with_digest(digest: Box<Digest>) -> Self {
+        HashWriter { digest: digest, phantom_: marker::PhantomData }
+    }
+
+    /// create a writer for any `H` (`CommitRef<SHA1>`...) when the type is
+    /// only known to be a `Hash`: the digest is chosen from the size of `H`
+    /// (SHA1 or SHA256).
+    ///
+    /// Returns `GitError::Other` if no digest has the size of `H`.
+    pub fn try_new() -> Result<Self> {
+        match H::digest_size() {
+            20 => Ok(Self::with_digest(Box::new(Sha1::new()))),
+            32 => Ok(Self::with_digest(Box::new(Sha256::new()))),
+            n  => Err(GitError::Other(format!("no digest of {} bytes", n)))
+        }
+    }
+
+    /// the hash of all the bytes written
+    pub fn finish(mut self) -> H {
+        let mut res = vec![0; self.digest.output_bytes()];
+        self.digest.result(&mut res);
+        H::from_bytes(res).expect("the digest has the size of the hash")
+    }
+}
+impl HashWriter<SHA1> {
+    pub fn new() -> Self { Self::with_digest(Box::new(Sha1::new())) }
+}
+impl HashWriter<SHA256> {
+    pub fn new() -> Self { Self::with_digest(Box::new(Sha256::new())) }
+}
+impl<H: Hash> io::Write for HashWriter<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.digest.input(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
 fn decode_bytes_<H: Hash>(i: &[u8]) -> nom::IResult<&[u8], H> {
     let size = H::digest_size();
     let input = &i[..size];
@@ -197,7 +360,7 @@ fn encode_hex_<H, W>(hash: &H, writer: &mut W) -> io::Result<usize>
   where H: Hash
       , W: io::Write
 {
-    writer.write_all(hash.to_hexadecimal().as_bytes()).map(|()| H::digest_size())
+    writer.write_all(hash.to_hexadecimal().as_bytes()).map(|()| H::digest_hex_size())
 }
 
 #[cfg(test)]
@@ -213,6 +376,33 @@ mod test {
     use ::protocol::test_encoder_decoder;
     use std::io;
 
+    #[test]
+    fn same_hash_across_types() {
+        use ::object::CommitRef;
+        let h = SHA1::from_hex("11f766d25092064380879e792eedac662dd2ec29").unwrap();
+        let commit = CommitRef::new(h.clone());
+        assert!(same_hash(&commit, &h));
+        assert!(same_hash(&h, &commit));
+        assert!(! same_hash(&commit, &SHA1::empty_tree()));
+        // same bytes, different digest sizes
+        let sha1 = SHA1::from_bytes(vec![0; 20]).unwrap();
+        let prefix = Partial::<SHA256>::from_bytes(vec![0; 20]).unwrap();
+        assert_eq!(sha1.as_bytes(), prefix.as_bytes());
+        assert!(! same_hash(&sha1, &prefix));
+    }
+
+    #[test]
+    fn hash_buffer_sizes() {
+        let data : Vec<u8> = (0..100000u32).map(|n| (n % 251) as u8).collect();
+        let sha1 = SHA1::hash(&mut data.as_slice()).unwrap();
+        let sha256 = SHA256::hash(&mut data.as_slice()).unwrap();
+        for &capacity in [1, 128, 4096, 65536].iter() {
+            let mut reader = io::BufReader::with_capacity(capacity, data.as_slice());
+            assert_eq!(SHA1::hash(&mut reader).unwrap(), sha1);
+            let mut reader = io::BufReader::with_capacity(capacity, data.as_slice());
+            assert_eq!(SHA256::hash(&mut reader).unwrap(), sha256);
+        }
+    }
     #[test]
     fn sha1_empty() {
         let data = String::new();
@@ -227,6 +417,48 @@ mod test {
         assert_eq!(hash.to_hexadecimal(), "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed");
     }
 
+    #[test]
+    fn hash_writer() {
+        use std::io::Write;
+        let data = b"hello world";
+        let mut writer = HashWriter::<SHA1>::new();
+        // in multiple writes
+        writer.write_all(&data[..4]).unwrap();
+        writer.write_all(&data[4..]).unwrap();
+        assert_eq!(writer.finish(), SHA1::hash(&mut &data[..]).unwrap());
+        assert_eq!(HashWriter::<SHA1>::new().finish(), SHA1::hash(&mut &b""[..]).unwrap());
+        let mut writer = HashWriter::<SHA256>::new();
+        writer.write_all(data).unwrap();
+        assert_eq!(writer.finish(), SHA256::hash(&mut &data[..]).unwrap());
+    }
+
+    #[test]
+    fn hash_writer_generic() {
+        use std::io::Write;
+        use ::object::CommitRef;
+        let data = b"hello world";
+        let mut writer = HashWriter::<CommitRef<SHA1>>::try_new().unwrap();
+        writer.write_all(data).unwrap();
+        assert_eq!(writer.finish(), CommitRef::new(SHA1::hash(&mut &data[..]).unwrap()));
+        let mut writer = HashWriter::<SHA256>::try_new().unwrap();
+        writer.write_all(data).unwrap();
+        assert_eq!(writer.finish(), SHA256::hash(&mut &data[..]).unwrap());
+    }
+
+    #[test]
+    fn sha256_empty() {
+        let data = String::new();
+        let hash = SHA256::hash(&mut data.as_bytes()).unwrap();
+        assert_eq!(hash.to_hexadecimal(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+
+    #[test]
+    fn sha256_basic() {
+        let data = "hello world";
+        let hash = SHA256::hash(&mut data.as_bytes()).unwrap();
+        assert_eq!(hash.to_hexadecimal(), "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+    }
+
     #[derive(PartialEq, Eq, Debug)]
     struct Bytes<H: Hash>(H);
     impl<H: Hash> Hash for Bytes<H> {
@@ -291,6 +523,21 @@ mod test {
         test_encoder_decoder(sha1);
     }
 
+    #[test]
+    fn sha256_hex_serialisable() {
+        let sha256_hex = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let sha256 = Hex::<SHA256>::from_hex(sha256_hex)
+                        .expect("expecting a valid SHA256 encoded in hexadecimal");
+        test_encoder_decoder(sha256);
+    }
+    #[test]
+    fn sha256_bytes_serialisable() {
+        let sha256_hex = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let sha256 = Bytes::<SHA256>::from_hex(sha256_hex)
+                        .expect("expecting a valid SHA256 encoded in bytes");
+        test_encoder_decoder(sha256);
+    }
+
     const DEFAULT_HASH : &'static str = r"2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
 
     #[test]
@@ -322,4 +569,46 @@ mod test {
                         .expect("expecting a Partial<SHA1> encoded in bytes");
         assert!(!prefix_sha1.is_prefix_of(&sha1));
     }
+
+    #[test]
+    fn partial_from_hex() {
+        let sha1 = SHA1::from_hex(DEFAULT_HASH).unwrap();
+        let prefix = Partial::<SHA1>::from_hex("2AAE6c3").expect("valid prefix");
+        assert_eq!(prefix.len_nibbles(), 7);
+        assert!(prefix.is_prefix_of(&sha1));
+        let full = Partial::<SHA1>::from_hex(DEFAULT_HASH).expect("a full hash is a valid prefix");
+        assert_eq!(full.len_nibbles(), SHA1::digest_hex_size());
+        assert!(full.is_prefix_of(&sha1));
+
+        assert_eq!(Partial::<SHA1>::from_hex(&format!("{}0", DEFAULT_HASH)), None);
+        assert_eq!(Partial::<SHA1>::from_hex("zzz"), None);
+        assert_eq!(Partial::<SHA1>::from_hex("2aae 6c"), None);
+    }
+
+    #[test]
+    fn sha1_try_from_hex() {
+        assert_eq!(SHA1::try_from_hex(DEFAULT_HASH), Ok(SHA1::from_hex(DEFAULT_HASH).unwrap()));
+        assert_eq!(SHA1::try_from_hex("2aae6c35"), Err(GitError::InvalidHexSize(40, 8)));
+        assert_eq!( SHA1::try_from_hex(&format!("{}00", DEFAULT_HASH))
+                  , Err(GitError::InvalidHexSize(40, 42))
+                  );
+        assert_eq!(SHA1::try_from_hex(&DEFAULT_HASH[1..]), Err(GitError::InvalidHexSize(40, 39)));
+        assert_eq!( SHA1::try_from_hex("2aae6c35c94fcfb415dbe95f408b9ce91ee846eg")
+                  , Err(GitError::NotHexadecimal("2aae6c35c94fcfb415dbe95f408b9ce91ee846eg".to_string()))
+                  );
+        assert!(Partial::<SHA1>::try_from_hex("2aae6c3").is_ok());
+    }
+
+    #[test]
+    fn partial_as_bytes() {
+        let even = Partial::<SHA1>::from_hex("2aae6c35").unwrap();
+        assert_eq!(even.as_bytes(), &[0x2a, 0xae, 0x6c, 0x35]);
+        let odd = Partial::<SHA1>::from_hex("2aae6c3").unwrap();
+        assert_eq!(odd.as_bytes(), &[0x2a, 0xae, 0x6c]);
+        assert_eq!(odd.to_hexadecimal(), "2aae6c3");
+        assert!(Partial::<SHA1>::from_hex("2").unwrap().as_bytes().is_empty());
+        let bytes = Partial::<SHA1>::from_bytes(vec![0x2a, 0xae]).unwrap();
+        assert_eq!(bytes.as_bytes(), &[0x2a, 0xae]);
+        assert_eq!(bytes, Partial::<SHA1>::from_hex("2aae").unwrap());
+    }
 }
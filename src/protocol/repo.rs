@@ -1,11 +1,23 @@
 use error::*;
-//use ::hash::SHA1;
-//use ::object::elements::hash::{HashRef, HasHashRef};
 use refs::{SpecRef, Ref};
-use object::{Obj, Object, Commit, CommitRef, Tree, TreeRef, BlobRef, Blob};
-use super::{Hash, Partial};
+use object::{Obj, Object, Commit, CommitRef, Tree, TreeRef, TreeEnt, BlobRef, Blob, Permissions, Tag, Date};
+use super::{Hash, Partial, CommitWalker, ObjectSource, decode_raw, same_hash};
+use std::collections::{HashMap, BinaryHeap};
+use std::cmp::Reverse;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf, Component};
+use std::io::Write;
+use std::str::FromStr;
+use either::Either;
 
-pub trait Repo {
+/// maximum number of symbolic references to follow (same as git)
+pub const MAX_SYMREF_DEPTH : usize = 5;
+/// maximum depth of nested trees to follow (see `Repo::list_tree_files`)
+pub const MAX_TREE_DEPTH : usize = 4096;
+
+/// a git repository: the references and the objects (read from the
+/// `ObjectSource`)
+pub trait Repo : ObjectSource {
     /// common function to validate the given Git Repository
     /// is valid. See GitFS.
     fn is_valid(&self) -> Result<()>;
@@ -22,30 +34,185 @@ pub trait Repo {
     /// HEAD -> master -> abcdef012345678..
     ///
     /// This function is a combination of get_ref and patten match on the Ref
-    /// enumeration.
+    /// enumeration. At most `MAX_SYMREF_DEPTH` links are followed, so a
+    /// cycle of references returns `GitError::RefCycle`.
     fn get_ref_follow_links<H>(&self, r: SpecRef)
         -> Result<H>
         where H: Hash
     {
-        match try!(self.get_ref(r)) {
-            Ref::Link(r) => self.get_ref_follow_links(r),
-            Ref::Hash(h) => Ok(h)
+        let mut current = r.clone();
+        for _ in 0..MAX_SYMREF_DEPTH + 1 {
+            match try!(self.get_ref(current)) {
+                Ref::Link(link) => current = link,
+                Ref::Hash(h) => return Ok(h)
+            }
         }
+        Err(GitError::RefCycle(r))
     }
+    /// check the object is in the repository, without reading it
+    fn exists<H: Hash>(&self, h: &H) -> Result<bool>;
     fn lookup_hash<H: Hash>(&self, prefix: &Partial<H>) -> Result<Vec<H>>;
 
+    /// list all the files of the given tree, recursively: the paths are
+    /// relative to the root tree (`src/main.rs`) and sorted.
+    ///
+    /// The symbolic links are listed (their blob is the path they point to)
+    /// but not the submodules. Fails if the trees are nested more than
+    /// `MAX_TREE_DEPTH` times.
+    fn list_tree_files<H: Hash>(&self, root: TreeRef<H>)
+        -> Result<Vec<(PathBuf, BlobRef<H>, Permissions)>>
+    {
+        let mut files = Vec::new();
+        let mut trees = vec![(PathBuf::new(), root, 0)];
+        while let Some((dir, tr, depth)) = trees.pop() {
+            if depth > MAX_TREE_DEPTH {
+                return Err(GitError::Other(format!("tree too deep: {}", dir.display())))
+            }
+            for te in try!(self.get_tree(tr)) {
+                match te {
+                    TreeEnt::Tree(_, path, tr) => trees.push((dir.join(path), tr, depth + 1)),
+                    TreeEnt::Blob(perm, path, br) => files.push((dir.join(path), br, perm)),
+                    TreeEnt::SymbolicLink(perm, path, br) => files.push((dir.join(path), br, perm)),
+                    TreeEnt::GitLink(_, _, _) => {}
+                }
+            }
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+
+    /// get the entry at the given path (`src/main.rs`) of the given tree,
+    /// reading the sub-trees as the path is followed
+    ///
+    /// Returns `None` if a component of the path is missing (or is not a
+    /// tree while it is followed). The path must be relative and without
+    /// `..` components.
+    fn tree_entry_at<H: Hash>(&self, root: TreeRef<H>, path: &Path)
+        -> Result<Option<TreeEnt<H>>>
+    {
+        let mut names = Vec::new();
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => names.push(PathBuf::from(name)),
+                Component::CurDir => {},
+                _ => return Err(GitError::Other(format!("invalid tree path: {}", path.display())))
+            }
+        }
+        let last = match names.pop() {
+            Some(last) => last,
+            None => return Ok(None)
+        };
+        let mut tr = root;
+        for name in names {
+            tr = match try!(self.get_tree(tr)).take(&name) {
+                Some(TreeEnt::Tree(_, _, sub)) => sub,
+                _ => return Ok(None)
+            };
+        }
+        Ok(try!(self.get_tree(tr)).take(&last))
+    }
+
+    /// resolve an abbreviated hash (as in `git cat-file -p e84556`) to the
+    /// hash of the only object starting with it.
+    ///
+    /// Returns `GitError::AmbiguousRef` if multiple objects match and
+    /// `GitError::InvalidRef` if none do.
+    fn resolve_prefix<H: Hash>(&self, prefix: &Partial<H>) -> Result<H> {
+        let mut found : Vec<H> = Vec::new();
+        for h in try!(self.lookup_hash(prefix)) {
+            // the same object may be loose and packed
+            if ! found.iter().any(|f| same_hash(f, &h)) {
+                found.push(h)
+            }
+        }
+        match found.len() {
+            0 => Err(GitError::InvalidRef(PathBuf::from(prefix.to_hexadecimal()))),
+            1 => Ok(found.pop().unwrap()),
+            _ => Err(GitError::AmbiguousRef(found.iter().map(|h| h.to_hexadecimal()).collect()))
+        }
+    }
+
+    /// resolve a revision (a subset of `git rev-parse`) to the hash of the
+    /// object it designates.
+    ///
+    /// The revision is tried, in order, as:
+    ///
+    /// * a full hash (which is not checked to exist);
+    /// * an abbreviated hash (see `resolve_prefix`);
+    /// * a reference, as parsed by `SpecRef::from_str` (`HEAD`,
+    ///   `refs/heads/master`...);
+    /// * a tag name (`refs/tags/<name>`) then a branch name
+    ///   (`refs/heads/<name>`).
+    ///
+    /// A name which is both a valid abbreviation and a reference (a branch
+    /// named `cafe`...) resolves to the object; an ambiguous abbreviation
+    /// falls back to the references and its error is returned if none
+    /// matches.
+    fn resolve<H: Hash>(&self, rev: &str) -> Result<H> {
+        let rev = rev.trim();
+        if let Some(h) = H::from_hex(rev) {
+            return Ok(h)
+        }
+        let mut err = GitError::InvalidRef(PathBuf::from(rev));
+        if ! rev.is_empty() {
+            match Partial::<H>::from_hex(rev) {
+                Some(prefix) => match self.resolve_prefix(&prefix) {
+                    Ok(h) => return Ok(h),
+                    Err(GitError::InvalidRef(_)) => {},
+                    Err(e) => err = e
+                },
+                None => if rev.chars().all(|c| c.is_digit(16)) {
+                    // hexadecimal but too long to be an abbreviated hash
+                    if let Err(e) = H::try_from_hex(rev) { err = e }
+                }
+            }
+        }
+        let mut candidates = Vec::new();
+        if let Ok(r) = SpecRef::from_str(rev) {
+            candidates.push(r)
+        }
+        // do not look outside of the references' directories
+        if ! rev.is_empty() && Path::new(rev).components().all(|c| match c { Component::Normal(_) => true, _ => false }) {
+            candidates.push(SpecRef::tag(rev));
+            candidates.push(SpecRef::branch(rev));
+        }
+        for candidate in candidates {
+            match self.get_ref_follow_links(candidate) {
+                Ok(h) => return Ok(h),
+                Err(e) => if ! is_missing_ref(&e) { return Err(e) }
+            }
+        }
+        Err(err)
+    }
+
     /// get object from a given hash ref
     fn get_object<H, O>(&self, r: O::Id) -> Result<O>
         where H: Hash
             , O: Object<H>
-            , O::Id: Hash;
-    fn get_object_<H>(&self, r: H) -> Result<Obj<H>> where H:Hash;
+            , O::Id: Hash
+    {
+        let (kind, content) = try!(self.read_raw(&r));
+        decode_raw::<H, O>(kind, &content)
+    }
+    /// get the object of the given hash, whatever its kind (see
+    /// `get_object` and the typed shortcuts `get_commit`, `get_tree` and
+    /// `get_blob` when the kind is known)
+    fn get_object_<H>(&self, r: H) -> Result<Obj<H>> where H:Hash {
+        let (kind, content) = try!(self.read_raw(&r));
+        decode_raw::<H, Obj<H>>(kind, &content)
+    }
+    /// read the given commit (shortcut for `get_object::<H, Commit<H>>`)
     fn get_commit<H: Hash>(&self, h: CommitRef<H>) -> Result<Commit<H>> {
         self.get_object(h)
     }
+    /// read the given tree (shortcut for `get_object::<H, Tree<H>>`)
     fn get_tree<H: Hash>(&self, h: TreeRef<H>) -> Result<Tree<H>> {
         self.get_object(h)
     }
+    /// read the given blob (shortcut for `get_object::<H, Blob>`)
+    ///
+    /// The whole content is loaded in memory, see `GitFS::open_blob` to
+    /// stream it instead.
     fn get_blob<H: Hash>(&self, h: BlobRef<H>) -> Result<Blob> {
         self.get_object(h)
     }
@@ -62,7 +229,179 @@ pub trait Repo {
             Ref::Link(sr) => CommitRef::new(try!(self.get_ref_follow_links(sr))),
             Ref::Hash(hr) => hr
         };
-        self.get_object(hr)
+        let hex = hr.to_hexadecimal();
+        self.get_object(hr).map_err(|e| e.context(format!("reading commit {}", hex)))
+    }
+
+    /// read the tree of the given commit
+    ///
+    /// The errors tell which commit (and tree) was read, see
+    /// `GitError::Context`.
+    fn get_commit_tree<H: Hash>(&self, c: CommitRef<H>) -> Result<Tree<H>> {
+        let hex = c.to_hexadecimal();
+        let commit = try!(self.get_commit(c).map_err(|e| e.context(format!("reading commit {}", hex))));
+        let tree = commit.tree().to_hexadecimal();
+        self.get_tree(commit.tree_ref)
+            .map_err(|e| e.context(format!("reading tree {} of commit {}", tree, hex)))
+    }
+
+    /// read the note attached to the given object (`git notes show`)
+    ///
+    /// The notes reference (`SpecRef::notes("commits")`) points to a commit
+    /// whose tree holds the notes, named after the hash of the object they
+    /// are attached to. The name may be split in nested directories of two
+    /// hexadecimal digits (`e8/45566c...`) when there are many notes: each
+    /// level is looked up. Returns `None` if the object has no note.
+    fn get_note<H: Hash>(&self, notes_ref: SpecRef, target: &H) -> Result<Option<Blob>> {
+        let notes : H = try!(self.get_ref_follow_links(notes_ref));
+        let mut tree = try!(self.get_commit_tree(CommitRef::new(notes)));
+        let hex = target.to_hexadecimal();
+        let mut rest = &hex[..];
+        loop {
+            if let Some(TreeEnt::Blob(_, _, blob)) = tree.take(&PathBuf::from(rest)) {
+                return self.get_blob(blob).map(Some)
+            }
+            if rest.len() <= 2 { return Ok(None) }
+            tree = match tree.take(&PathBuf::from(&rest[..2])) {
+                Some(TreeEnt::Tree(_, _, sub)) => try!(self.get_tree(sub)),
+                _ => return Ok(None)
+            };
+            rest = &rest[2..];
+        }
+    }
+
+    /// read the object behind a tag reference
+    ///
+    /// An annotated tag points to a `Tag` object (returned on the left)
+    /// while a lightweight tag points straight to a commit (returned on the
+    /// right). Fails with `GitError::InvalidTag` if the reference points to
+    /// another kind of object.
+    fn get_tag<H>(&self, r: SpecRef) -> Result<Either<Tag<H>, Commit<H>>>
+        where H: Hash
+    {
+        let h : H = try!(self.get_ref_follow_links(r.clone()));
+        match try!(self.get_object_(h)) {
+            Obj::Tag(tag) => Ok(Either::Left(tag)),
+            Obj::Commit(commit) => Ok(Either::Right(commit)),
+            _ => Err(GitError::InvalidTag(PathBuf::from(r)))
+        }
+    }
+
+    /// count the commits reachable from `local` but not from `upstream`
+    /// (ahead) and the commits reachable from `upstream` but not from
+    /// `local` (behind).
+    ///
+    /// This is what `git status` reports against a tracking branch:
+    /// *Your branch is ahead of 'origin/master' by 2 commits.*
+    ///
+    /// Only the commits down to where the two histories meet are read (see
+    /// `Painter`), not the whole history.
+    fn ahead_behind<H>(&self, local: CommitRef<H>, upstream: CommitRef<H>)
+        -> Result<(usize, usize)>
+        where H: Hash
+    {
+        let mut painter = Painter::<Self, H>::new(self, LOCAL | UPSTREAM);
+        try!(painter.paint(local.to_hexadecimal(), LOCAL));
+        try!(painter.paint(upstream.to_hexadecimal(), UPSTREAM));
+        try!(painter.run());
+        Ok(( painter.count(LOCAL), painter.count(UPSTREAM) ))
+    }
+
+    /// find a best common ancestor of the two commits (`git merge-base a b`)
+    ///
+    /// The best common ancestors are the commits reachable from both `a`
+    /// and `b` which are not the ancestor of another such commit: `a` (or
+    /// `b`) itself if it is an ancestor of the other one, `None` if the
+    /// histories are unrelated. With criss-cross merges, only one of the
+    /// merge bases is returned (the most recent one).
+    ///
+    /// Only the commits down to the merge bases are read (see `Painter`),
+    /// not the whole histories.
+    fn merge_base<H>(&self, a: CommitRef<H>, b: CommitRef<H>)
+        -> Result<Option<CommitRef<H>>>
+        where H: Hash
+    {
+        let mut painter = Painter::<Self, H>::new(self, LOCAL | UPSTREAM | STALE);
+        try!(painter.paint(a.to_hexadecimal(), LOCAL));
+        try!(painter.paint(b.to_hexadecimal(), UPSTREAM));
+        try!(painter.run());
+        Ok(painter.merge_bases().first().and_then(|hex| CommitRef::from_hex(hex)))
+    }
+
+    /// find the commit which last modified the file at the given path
+    /// (`git log -1 --first-parent -- <path>`)
+    ///
+    /// The first parents are followed from `start` as long as the entry at
+    /// the path has the same hash: the commit returned is the one the entry
+    /// differs (or is missing) in its first parent. Returns `None` if the
+    /// path does not exist in `start`.
+    fn last_commit_for_path<H: Hash>(&self, start: CommitRef<H>, path: &Path)
+        -> Result<Option<CommitRef<H>>>
+    {
+        let mut hex = start.to_hexadecimal();
+        let commit = try!(self.get_commit(start));
+        let id = match try!(self.tree_entry_at(commit.tree_ref, path)) {
+            Some(te) => te.get_hash().to_hexadecimal(),
+            None => return Ok(None)
+        };
+        let mut parents = commit.parents;
+        while let Some(parent) = parents.into_iter().next() {
+            let parent_hex = parent.to_hexadecimal();
+            let parent_commit = try!(self.get_commit(parent));
+            let same = match try!(self.tree_entry_at(parent_commit.tree_ref, path)) {
+                Some(te) => te.get_hash().to_hexadecimal() == id,
+                None => false
+            };
+            if ! same { break }
+            hex = parent_hex;
+            parents = parent_commit.parents;
+        }
+        Ok(H::from_hex(&hex).map(CommitRef::new))
+    }
+
+    /// iterate over the history starting at the given commit
+    ///
+    /// See `CommitWalker` for the order in which the commits are visited.
+    fn walk_commits<H: Hash>(&self, start: CommitRef<H>) -> CommitWalker<Self, H> {
+        CommitWalker::new(self, start)
+    }
+
+    /// write the history starting at the given commit in a text form which
+    /// can be compared with `diff` (close to `git log --format=raw`)
+    ///
+    /// Every commit (in the order of `walk_commits`) is written as a
+    /// `commit <hash>` line followed by its content (see `Commit`'s
+    /// `Display`) and an empty line.
+    fn export_commits<H: Hash, W: Write>(&self, start: CommitRef<H>, out: &mut W) -> Result<()> {
+        let mut walker = self.walk_commits(start);
+        while let Some(next) = walker.next_with_id() {
+            let (id, commit) = try!(next);
+            try!(write!(out, "commit {}\n{}\n", id.to_hexadecimal(), commit));
+        }
+        Ok(())
+    }
+
+    /// call `f` with every branch, tag and remote reference and the hash it
+    /// resolves to (following the symbolic references), as
+    /// `git for-each-ref` lists them
+    ///
+    /// A reference which cannot be resolved (a dangling symbolic reference,
+    /// a corrupted file...) is given to `f` with its error and the other
+    /// references are still listed: `f` decides whether to skip it or to
+    /// stop. Stops on the first error listing the references or returned
+    /// by `f`.
+    fn for_each_ref<H, F>(&self, mut f: F) -> Result<()>
+        where H: Hash
+            , F: FnMut(SpecRef, Result<H>) -> Result<()>
+    {
+        let mut refs = try!(self.list_branches());
+        refs.extend(try!(self.list_tags()));
+        refs.extend(try!(self.list_remotes()));
+        for r in refs {
+            let h = self.get_ref_follow_links(r.clone());
+            try!(f(r, h));
+        }
+        Ok(())
     }
 
     fn get_head<H: Hash>(&self) -> Result<Ref<H>> { self.get_ref(SpecRef::Head) }
@@ -70,3 +409,136 @@ pub trait Repo {
     fn list_remotes(&self) -> Result<Vec<SpecRef>>;
     fn list_tags(&self) -> Result<Vec<SpecRef>>;
 }
+
+/// tell if the error only means the reference does not exist (see
+/// `Repo::resolve`)
+fn is_missing_ref(err: &GitError) -> bool {
+    match err.root_cause() {
+        &GitError::InvalidRef(_) => true,
+        _ => err.io_kind() == Some(::std::io::ErrorKind::NotFound)
+    }
+}
+
+/// the commit is reachable from the local branch (see `Painter`)
+const LOCAL : u8 = 1;
+/// the commit is reachable from the upstream branch (see `Painter`)
+const UPSTREAM : u8 = 2;
+/// the commit is an ancestor of a commit reachable from both sides (see
+/// `Painter::merge_bases`)
+const STALE : u8 = 4;
+
+/// walk of the histories of two commits at once, marking ("painting") every
+/// commit with the sides (`LOCAL`, `UPSTREAM`) it is reachable from
+///
+/// The commits are visited by committer date, the most recent first (as
+/// `CommitWalker` does), and the walk stops once every commit left to visit
+/// has the marks given to `new`: reachable from both sides, their
+/// ancestors are common too, and `STALE` to find the merge bases. A commit
+/// whose sides change after it was visited is visited again, so its
+/// ancestors are painted with both sides. As with git, a commit older than
+/// one of its parents (clock skew) may stop the walk too early.
+struct Painter<'a, R: Repo + ?Sized + 'a, H: Hash> {
+    repo: &'a R,
+    /// the sides of the commits met so far
+    sides: HashMap<String, u8>,
+    /// the sides the parents of the commit were painted with
+    painted: HashMap<String, u8>,
+    /// the parents of the commits read so far
+    parents: HashMap<String, Vec<String>>,
+    dates: HashMap<String, Date>,
+    queue: BinaryHeap<(Date, Reverse<usize>, String)>,
+    /// the marks a commit needs for the walk to stop there
+    done: u8,
+    /// the number of entries of the queue for each commit
+    queued: HashMap<String, usize>,
+    /// the number of entries of the queue whose commit does not have the
+    /// `done` marks yet: the walk stops when there are none left
+    pending: usize,
+    /// the commits found reachable from both sides but not `STALE`
+    bases: Vec<String>,
+    seq: usize,
+    phantom_: PhantomData<H>
+}
+impl<'a, R: Repo + ?Sized + 'a, H: Hash> Painter<'a, R, H> {
+    fn new(repo: &'a R, done: u8) -> Self {
+        Painter {
+            repo: repo,
+            sides: HashMap::new(),
+            painted: HashMap::new(),
+            parents: HashMap::new(),
+            dates: HashMap::new(),
+            queue: BinaryHeap::new(),
+            done: done,
+            queued: HashMap::new(),
+            pending: 0,
+            bases: Vec::new(),
+            seq: 0,
+            phantom_: PhantomData
+        }
+    }
+
+    /// add the given sides to the commit, queuing it if they changed
+    fn paint(&mut self, hex: String, sides: u8) -> Result<()> {
+        let old = self.sides.get(&hex).cloned().unwrap_or(0);
+        if old | sides == old { return Ok(()) }
+        self.sides.insert(hex.clone(), old | sides);
+        let queued = self.queued.get(&hex).cloned().unwrap_or(0);
+        if (old | sides) & self.done == self.done {
+            // the entries already queued for this commit are done now
+            self.pending -= queued;
+        } else {
+            self.pending += 1;
+        }
+        self.queued.insert(hex.clone(), queued + 1);
+        if ! self.dates.contains_key(&hex) {
+            let cr = match CommitRef::<H>::from_hex(&hex) {
+                Some(cr) => cr,
+                None => return Err(GitError::InvalidRef(PathBuf::from(hex)))
+            };
+            let commit = try!(self.repo.get_commit(cr));
+            self.dates.insert(hex.clone(), commit.committer().date().clone());
+            self.parents.insert(hex.clone(), commit.parents.iter().map(|p| p.to_hexadecimal()).collect());
+        }
+        self.queue.push((self.dates[&hex], Reverse(self.seq), hex));
+        self.seq += 1;
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while self.pending > 0 {
+            let hex = match self.queue.pop() {
+                Some((_, _, hex)) => hex,
+                None => break
+            };
+            let sides = self.sides[&hex];
+            if let Some(queued) = self.queued.get_mut(&hex) { *queued -= 1 }
+            if sides & self.done != self.done { self.pending -= 1 }
+            if self.painted.get(&hex) == Some(&sides) { continue }
+            self.painted.insert(hex.clone(), sides);
+            let mut parents_sides = sides;
+            if self.done & STALE != 0 && sides == LOCAL | UPSTREAM {
+                // common, and not the ancestor of a common commit met so far
+                self.bases.push(hex.clone());
+                parents_sides |= STALE;
+            }
+            for parent in self.parents[&hex].clone() {
+                try!(self.paint(parent, parents_sides));
+            }
+        }
+        Ok(())
+    }
+
+    /// the number of commits reachable only from the given side
+    fn count(&self, side: u8) -> usize {
+        self.sides.values().filter(|s| **s == side).count()
+    }
+
+    /// the best common ancestors found by a walk with the `STALE` mark, the
+    /// most recent first: the common commits which are not the ancestor of
+    /// another common commit
+    fn merge_bases(&self) -> Vec<&String> {
+        // a base found too early (clock skew) may be marked `STALE` later
+        self.bases.iter().filter(|hex| self.sides[*hex] & STALE == 0).collect()
+    }
+}
+
@@ -0,0 +1,99 @@
+//! the storage of the objects
+
+use error::Result;
+use object::{ObjectKind, Object};
+use super::{Hash, decoded_all};
+
+/// where the objects of a repository are read from
+///
+/// The objects are given raw: their kind and their content, without the
+/// `<type> <size>\0` header. The `Repo` default methods (`get_object`,
+/// `get_commit`...) decode them, so any storage (the filesystem, memory,
+/// network...) can be used behind a `Repo`.
+pub trait ObjectSource {
+    /// read the kind and the content of the given object
+    fn read_raw<H: Hash>(&self, h: &H) -> Result<(ObjectKind, Vec<u8>)>;
+}
+
+/// decode the object from its kind and its content (see
+/// `ObjectSource::read_raw`): the content is decoded in place, no header
+/// is rebuilt in front of it
+pub fn decode_raw<H: Hash, O: Object<H>>(kind: ObjectKind, content: &[u8]) -> Result<O> {
+    decoded_all(O::decode_content(kind, content))
+}
+
+// -- --------------------------------------------------------------------- --
+// --                                 Tests                                 --
+// -- --------------------------------------------------------------------- --
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::GitError;
+    use object::{Commit, CommitRef, TreeRef, Tree, Blob, Person, Date, Obj};
+    use protocol::{Repo, Partial, Encoder, SHA1};
+    use refs::{SpecRef, Ref};
+    use std::collections::BTreeMap;
+
+    /// objects only stored in memory
+    struct MemSource(BTreeMap<Vec<u8>, (ObjectKind, Vec<u8>)>);
+    impl ObjectSource for MemSource {
+        fn read_raw<H: Hash>(&self, h: &H) -> Result<(ObjectKind, Vec<u8>)> {
+            self.0.get(h.as_bytes())
+                .map(|&(kind, ref content)| (kind, content.clone()))
+                .ok_or(GitError::Other(format!("missing object {}", h.to_hexadecimal())))
+        }
+    }
+    impl Repo for MemSource {
+        fn is_valid(&self) -> Result<()> { Ok(()) }
+        fn get_description(&self) -> Result<String> { Ok("in memory".to_string()) }
+        fn get_ref<H: Hash>(&self, r: SpecRef) -> Result<Ref<H>> {
+            Err(GitError::InvalidRef(r.into()))
+        }
+        fn exists<H: Hash>(&self, h: &H) -> Result<bool> { Ok(self.0.contains_key(h.as_bytes())) }
+        fn lookup_hash<H: Hash>(&self, _: &Partial<H>) -> Result<Vec<H>> { Ok(Vec::new()) }
+        fn list_branches(&self) -> Result<Vec<SpecRef>> { Ok(Vec::new()) }
+        fn list_remotes(&self) -> Result<Vec<SpecRef>> { Ok(Vec::new()) }
+        fn list_tags(&self) -> Result<Vec<SpecRef>> { Ok(Vec::new()) }
+    }
+
+    #[test]
+    fn repo_on_memory_source() {
+        let tree = TreeRef::new(SHA1::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904").unwrap());
+        let me = Person::new( "Test".to_string()
+                            , "git-test@example.com".to_string()
+                            , Date::seconds_since_epoch(1480007832)
+                            );
+        let commit : Commit<SHA1> = Commit::new(tree, me.clone(), me, "in memory\n");
+        let mut data = Vec::new();
+        commit.encode(&mut data).unwrap();
+        let h : SHA1 = SHA1::hash(&mut data.as_slice()).unwrap();
+        let mut objects = BTreeMap::new();
+        // the content, without the object header
+        let content = data.split_off(data.iter().position(|&b| b == 0).unwrap() + 1);
+        objects.insert(h.as_bytes().to_vec(), (ObjectKind::Commit, content));
+        let source = MemSource(objects);
+
+        assert_eq!(source.get_commit(CommitRef::new(h.clone())), Ok(commit.clone()));
+        match source.get_object_(h.clone()) {
+            Ok(Obj::Commit(c)) => assert_eq!(c, commit),
+            _ => panic!("expected a commit")
+        }
+        assert!(source.get_commit(CommitRef::new(SHA1::from_hex("da39a3ee5e6b4b0d3255bfef95601890afd80709").unwrap())).is_err());
+    }
+
+    #[test]
+    fn decode_raw_content() {
+        let blob : Blob = decode_raw::<SHA1, Blob>(ObjectKind::Blob, b"tree 0\0").unwrap();
+        assert_eq!(blob.as_slice(), b"tree 0\0");
+        match decode_raw::<SHA1, Obj<SHA1>>(ObjectKind::Tree, b"") {
+            Ok(Obj::Tree(tree)) => assert!(tree.is_empty()),
+            _ => panic!("expected a tree")
+        }
+        // the kind is checked, the content is not taken for a header
+        assert!(decode_raw::<SHA1, Commit<SHA1>>(ObjectKind::Blob, b"tree ").is_err());
+        assert_eq!( decode_raw::<SHA1, Tree<SHA1>>(ObjectKind::Tree, b"100644 a")
+                  , Err(GitError::TrailingBytes(8))
+                  );
+    }
+}
@@ -0,0 +1,88 @@
+//! round trip assertions for the `Encoder`/`Decoder` implementations
+//!
+//! This module is only available with the `testing` feature. It allows the
+//! crates providing their own `Hash` to check the objects (`Commit`,
+//! `Tree`...) are still encoded and decoded as expected with it.
+//!
+//! The functions panic (as `assert!` does) on failure.
+//!
+//! # Example
+//!
+//! ```
+//! use git::protocol::{Hash, SHA1};
+//! use git::protocol::testing::{test_encoder_decoder, test_decode_encode};
+//! use git::object::{Blob, Commit};
+//! use git::Result;
+//! use std::io::BufRead;
+//!
+//! /// a custom hash (here simply a wrapper around SHA1)
+//! #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+//! struct MyHash(SHA1);
+//! impl Hash for MyHash {
+//!     fn hash<R: BufRead>(data: &mut R) -> Result<Self> { SHA1::hash(data).map(MyHash) }
+//!     fn from_bytes(v: Vec<u8>) -> Option<Self> { SHA1::from_bytes(v).map(MyHash) }
+//!     fn digest_size() -> usize { SHA1::digest_size() }
+//!     fn as_bytes(&self) -> &[u8] { self.0.as_bytes() }
+//! }
+//!
+//! test_encoder_decoder(Blob::new(b"hello\n".to_vec()));
+//! let commit = b"commit 169\0tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+//!                author Test <git-test@example.com> 1480007832 +0100\n\
+//!                committer Test <git-test@example.com> 1480007832 +0100\n\
+//!                \ninitial commit\n";
+//! test_decode_encode::<Commit<MyHash>>(commit.to_vec());
+//! ```
+
+use std::fmt::{Debug, Display};
+use super::{Encoder, Decoder};
+
+/// encode the given value, decode it back and check the decoded value is
+/// the same as the original one (and that all the encoded data is consumed)
+pub fn test_encoder_decoder<T: Encoder+Decoder+Eq+Debug>(t: T) {
+  use nom::IResult::*;
+  let v = t.encode_to_vec().expect("encoding into buffer");
+  let (v_, t_) = match T::decode(&mut v.as_slice()) {
+      Done(i, o) => (i, o),
+      Error(err) => {
+          panic!( "decoding {:?} from buffer {:?}: {:?}"
+                , t, String::from_utf8_lossy(&v), err
+                )
+      },
+      Incomplete(needed) => {
+          panic!( "decoding {:?} from buffer {:?}: not enough data: needed({:?})"
+                , t, String::from_utf8_lossy(&v), needed
+                )
+      }
+  };
+  assert_eq!(t, t_);
+  assert!( v_.is_empty()
+         , "bytes left after decoding {:?}: {:?}", t, String::from_utf8_lossy(v_)
+         );
+}
+
+
+/// decode the given data, encode the decoded value back and check the
+/// encoded data is the same as the original one (byte for byte)
+pub fn test_decode_encode<T: Encoder+Decoder+Eq+Debug+Display>(data: Vec<u8>) {
+  use nom::IResult::*;
+  let (v_, t) = match T::decode(&mut data.as_slice()) {
+      Done(i, o) => (i, o),
+      Error(err) => {
+          panic!("decoding from buffer {:?}: {:?}", String::from_utf8_lossy(&data), err)
+      },
+      Incomplete(needed) => {
+          panic!( "decoding from buffer {:?}: not enough data: needed({:?})"
+                , String::from_utf8_lossy(&data), needed
+                )
+      }
+  };
+  // we expect to read all the buffer
+  assert!( v_.is_empty()
+         , "bytes left after decoding:\n{}\n{:?}", t, String::from_utf8_lossy(v_)
+         );
+  let v = t.encode_to_vec().expect("encoding into buffer");
+  assert!( data == v
+         , "encoded differently:\n{:?}\n{:?}"
+         , String::from_utf8_lossy(&data), String::from_utf8_lossy(&v)
+         );
+}
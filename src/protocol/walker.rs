@@ -0,0 +1,136 @@
+//! walk through the history of a repository
+
+use error::{Result, GitError};
+use object::{Commit, CommitRef, Date};
+use super::{Hash, Repo};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, BinaryHeap};
+
+/// a commit read but not yielded yet, ordered by committer date (the most
+/// recent first) then by the order the commits were met
+struct Pending<H: Hash> {
+    date: Date,
+    seq: usize,
+    id: CommitRef<H>,
+    commit: Commit<H>
+}
+impl<H: Hash> PartialEq for Pending<H> {
+    fn eq(&self, rhs: &Self) -> bool { self.cmp(rhs) == Ordering::Equal }
+}
+impl<H: Hash> Eq for Pending<H> {}
+impl<H: Hash> PartialOrd for Pending<H> {
+    fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> { Some(self.cmp(rhs)) }
+}
+impl<H: Hash> Ord for Pending<H> {
+    fn cmp(&self, rhs: &Self) -> Ordering {
+        // `BinaryHeap` pops the greatest: the most recent date, then the
+        // smallest sequence number
+        self.date.cmp(&rhs.date).then(rhs.seq.cmp(&self.seq))
+    }
+}
+
+/// iterator over the commits reachable from a given commit
+///
+/// The commits are yielded by committer date, the most recent first, as
+/// `git log` does: a commit is read when one of its children is yielded
+/// and the commits with the same date are yielded in the order they were
+/// met. So a commit comes after all its children unless their dates are
+/// wrong (a child older than its parent). Each commit is yielded only once,
+/// even if it is reachable through multiple paths (merge commits). With
+/// `first_parent` only the first parent of every commit is followed, as
+/// `git log --first-parent` does.
+///
+/// The walk stops on the root commits (the commits without parents). If a
+/// commit cannot be read the error is returned and the walk stops.
+///
+/// The iterator yields the commits only, `next_with_id` gives their hashes
+/// along.
+///
+/// See `Repo::walk_commits`.
+pub struct CommitWalker<'a, R: Repo + ?Sized + 'a, H: Hash> {
+    repo: &'a R,
+    start: Option<CommitRef<H>>,
+    queue: BinaryHeap<Pending<H>>,
+    seq: usize,
+    visited: BTreeSet<Vec<u8>>,
+    first_parent: bool,
+    /// error reading the parents of the last commit yielded, returned by the
+    /// next call
+    error: Option<GitError>,
+    failed: bool
+}
+impl<'a, R: Repo + ?Sized + 'a, H: Hash> CommitWalker<'a, R, H> {
+    /// start walking the history of the given repository from `start`
+    pub fn new(repo: &'a R, start: CommitRef<H>) -> Self {
+        CommitWalker {
+            repo: repo,
+            start: Some(start),
+            queue: BinaryHeap::new(),
+            seq: 0,
+            visited: BTreeSet::new(),
+            first_parent: false,
+            error: None,
+            failed: false
+        }
+    }
+
+    /// only follow the first parent of the commits
+    pub fn first_parent(mut self) -> Self {
+        self.first_parent = true;
+        self
+    }
+
+    /// the next commit and its hash: the hash it was read with, not one
+    /// computed again from the decoded commit
+    pub fn next_with_id(&mut self) -> Option<Result<(CommitRef<H>, Commit<H>)>> {
+        if self.failed { return None }
+        if let Some(err) = self.error.take() {
+            self.failed = true;
+            return Some(Err(err))
+        }
+        if let Some(start) = self.start.take() {
+            if let Err(err) = self.push(start) {
+                self.failed = true;
+                return Some(Err(err))
+            }
+        }
+        let pending = match self.queue.pop() {
+            Some(pending) => pending,
+            None => return None
+        };
+        if let Err(err) = self.push_parents(&pending.commit) {
+            self.error = Some(err);
+        }
+        Some(Ok((pending.id, pending.commit)))
+    }
+
+    /// read the commit (if not already met) and queue it
+    fn push(&mut self, cr: CommitRef<H>) -> Result<()> {
+        if ! self.visited.insert(cr.as_bytes().to_vec()) { return Ok(()) }
+        // `H` is not required to be `Clone`: read the commit with a copy
+        let commit = match CommitRef::from_bytes(cr.as_bytes().to_vec()) {
+            Some(copy) => try!(self.repo.get_commit(copy)),
+            None => return Err(GitError::Other(format!("invalid commit hash {}", cr.to_hexadecimal())))
+        };
+        let date = commit.committer().date().clone();
+        self.queue.push(Pending { date: date, seq: self.seq, id: cr, commit: commit });
+        self.seq += 1;
+        Ok(())
+    }
+    fn push_parents(&mut self, commit: &Commit<H>) -> Result<()> {
+        let count = if self.first_parent { 1 } else { commit.parent_ids().len() };
+        for p in commit.parent_ids().iter().take(count) {
+            // `H` is not required to be `Clone`: copy the parents' bytes
+            if let Some(p) = CommitRef::from_bytes(p.as_bytes().to_vec()) {
+                try!(self.push(p))
+            }
+        }
+        Ok(())
+    }
+}
+impl<'a, R: Repo + ?Sized + 'a, H: Hash> Iterator for CommitWalker<'a, R, H> {
+    type Item = Result<Commit<H>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_id().map(|r| r.map(|(_, commit)| commit))
+    }
+}
@@ -25,6 +25,9 @@ pub enum SpecRef {
     Branch(RefName),
     Remote(RefName, RefName),
     Patch(RefName),
+    /// the notes attached to the objects (`refs/notes/commits`), see
+    /// `Repo::get_note`
+    Notes(RefName),
     Stash,
     Head,
     OriginHead,
@@ -42,6 +45,7 @@ impl SpecRef {
                        )
     }
     pub fn patch<T: AsRef<Path>>(bn: T)  -> Self { SpecRef::Patch(PathBuf::new().join(bn)) }
+    pub fn notes<T: AsRef<Path>>(bn: T)  -> Self { SpecRef::Notes(PathBuf::new().join(bn)) }
     pub fn stash()                       -> Self { SpecRef::Stash }
     pub fn head()                        -> Self { SpecRef::Head }
     pub fn origin_head()                 -> Self { SpecRef::OriginHead }
@@ -51,6 +55,8 @@ impl SpecRef {
 impl FromStr for SpecRef {
     type Err = GitError;
     fn from_str(s: &str) -> Result<Self> {
+        // `@` alone is a shortcut for `HEAD`
+        if s.trim_right() == "@" { return Ok(SpecRef::head()) }
         let refnstr = PathBuf::from(s.trim_right());
         let mut components = refnstr.components();
         if let Some(Component::Normal(r)) = components.next() {
@@ -59,6 +65,7 @@ impl FromStr for SpecRef {
                     if t == "tags"    { return Ok(SpecRef::tag(components.as_path())) }
                     if t == "heads"   { return Ok(SpecRef::branch(components.as_path())) }
                     if t == "patches" { return Ok(SpecRef::patch(components.as_path())) }
+                    if t == "notes"   { return Ok(SpecRef::notes(components.as_path())) }
                     if t == "stash"   { return Ok(SpecRef::stash()) }
                     if t == "remotes" {
                         if let Some(Component::Normal(rem)) = components.next() {
@@ -81,6 +88,7 @@ impl fmt::Display for SpecRef {
             &SpecRef::Branch(ref b) => write!(f, "refs/heads/{}", b.display()),
             &SpecRef::Remote(ref r, ref b) => write!(f, "refs/remotes/{}/{}", r.display(), b.display()),
             &SpecRef::Patch(ref p)  => write!(f, "refs/patches/{}", p.display()),
+            &SpecRef::Notes(ref n)  => write!(f, "refs/notes/{}", n.display()),
             &SpecRef::Stash         => write!(f, "refs/stash"),
             &SpecRef::Head          => write!(f, "HEAD"),
             &SpecRef::OriginHead    => write!(f, "ORIG_HEAD"),
@@ -135,17 +143,40 @@ impl<H: Hash+fmt::Display> fmt::Display for Ref<H> {
         }
     }
 }
+/// state of the `HEAD` of a repository
+///
+/// * OnBranch: `HEAD` points to a branch (`ref: refs/heads/master`), the
+///   branch may not exist yet (a new repository);
+/// * Detached: `HEAD` points directly to a commit.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub enum HeadState<H: Hash> {
+    OnBranch(SpecRef),
+    Detached(H)
+}
+impl<H: Hash> HeadState<H> {
+    pub fn is_detached(&self) -> bool {
+        match self {
+            &HeadState::Detached(_) => true,
+            &HeadState::OnBranch(_) => false
+        }
+    }
+}
+
 impl<H: Hash> FromStr for Ref<H> {
     type Err = GitError;
+    /// the surrounding white spaces are ignored: the ref files end with a
+    /// new line (`\r\n` if written on Windows). As git does, any white
+    /// spaces may separate `ref:` from the reference.
     fn from_str(s: &str) -> Result<Self> {
-        if s.starts_with("ref: ") {
-            let sub :&str = &s[5..];
+        let s = s.trim();
+        if s.starts_with("ref:") {
+            let sub :&str = s[4..].trim_left();
             return Ok(Ref::Link(try!(SpecRef::from_str(sub))));
         }
         if let Some(h) = H::from_hex(s) {
             return Ok(Ref::Hash(h))
         }
-        panic!("not working")
+        Err(GitError::InvalidRef(PathBuf::from(s)))
     }
 }
 
@@ -155,24 +186,26 @@ mod tests {
     use protocol::SHA1;
     use std::str::FromStr;
 
-    fn get_specref() -> [SpecRef; 9] {
+    fn get_specref() -> [SpecRef; 10] {
         [ SpecRef::tag("v-1.1")
         , SpecRef::branch("master")
         , SpecRef::branch("dev/stage")
         , SpecRef::remote("origin", "master")
         , SpecRef::patch("patch-file")
+        , SpecRef::notes("commits")
         , SpecRef::stash()
         , SpecRef::head()
         , SpecRef::origin_head()
         , SpecRef::fetch_head()
         ]
     }
-    fn get_ref() -> [Ref<SHA1>; 9] {
+    fn get_ref() -> [Ref<SHA1>; 10] {
         [ Ref::Link(SpecRef::tag("v-1.1"))
         , Ref::Link(SpecRef::branch("master"))
         , Ref::Link(SpecRef::branch("dev/stage"))
         , Ref::Link(SpecRef::remote("origin", "master"))
         , Ref::Link(SpecRef::patch("patch-file"))
+        , Ref::Link(SpecRef::notes("commits"))
         , Ref::Link(SpecRef::stash())
         , Ref::Link(SpecRef::head())
         , Ref::Link(SpecRef::origin_head())
@@ -196,6 +229,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn decode_specref_head_alias() {
+        assert_eq!(SpecRef::from_str("@").unwrap(), SpecRef::head());
+        assert_eq!(SpecRef::from_str("@\n").unwrap(), SpecRef::head());
+        assert!(SpecRef::from_str("@/master").is_err());
+    }
+
+    #[test]
+    fn decode_ref_with_white_spaces() {
+        let hex = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
+        let hash = Ref::Hash(SHA1::from_hex(hex).unwrap());
+        assert_eq!( Ref::<SHA1>::from_str("ref: refs/heads/master\n").unwrap()
+                  , Ref::Link(SpecRef::branch("master"))
+                  );
+        assert_eq!( Ref::<SHA1>::from_str("ref: refs/heads/master\r\n").unwrap()
+                  , Ref::Link(SpecRef::branch("master"))
+                  );
+        assert_eq!(Ref::<SHA1>::from_str(&format!("{}\r\n", hex)).unwrap(), hash);
+        assert_eq!(Ref::<SHA1>::from_str(&format!("{}  \t", hex)).unwrap(), hash);
+        assert!(Ref::<SHA1>::from_str("not a ref\n").is_err());
+    }
+
+    #[test]
+    fn decode_symbolic_ref_separators() {
+        for s in ["ref: refs/heads/master\n", "ref:  refs/heads/master\n", "ref:\trefs/heads/master\n", "  ref:refs/heads/master"].iter() {
+            assert_eq!(Ref::<SHA1>::from_str(s), Ok(Ref::Link(SpecRef::branch("master"))), "{:?}", s);
+        }
+        assert!(Ref::<SHA1>::from_str("ref:").is_err());
+    }
+
     #[test]
     fn encode_decode_ref() {
         for sr in get_ref().iter() {
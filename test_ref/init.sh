@@ -9,5 +9,19 @@ echo "README" > README.md
 git add README.md
 git commit -m "initial commit"
 
+seq 1 1000 > numbers.txt
+git add numbers.txt
+git commit -m "add numbers"
+seq 1 1001 > numbers.txt
+git commit -am "add one more number"
+
 git remote add origin https://github.com/NicolasDP/git
 git fetch
+
+git tag v0.0.1
+git tag -a v0.1 -m "first release"
+
+# pack all the objects so the pack files are exercised too
+git repack -a -d
+# and the tags (the branches stay loose)
+git pack-refs